@@ -1,3 +1,4 @@
+use c8::instruction::{Instruction, decode};
 use criterion::{criterion_group, criterion_main, Criterion};
 use rand::Rng;
 use std::hint::black_box;
@@ -57,6 +58,13 @@ fn match_instruction_multi(opcode_list: Vec<u16>) -> Vec<usize> {
     result
 }
 
+fn decode_instructions(opcode_list: Vec<u16>) -> Vec<Instruction> {
+    opcode_list
+        .iter()
+        .map(|&opcode| decode(opcode, 0))
+        .collect()
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let mut rng = rand::rng();
     let length = 2048;
@@ -71,6 +79,10 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("multi match", |b| {
         b.iter(|| match_instruction_multi(black_box(test_vec.clone())))
     });
+
+    c.bench_function("decode", |b| {
+        b.iter(|| decode_instructions(black_box(test_vec.clone())))
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -30,6 +30,16 @@ impl AudioSettings {
         Self::default()
     }
 
+    /// Create audio settings which don't play any sound, for tests and tools without an audio
+    /// output. An audio recorder still records the sound.
+    #[must_use]
+    pub fn silent() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
     /// Is audio enabled
     #[must_use] 
     pub fn is_enabled(&self) -> bool {
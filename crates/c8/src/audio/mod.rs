@@ -4,12 +4,20 @@ use audio_settings::AudioSettings;
 /// Custom audio settings
 pub mod audio_settings;
 
+/// Audio recording to WAV files
+pub mod recorder;
+
+mod square_wave;
+
 #[cfg(feature = "audio")]
 mod tiny_audio;
 
 #[cfg(not(feature = "audio"))]
 mod null_audio;
 
+/// Sample rate of the synthesized audio
+pub(crate) const SAMPLE_RATE: usize = 44100;
+
 /// Trait for sound devices
 pub(crate) trait SoundDevice: std::fmt::Debug {
     fn play_beep(&mut self, audio_settings: AudioSettings);
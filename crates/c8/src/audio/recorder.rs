@@ -0,0 +1,234 @@
+use std::io::Write;
+
+use super::{
+    audio_settings::AudioSettings,
+    square_wave::{SquareWave, BEEP_BUFFER, BEEP_PITCH},
+    SAMPLE_RATE,
+};
+use crate::cpu::SoundEvent;
+
+/// Samples rendered for each 60 Hz frame
+const FRAME_SAMPLES: usize = SAMPLE_RATE / 60;
+
+/// Renders the sound of a device to 16-bit mono PCM, which can be saved as a WAV file
+///
+/// The beeps and XO-Chip audio patterns are synthesized the same way as the sound device plays
+/// them, one frame at a time, so the recording doesn't depend on an audio output or the speed
+/// of the emulation.
+pub struct AudioRecorder {
+    /// Synthesizer of the current pattern
+    square_wave: SquareWave,
+
+    /// Phase of the synthesizer
+    phase_bit: f32,
+
+    /// Whether a pattern was set, otherwise patterns playing since before the recording are set
+    has_pattern: bool,
+
+    /// Volume of the samples, from 0 to 1
+    volume: f32,
+
+    /// The rendered samples
+    samples: Vec<i16>,
+}
+
+impl std::fmt::Debug for AudioRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioRecorder")
+            .field("volume", &self.volume)
+            .field("samples", &self.samples.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for AudioRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioRecorder {
+    /// Create a recorder at the default volume
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            square_wave: SquareWave::new(),
+            phase_bit: 0.0,
+            has_pattern: false,
+            volume: AudioSettings::default().volume,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Get the sample rate of the recording
+    #[must_use]
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE as u32
+    }
+
+    /// Get the volume
+    #[must_use]
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Set the volume, from 0 to 1
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Get the rendered samples
+    #[must_use]
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Get the length of the recording in seconds
+    #[must_use]
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / SAMPLE_RATE as f32
+    }
+
+    /// Check if nothing was recorded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Remove the rendered samples
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Write the recording as a 16-bit mono WAV file
+    pub fn write_wav(&self, mut writer: impl Write) -> std::io::Result<()> {
+        const CHANNELS: u16 = 1;
+        const BITS_PER_SAMPLE: u16 = 16;
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        let byte_rate = self.sample_rate() * u32::from(block_align);
+        let data_size = u32::try_from(self.samples.len() * 2)
+            .map_err(|_| std::io::Error::other("the recording is too long for a WAV file"))?;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        // Format chunk, with format 1 for PCM
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&self.sample_rate().to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the recording as a 16-bit mono WAV file
+    #[must_use]
+    pub fn to_wav(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(44 + self.samples.len() * 2);
+
+        // Note: Writing to a vector can't fail, unless the recording is too long
+        let _ = self.write_wav(&mut data);
+        data
+    }
+
+    /// Render a frame of sound, after the timers were updated
+    pub(crate) fn record_frame(&mut self, event: SoundEvent, pattern: &[u8], pitch: u8) {
+        let playing = match event {
+            SoundEvent::Beep => {
+                self.square_wave.set_pattern(BEEP_PITCH, &BEEP_BUFFER);
+                self.has_pattern = false;
+                true
+            }
+            SoundEvent::PatternChanged => {
+                self.square_wave.set_pattern(pitch, pattern);
+                self.has_pattern = true;
+                true
+            }
+            SoundEvent::PatternPlaying => {
+                if !self.has_pattern {
+                    self.square_wave.set_pattern(pitch, pattern);
+                    self.has_pattern = true;
+                }
+                true
+            }
+            SoundEvent::Silence => false,
+        };
+
+        // Note: The phase keeps running while silent, like the sound device
+        let volume = if playing { self.volume } else { 0.0 };
+        for _ in 0..FRAME_SAMPLES {
+            let sample = self.square_wave.sample(&mut self.phase_bit, volume);
+            self.samples.push((sample * f32::from(i16::MAX)) as i16);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::device::C8;
+
+    use super::*;
+
+    #[test]
+    fn test_beep() {
+        // v0 := 3, buzzer := v0, loop
+        let rom = [0x60, 0x03, 0xF0, 0x18, 0x12, 0x04];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.set_audio_recorder(Some(AudioRecorder::new()));
+
+        for _ in 0..6 {
+            c8.step(10);
+        }
+
+        let recorder = c8.set_audio_recorder(None).unwrap();
+        assert_eq!(recorder.samples().len(), 6 * FRAME_SAMPLES);
+
+        // The timers are updated before the instructions run, so the buzzer sounds from the
+        // second frame for 3 frames
+        let frames: Vec<bool> = recorder
+            .samples()
+            .chunks(FRAME_SAMPLES)
+            .map(|frame| frame.iter().any(|&sample| sample != 0))
+            .collect();
+        assert_eq!(frames, [false, true, true, true, false, false]);
+
+        let peak = recorder.samples().iter().map(|sample| sample.abs()).max();
+        assert_eq!(peak, Some((0.15 * f32::from(i16::MAX)) as i16));
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut recorder = AudioRecorder::new();
+        recorder.set_volume(1.0);
+        recorder.record_frame(SoundEvent::Beep, &[], 0);
+        recorder.record_frame(SoundEvent::Silence, &[], 0);
+        assert_eq!(recorder.duration(), 2.0 / 60.0);
+
+        let wav = recorder.to_wav();
+        assert_eq!(wav.len(), 44 + 2 * FRAME_SAMPLES * 2);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[24..28], &44100u32.to_le_bytes());
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(&wav[40..44], &(2 * FRAME_SAMPLES as u32 * 2).to_le_bytes());
+
+        // The beep pattern starts high
+        assert_eq!(&wav[44..46], &i16::MAX.to_le_bytes());
+
+        recorder.clear();
+        assert!(recorder.is_empty());
+    }
+}
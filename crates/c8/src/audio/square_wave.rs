@@ -0,0 +1,65 @@
+use super::SAMPLE_RATE;
+
+// Default buffer used for beeps
+pub(crate) const BEEP_BUFFER: [u8; 16] = [
+    0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00,
+];
+
+// Pitch of beeps
+pub(crate) const BEEP_PITCH: u8 = 128;
+
+// Offset of the phase when reading the pattern
+const PHASE_OFFSET: f32 = 0.15;
+
+/// Synthesizes the audio pattern, shared by the sound devices and the audio recorder
+#[derive(Default)]
+pub(crate) struct SquareWave {
+    bit_pattern: Vec<u8>, // 128 1-bit samples
+    phase_inc: f32,       // (4000*2^((vx-64)/48)) / device_sample_rate
+}
+
+impl SquareWave {
+    pub fn new() -> Self {
+        Self {
+            bit_pattern: vec![0u8; 128],
+            phase_inc: Self::pitch_to_ratio(128),
+        }
+    }
+
+    pub fn pitch_to_ratio(pitch: u8) -> f32 {
+        let base = 2.0f32;
+        let sr = 4000.0 * base.powf((f32::from(pitch) - 64.0) / 48.0);
+        sr / SAMPLE_RATE as f32
+    }
+
+    pub fn set_pattern(&mut self, pitch: u8, pattern: &[u8]) {
+        // TODO: Apply filtering to the values
+        // Map the 16 byte pattern to 128 bits and clamp to 0-1
+        let length = 8; // = self.bit_pattern.len() / pattern.len();
+        self.bit_pattern = pattern
+            .iter()
+            //.flat_map(|&x| vec![x.clamp(0, 1); length])
+            .flat_map(|&x| vec![x; length])
+            .collect();
+
+        self.phase_inc = Self::pitch_to_ratio(pitch);
+    }
+
+    /// Get the next sample and advance the phase
+    ///
+    /// The phase is kept by the caller, so the pattern can be shared behind a read lock.
+    pub fn sample(&self, phase_bit: &mut f32, volume: f32) -> f32 {
+        let sample = if self.bit_pattern[(*phase_bit + PHASE_OFFSET) as usize] != 0 {
+            volume
+        } else {
+            -volume
+        };
+
+        *phase_bit += self.phase_inc;
+        if (*phase_bit + PHASE_OFFSET) as usize > self.bit_pattern.len() - 1 {
+            *phase_bit = 0.0;
+        }
+
+        sample
+    }
+}
@@ -4,54 +4,16 @@ use std::sync::{Arc, RwLock};
 use tinyaudio::prelude::*;
 
 use super::audio_settings::AudioSettings;
-use super::SoundDevice;
+use super::square_wave::{SquareWave, BEEP_BUFFER, BEEP_PITCH};
+use super::{SoundDevice, SAMPLE_RATE};
 
 // TODO: Check if RWLock has better results
 
-const SAMPLE_RATE: usize = 44100;
-
-// Default buffer used for beeps
-const BUFFER: [u8; 16] = [
-    0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00,
-];
-
-#[derive(Default)]
-struct SquareWave {
-    bit_pattern: Vec<u8>, // 128 1-bit samples
-    phase_inc: f32,       // (4000*2^((vx-64)/48)) / device_sample_rate
-}
-
-impl SquareWave {
-    pub fn new() -> Self {
-        Self {
-            bit_pattern: vec![0u8; 128],
-            phase_inc: Self::pitch_to_ratio(128),
-        }
-    }
-
-    pub fn pitch_to_ratio(pitch: u8) -> f32 {
-        let base = 2.0f32;
-        let sr = 4000.0 * base.powf((f32::from(pitch) - 64.0) / 48.0);
-        sr / SAMPLE_RATE as f32
-    }
-
-    pub fn set_pattern(&mut self, pitch: u8, pattern: Vec<u8>) {
-        // TODO: Apply filtering to the values
-        // Map the 16 byte pattern to 128 bits and clamp to 0-1
-        let length = 8; // = self.bit_pattern.len() / pattern.len();
-        self.bit_pattern = pattern
-            .iter()
-            //.flat_map(|&x| vec![x.clamp(0, 1); length])
-            .flat_map(|&x| vec![x; length])
-            .collect();
-
-        self.phase_inc = Self::pitch_to_ratio(pitch);
-    }
-}
-
 #[derive(Default)]
 pub struct TinyAudio {
     device: Option<OutputDevice>,
+    // Set when no output device could be opened, so it isn't tried again for every sound
+    unavailable: bool,
     playing: Arc<AtomicBool>,
     square_wave: Arc<RwLock<SquareWave>>,
     volume: Arc<RwLock<f32>>,
@@ -61,6 +23,7 @@ impl TinyAudio {
     pub fn new() -> Self {
         Self {
             device: None,
+            unavailable: false,
             playing: Arc::new(AtomicBool::new(false)),
             square_wave: Arc::new(RwLock::new(SquareWave::new())),
             volume: Arc::new(RwLock::new(0.15)),
@@ -68,7 +31,7 @@ impl TinyAudio {
     }
 
     fn init(&mut self) {
-        if self.device.is_some() {
+        if self.device.is_some() || self.unavailable {
             return;
         }
 
@@ -99,28 +62,21 @@ impl TinyAudio {
                     0.0
                 };
 
-                const PHASE_INC: f32 = 0.15;
                 for samples in data.chunks_mut(params.channels_count) {
                     for sample in samples {
-                        if let Ok(ref mut mutex) = square_wave.try_read() {
-                            *sample = if mutex.bit_pattern[(phase_bit + PHASE_INC) as usize] != 0 {
-                                volume
-                            } else {
-                                -volume
-                            };
-
-                            phase_bit += mutex.phase_inc;
-                            if (phase_bit + PHASE_INC) as usize > mutex.bit_pattern.len() - 1 {
-                                phase_bit = 0.0;
-                            }
+                        if let Ok(ref mutex) = square_wave.try_read() {
+                            *sample = mutex.sample(&mut phase_bit, volume);
                         }
                     }
                 }
             }
-        })
-        .unwrap();
+        });
 
-        self.device = Some(device);
+        // Note: Without an audio output the device runs silently
+        match device {
+            Ok(device) => self.device = Some(device),
+            Err(_) => self.unavailable = true,
+        }
     }
 }
 
@@ -138,7 +94,7 @@ impl SoundDevice for TinyAudio {
         }
 
         if let Ok(ref mut mutex) = self.square_wave.try_write() {
-            mutex.set_pattern(128, BUFFER.to_vec());
+            mutex.set_pattern(BEEP_PITCH, &BEEP_BUFFER);
             self.playing.store(true, Ordering::SeqCst);
         } else {
             eprintln!("play_beep: try_write failed");
@@ -152,7 +108,7 @@ impl SoundDevice for TinyAudio {
         }
 
         if let Ok(ref mut mutex) = self.square_wave.try_write() {
-            mutex.set_pattern(buffer_pitch, buffer.clone());
+            mutex.set_pattern(buffer_pitch, &buffer);
             self.playing.store(true, Ordering::SeqCst);
         } else {
             eprintln!("play_beep: try_write failed");
@@ -0,0 +1,373 @@
+use std::io::{Read, Write};
+
+use serde_json::Value;
+
+use crate::{
+    display::Display,
+    octo_options::{OctoOptions, OptionsError, OptionsWarning},
+    screenshot::{pixel_value, ScreenshotColors},
+};
+
+/// Width of a cartridge image
+pub const CARTRIDGE_WIDTH: usize = 160;
+
+/// Height of a cartridge image
+pub const CARTRIDGE_HEIGHT: usize = 128;
+
+/// Position and size of the label, the display is drawn on it at 128x64 pixels
+const LABEL_X: usize = 16;
+const LABEL_Y: usize = 16;
+const LABEL_WIDTH: usize = 128;
+const LABEL_HEIGHT: usize = 64;
+
+/// Colors of the cartridge around the label
+const SHELL_COLOR: [u8; 3] = [0x55, 0x55, 0x55];
+const SHADE_COLOR: [u8; 3] = [0x33, 0x33, 0x33];
+
+/// Label color indices of the shell, the display pixel values follow them
+const SHELL: u8 = 0;
+const SHADE: u8 = 1;
+const DISPLAY: u8 = 2;
+
+/// Payload bits stored in each pixel
+const PIXEL_BITS: usize = 2;
+
+/// Payload bytes stored in each frame
+const FRAME_BYTES: usize = CARTRIDGE_WIDTH * CARTRIDGE_HEIGHT * PIXEL_BITS / 8;
+
+/// Errors which can occur while reading a cartridge
+#[derive(Debug, Clone, PartialEq)]
+pub enum CartridgeError {
+    /// The image isn't a valid GIF
+    InvalidGif(String),
+
+    /// The image doesn't hold a payload, or the payload is cut short
+    InvalidPayload,
+
+    /// The payload isn't valid JSON
+    InvalidJson(String),
+
+    /// The payload doesn't hold a program
+    MissingProgram,
+
+    /// The options of the payload can't be read
+    InvalidOptions(OptionsError),
+}
+
+impl std::fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CartridgeError::InvalidGif(error) => write!(f, "Invalid GIF: {error}"),
+            CartridgeError::InvalidPayload => write!(f, "The image doesn't hold a cartridge"),
+            CartridgeError::InvalidJson(error) => write!(f, "Invalid cartridge data: {error}"),
+            CartridgeError::MissingProgram => write!(f, "The cartridge doesn't hold a program"),
+            CartridgeError::InvalidOptions(error) => {
+                write!(f, "Invalid cartridge options: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+/// An Octo cartridge, a GIF with a picture label which holds a program and its options
+///
+/// The payload is a 4 byte big endian length followed by a UTF-8 JSON object with the
+/// `options` and the Octo source `program`. It is stored 2 bits per pixel, most significant
+/// first, in the low bits of the palette indices, over as many frames as it needs. The higher
+/// bits pick the color of the label, each color is repeated in the palette for every value of
+/// the low bits so the payload doesn't show.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cartridge {
+    /// Octo source of the program
+    pub program: String,
+
+    /// Settings the program runs with
+    pub options: OctoOptions,
+}
+
+impl Cartridge {
+    /// Create a cartridge of a ROM, the program is Octo source which assembles to the same bytes
+    #[must_use]
+    pub fn from_rom(rom: &[u8], options: OctoOptions) -> Self {
+        let mut program = String::from("# Packed from a ROM\n: main\n");
+
+        for line in rom.chunks(16) {
+            let bytes: Vec<String> = line.iter().map(|byte| format!("0x{byte:02X}")).collect();
+            program.push_str(&format!("  {}\n", bytes.join(" ")));
+        }
+
+        Self { program, options }
+    }
+
+    /// Read a cartridge image
+    ///
+    /// Options without an equivalent setting are skipped and returned as warnings.
+    pub fn read_gif(reader: impl Read) -> Result<(Self, Vec<OptionsWarning>), CartridgeError> {
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(reader)
+            .map_err(|error| CartridgeError::InvalidGif(error.to_string()))?;
+
+        let mut payload = Vec::new();
+        let mut byte = 0;
+        let mut bits = 0;
+
+        while let Some(frame) = decoder
+            .read_next_frame()
+            .map_err(|error| CartridgeError::InvalidGif(error.to_string()))?
+        {
+            for index in frame.buffer.iter() {
+                byte = (byte << PIXEL_BITS) | (index & 0b11);
+                bits += PIXEL_BITS;
+
+                if bits == 8 {
+                    payload.push(byte);
+                    byte = 0;
+                    bits = 0;
+                }
+            }
+        }
+
+        let (length, data) = payload
+            .split_first_chunk::<4>()
+            .ok_or(CartridgeError::InvalidPayload)?;
+        let json = usize::try_from(u32::from_be_bytes(*length))
+            .ok()
+            .and_then(|length| data.get(..length))
+            .ok_or(CartridgeError::InvalidPayload)?;
+
+        let value: Value = serde_json::from_slice(json)
+            .map_err(|error| CartridgeError::InvalidJson(error.to_string()))?;
+        let program = value
+            .get("program")
+            .and_then(Value::as_str)
+            .ok_or(CartridgeError::MissingProgram)?
+            .to_string();
+        let (options, warnings) = match value.get("options") {
+            Some(options) => {
+                OctoOptions::from_value(options).map_err(CartridgeError::InvalidOptions)?
+            }
+            None => (OctoOptions::default(), Vec::new()),
+        };
+
+        Ok((Self { program, options }, warnings))
+    }
+
+    /// Write the cartridge as a GIF, with a label of the display
+    pub fn write_gif(
+        &self,
+        writer: impl Write,
+        display: &Display,
+        colors: &ScreenshotColors,
+    ) -> std::io::Result<()> {
+        let payload = self.payload();
+        let label = label(display);
+
+        // Each label color is repeated for the 4 values of the payload bits
+        let label_colors = [
+            SHELL_COLOR,
+            SHADE_COLOR,
+            rgb(colors.background),
+            rgb(colors.foreground1),
+            rgb(colors.foreground2),
+            rgb(colors.blended),
+        ];
+        let palette: Vec<u8> = label_colors
+            .iter()
+            .flat_map(|color| color.repeat(1 << PIXEL_BITS))
+            .collect();
+
+        // Note: The size of a cartridge fits in 16 bits
+        let mut encoder = gif::Encoder::new(
+            writer,
+            CARTRIDGE_WIDTH as u16,
+            CARTRIDGE_HEIGHT as u16,
+            &palette,
+        )
+        .map_err(std::io::Error::other)?;
+
+        for chunk in payload.chunks(FRAME_BYTES) {
+            let buffer: Vec<u8> = label
+                .iter()
+                .enumerate()
+                .map(|(pixel, &color)| {
+                    let byte = chunk.get(pixel * PIXEL_BITS / 8).copied().unwrap_or(0);
+                    let shift = 8 - PIXEL_BITS - (pixel * PIXEL_BITS % 8);
+                    (color << PIXEL_BITS) | ((byte >> shift) & 0b11)
+                })
+                .collect();
+
+            let frame = gif::Frame {
+                width: CARTRIDGE_WIDTH as u16,
+                height: CARTRIDGE_HEIGHT as u16,
+                buffer: buffer.into(),
+                ..gif::Frame::default()
+            };
+
+            encoder.write_frame(&frame).map_err(std::io::Error::other)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the cartridge as a GIF, with a label of the display
+    #[must_use]
+    pub fn to_gif(&self, display: &Display, colors: &ScreenshotColors) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        // Note: Writing to a vector can't fail
+        let _ = self.write_gif(&mut data, display, colors);
+        data
+    }
+
+    /// Get the length prefixed JSON payload
+    fn payload(&self) -> Vec<u8> {
+        let json = format!(
+            "{{\"options\":{},\"program\":{}}}",
+            self.options.to_json(),
+            Value::from(self.program.as_str())
+        );
+
+        let mut payload = Vec::with_capacity(json.len() + 4);
+        // Note: Programs are far shorter than 4 GiB
+        payload.extend_from_slice(&(json.len() as u32).to_be_bytes());
+        payload.extend_from_slice(json.as_bytes());
+        payload
+    }
+}
+
+/// Drop the alpha of a color
+fn rgb([r, g, b, _]: [u8; 4]) -> [u8; 3] {
+    [r, g, b]
+}
+
+/// Draw the label color index of every pixel of a cartridge
+fn label(display: &Display) -> Vec<u8> {
+    let (width, height) = display.resolution().resolution_size_xy();
+    let values: Vec<u8> = display
+        .zipped_iterator()
+        .map(|(&p0, &p1)| pixel_value(p0, p1))
+        .collect();
+    let scale_x = LABEL_WIDTH / width;
+    let scale_y = LABEL_HEIGHT / height;
+
+    let mut label = vec![SHELL; CARTRIDGE_WIDTH * CARTRIDGE_HEIGHT];
+    for (index, color) in label.iter_mut().enumerate() {
+        let (x, y) = (index % CARTRIDGE_WIDTH, index / CARTRIDGE_WIDTH);
+
+        let on_label = (LABEL_X..LABEL_X + LABEL_WIDTH).contains(&x)
+            && (LABEL_Y..LABEL_Y + LABEL_HEIGHT).contains(&y);
+        let on_border = (LABEL_X - 1..=LABEL_X + LABEL_WIDTH).contains(&x)
+            && (LABEL_Y - 1..=LABEL_Y + LABEL_HEIGHT).contains(&y);
+        // Grip lines below the label
+        let on_grip = (LABEL_X + 8..LABEL_X + LABEL_WIDTH - 8).contains(&x)
+            && y > LABEL_Y + LABEL_HEIGHT + 8
+            && y < CARTRIDGE_HEIGHT - 8
+            && y % 4 == 0;
+
+        if on_label {
+            let display_x = (x - LABEL_X) / scale_x;
+            let display_y = (y - LABEL_Y) / scale_y;
+            *color = DISPLAY + values[display_y * width + display_x];
+        } else if on_border || on_grip {
+            *color = SHADE;
+        }
+    }
+
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{device::C8, fonts::FontName};
+
+    use super::*;
+
+    /// Create a device which drew a 0 from the font at the top left
+    fn device() -> C8 {
+        // i := hex v0, sprite v0 v0 5
+        let rom = [0xF0, 0x29, 0xD0, 0x05];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.step(2);
+        c8
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let c8 = device();
+        let mut options = OctoOptions {
+            tickrate: Some(500),
+            font: Some(FontName::VIP),
+            ..OctoOptions::default()
+        };
+        options.quirks.clip_sprites = true;
+        options.colors.foreground1 = Some([0xFF, 0xCC, 0x00]);
+
+        // Long enough to take several frames
+        let rom: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        let cartridge = Cartridge::from_rom(&rom, options);
+        assert!(cartridge.payload().len() > 2 * FRAME_BYTES);
+
+        let data = cartridge.to_gif(c8.display(), &ScreenshotColors::default());
+        assert_eq!(&data[..6], b"GIF89a");
+
+        let (read, warnings) = Cartridge::read_gif(data.as_slice()).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(read, cartridge);
+        assert!(read
+            .program
+            .starts_with("# Packed from a ROM\n: main\n  0x00 0x01 0x02"));
+    }
+
+    #[test]
+    fn test_label() {
+        let c8 = device();
+        let colors = ScreenshotColors::default();
+        let cartridge = Cartridge::from_rom(&[0x12, 0x00], OctoOptions::default());
+        let data = cartridge.to_gif(c8.display(), &colors);
+
+        let mut decoder = gif::DecodeOptions::new();
+        decoder.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = decoder.read_info(data.as_slice()).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(
+            (usize::from(frame.width), usize::from(frame.height)),
+            (CARTRIDGE_WIDTH, CARTRIDGE_HEIGHT)
+        );
+
+        // The low resolution display is doubled, the top row of the 0 is 8 pixels wide and
+        // the payload doesn't change the colors
+        let pixel = |x: usize, y: usize| &frame.buffer[(y * CARTRIDGE_WIDTH + x) * 4..][..3];
+        let label_pixel = |x: usize, y: usize| pixel(LABEL_X + x, LABEL_Y + y);
+        assert_eq!(label_pixel(0, 0), &colors.foreground1[..3]);
+        assert_eq!(label_pixel(7, 1), &colors.foreground1[..3]);
+        assert_eq!(label_pixel(8, 0), &colors.background[..3]);
+        assert_eq!(label_pixel(2, 2), &colors.background[..3]);
+
+        assert_eq!(pixel(0, 0), SHELL_COLOR);
+        assert_eq!(pixel(LABEL_X - 1, LABEL_Y - 1), SHADE_COLOR);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let c8 = device();
+        let colors = ScreenshotColors::default();
+
+        assert!(matches!(
+            Cartridge::read_gif(&b"not a gif"[..]),
+            Err(CartridgeError::InvalidGif(_))
+        ));
+
+        // A GIF recording isn't a cartridge
+        let mut recording = crate::recording::Recording::new();
+        recording.record(c8.display(), &colors);
+        let mut data = Vec::new();
+        recording.write_gif(&mut data, 1).unwrap();
+        assert_eq!(
+            Cartridge::read_gif(data.as_slice()),
+            Err(CartridgeError::InvalidPayload)
+        );
+    }
+}
@@ -4,14 +4,19 @@ use std::vec;
 
 use crate::{
     display::{Display, DisplayResolution},
+    error::{ErrorPolicy, ExecutionError},
     fonts::FONT_DATA,
+    instruction::{decode, Instruction},
     keypad::{Keypad, KeypadKey, KEYPAD_KEYS},
     memory::Memory,
     message::DeviceMessage,
+    random::RandomGenerator,
+    snapshot::CpuSnapshot,
+    stack::Stack,
+    timing::{vip_instruction_cycles, VIP_KEY_WAIT_CYCLES},
 };
 
-use super::{display, quirks, PROGRAM_START};
-use rand::prelude::*;
+use super::{display, quirks, quirks::CompatibilityDevice, PROGRAM_START};
 
 /// The general purpose registers of the Chip-8
 // Dead code is allowed here because:
@@ -38,6 +43,22 @@ pub enum Register {
     VF,
 }
 
+/// Sound changes produced when the timers are updated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SoundEvent {
+    /// The sound timer is running without an audio pattern
+    Beep,
+
+    /// The sound timer is running with an audio pattern, which changed since the last update
+    PatternChanged,
+
+    /// The sound timer is running with the same audio pattern as the last update
+    PatternPlaying,
+
+    /// The sound timer is not running
+    Silence,
+}
+
 #[derive(Debug, Clone)]
 struct WaitingForKey {
     register: usize,
@@ -56,11 +77,9 @@ pub struct CPU {
     /// General purpose registers
     registers: Vec<u8>,
 
-    // TODO: Make private when timers are implemented
     /// Delay timer
     pub(crate) delay_timer: u8,
 
-    // TODO: Make private when timers are implemented
     /// Sound timer
     pub(crate) sound_timer: u8,
 
@@ -69,6 +88,9 @@ pub struct CPU {
     // Flags are saved to a file or external storage in some implementations
     saved_registers: Vec<u8>,
 
+    // Set when the flags were saved, so the device can write them to its flag storage
+    flags_saved: bool,
+
     audio_buffer: Vec<u8>,
 
     buffer_pitch: u8,
@@ -76,6 +98,21 @@ pub struct CPU {
     requesting_exit: bool,
 
     sound_dirty: bool,
+
+    // Random number generator used by CXNN
+    random: RandomGenerator,
+
+    // How memory and index register errors are handled
+    error_policy: ErrorPolicy,
+
+    // The device being emulated, used for instructions which differ between devices
+    compatibility_device: CompatibilityDevice,
+
+    // Set when a sprite was drawn with the display wait quirk, ending the current frame
+    v_blank_wait: bool,
+
+    // COSMAC VIP machine cycles used by the last instruction
+    vip_cycles: u32,
 }
 
 impl Default for CPU {
@@ -88,10 +125,16 @@ impl Default for CPU {
             sound_timer: 0,
             waiting_for_key: None,
             saved_registers: vec![0; 16],
+            flags_saved: false,
             audio_buffer: Vec::new(),
             buffer_pitch: 64,
             requesting_exit: false,
             sound_dirty: false,
+            random: RandomGenerator::default(),
+            error_policy: ErrorPolicy::default(),
+            compatibility_device: CompatibilityDevice::default(),
+            v_blank_wait: false,
+            vip_cycles: 0,
         }
     }
 }
@@ -132,23 +175,161 @@ impl CPU {
         &self.registers
     }
 
-    pub(crate) fn sound_dirty(&self) -> bool {
-        self.sound_dirty
+    /// Get the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Get the sound timer
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Update the timers for a single 60 Hz tick
+    pub(crate) fn tick_timers(&mut self) -> SoundEvent {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+
+        if self.sound_timer == 0 {
+            self.audio_buffer.clear();
+            return SoundEvent::Silence;
+        }
+
+        self.sound_timer -= 1;
+
+        if self.audio_buffer.is_empty() {
+            SoundEvent::Beep
+        } else if std::mem::take(&mut self.sound_dirty) {
+            SoundEvent::PatternChanged
+        } else {
+            SoundEvent::PatternPlaying
+        }
+    }
+
+    /// Get the random number generator
+    pub fn random_generator(&self) -> &RandomGenerator {
+        &self.random
+    }
+
+    pub(crate) fn set_random_generator(&mut self, random: RandomGenerator) {
+        self.random = random;
+    }
+
+    /// Get the emulated compatibility device
+    pub fn compatibility_device(&self) -> CompatibilityDevice {
+        self.compatibility_device
+    }
+
+    /// Set the emulated compatibility device
+    pub(crate) fn set_compatibility_device(&mut self, device: CompatibilityDevice) {
+        self.compatibility_device = device;
+    }
+
+    /// Check if the CPU is waiting for the next frame, this also clears the wait
+    pub(crate) fn take_v_blank_wait(&mut self) -> bool {
+        std::mem::take(&mut self.v_blank_wait)
+    }
+
+    /// Get the RPL user flags saved by `FX75`
+    #[must_use]
+    pub fn saved_flags(&self) -> &[u8] {
+        &self.saved_registers
+    }
+
+    /// Set the RPL user flags, extra flags are ignored
+    pub(crate) fn set_saved_flags(&mut self, flags: &[u8]) {
+        let count = flags.len().min(self.saved_registers.len());
+        self.saved_registers[..count].copy_from_slice(&flags[..count]);
+    }
+
+    /// Check if the flags were saved since the last check, this also clears the check
+    pub(crate) fn take_flags_saved(&mut self) -> bool {
+        std::mem::take(&mut self.flags_saved)
+    }
+
+    /// Check if the CPU is waiting for a key press or release
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key.is_some()
+    }
+
+    /// Decode the instruction at the program counter without running it
+    pub fn next_instruction(&self, memory: &Memory) -> Option<Instruction> {
+        let pc = usize::from(self.program_counter);
+        let opcode = self.read_word(memory, pc)?;
+        let next_word = self.read_word(memory, pc + 2).unwrap_or_default();
+
+        Some(decode(opcode, next_word))
+    }
+
+    /// Get the COSMAC VIP machine cycles used by the last instruction
+    pub fn vip_cycles(&self) -> u32 {
+        self.vip_cycles
+    }
+
+    /// Get the error policy
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    /// Set the error policy
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    /// Capture the CPU state
+    pub(crate) fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            registers: self.registers.clone(),
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            waiting_for_key: self
+                .waiting_for_key
+                .as_ref()
+                .map(|task| (task.register, task.key)),
+            saved_registers: self.saved_registers.clone(),
+            audio_buffer: self.audio_buffer.clone(),
+            buffer_pitch: self.buffer_pitch,
+            random: self.random.clone(),
+        }
+    }
+
+    /// Restore the CPU state from a validated snapshot
+    pub(crate) fn restore(&mut self, snapshot: &CpuSnapshot) {
+        self.registers.clone_from(&snapshot.registers);
+        self.index_register = snapshot.index_register;
+        self.program_counter = snapshot.program_counter;
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.waiting_for_key = snapshot
+            .waiting_for_key
+            .map(|(register, key)| WaitingForKey { register, key });
+        self.saved_registers.clone_from(&snapshot.saved_registers);
+        self.audio_buffer.clone_from(&snapshot.audio_buffer);
+        self.buffer_pitch = snapshot.buffer_pitch;
+        self.random.clone_from(&snapshot.random);
+        self.requesting_exit = false;
+
+        // Make sure the restored audio pattern gets played
+        self.restart_sound();
     }
 
-    pub(crate) fn clear_sound_dirty(&mut self) {
-        self.sound_dirty = false;
+    /// Play the audio pattern again on the next timer update, as if it just changed
+    pub(crate) fn restart_sound(&mut self) {
+        self.sound_dirty = !self.audio_buffer.is_empty();
     }
 
     /// Step the CPU by one instruction
+    ///
+    /// When an error occurs, the program counter points past the faulting instruction.
     pub fn step(
         &mut self,
         memory: &mut Memory,
         display: &mut display::Display,
-        stack: &mut Vec<u16>,
+        stack: &mut Stack,
         quirks: &quirks::Quirks,
         keypad: &Keypad,
-    ) -> Option<DeviceMessage> {
+    ) -> Result<Option<DeviceMessage>, ExecutionError> {
         // Note: This feels very hacky and should be refactored
         if let Some(task) = self.waiting_for_key.as_mut() {
             match task.key {
@@ -167,27 +348,25 @@ impl CPU {
                     }
                 }
             }
-            return None;
-        }
 
-        let pc = self.program_counter as usize;
-        //println!("Program counter: {:#X}", pc);
-        let opcode = ((memory.data[pc] as u16) << 8) | (memory.data[pc + 1] as u16);
+            self.vip_cycles = VIP_KEY_WAIT_CYCLES;
+            return Ok(None);
+        }
 
-        // TODO: Move to a UI window
-        /*
-        println!(
-            "Executing opcode: {:#X} from {:#X}, {:#X}",
-            opcode,
-            (memory.data[pc] as u16) << 8,
-            memory.data[pc + 1] as u16
-        );
-        panic!("Stop");
-         */
+        let pc = self.program_counter;
+        let opcode = self
+            .read_word(memory, pc as usize)
+            .ok_or(ExecutionError::FetchOutOfBounds { pc })?;
+        let next_word = self.read_word(memory, pc as usize + 2).unwrap_or_default();
+        let instruction = decode(opcode, next_word);
+        self.vip_cycles = vip_instruction_cycles(&instruction, &self.registers);
 
-        self.program_counter += 2;
+        // Note: The program counter wraps around like the 16-bit register it emulates
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.random.tick();
 
-        let message = self.execute_instruction(opcode, memory, display, stack, *quirks, keypad);
+        let message =
+            self.execute_instruction(instruction, opcode, memory, display, stack, *quirks, keypad)?;
 
         if let Some(DeviceMessage::WaitingForKey(register)) = message {
             self.waiting_for_key = Some(WaitingForKey {
@@ -200,316 +379,238 @@ impl CPU {
             });
         }
 
-        message
+        Ok(message)
     }
 
     // Intentionally allowing too many lines
-    #[allow(clippy::too_many_lines)]
+    #[allow(clippy::too_many_lines, clippy::too_many_arguments)]
     fn execute_instruction(
         &mut self,
+        instruction: Instruction,
         opcode: u16,
         memory: &mut Memory,
         display: &mut display::Display,
-        stack: &mut Vec<u16>,
+        stack: &mut Stack,
         quirks: quirks::Quirks,
         keypad: &Keypad,
-    ) -> Option<DeviceMessage> {
+    ) -> Result<Option<DeviceMessage>, ExecutionError> {
         let mut message = None;
 
-        //println!("Executing opcode: {:#X}", opcode);
-
-        // Extract the opcode parts
-        let reg_x = ((opcode & 0x0F00) >> 8) as usize;
-        let reg_y = ((opcode & 0x00F0) >> 4) as usize;
-        let n = (opcode & 0x000F) as u8;
-        let nn = (opcode & 0x00FF) as u8;
-        let nnn = opcode & 0x0FFF;
-
-        match opcode & 0xF000 {
-            0x0000 => match opcode & 0x00F0 {
-                //NOP
-                // 0x0000
-                0x0000 => {}
-
-                // Scroll down n lines
-                // 0x00CN
-                0x00C0 => {
-                    display.scroll_down(n);
+        match instruction {
+            // Machine code routines can't run, so they are skipped on devices whose programs
+            // call them. 0x0000 is always a NOP.
+            Instruction::MachineCall(nnn) => {
+                if nnn != 0 && !self.compatibility_device.supports_machine_calls() {
+                    message = unknown_opcode(nnn);
                 }
+            }
 
-                // Scroll up n lines
-                // 0x00DN
-                0x00D0 => {
-                    display.scroll_up(n);
-                }
+            Instruction::ScrollDown(n) => {
+                display.scroll_down(n);
+            }
 
-                0x00E0 => match opcode & 0xF0FF {
-                    // Clear the display
-                    // 0x00E0
-                    0x00E0 => {
-                        for layer in 0..display.plane_count() {
-                            if display.active_plane() & (layer + 1) == 0 {
-                                continue;
-                            }
-                            display.clear(layer);
-                        }
-                    }
+            Instruction::ScrollUp(n) => {
+                display.scroll_up(n);
+            }
 
-                    // Return from a subroutine
-                    // 0x00EE
-                    0x00EE => {
-                        // TODO: Make this more graceful
-                        self.program_counter =
-                            stack.pop().unwrap_or_else(|| panic!("Stack underflow"));
-                    }
+            Instruction::ClearScreen => {
+                display.clear_active();
+            }
 
-                    _ => {
-                        message = unknown_opcode(opcode);
-                    }
-                },
+            Instruction::Return => {
+                self.program_counter = stack.pop().ok_or(ExecutionError::StackUnderflow {
+                    pc: self.program_counter.wrapping_sub(2),
+                })?;
+            }
 
-                0x00F0 => match opcode & 0xF0FF {
-                    // Scroll right 4 pixels
-                    // 0x00FB
-                    0x00FB => {
-                        display.scroll_right(4);
-                    }
+            Instruction::ScrollRight => {
+                display.scroll_right(4);
+            }
 
-                    // Scroll left 4 pixels
-                    // 0x00FC
-                    0x00FC => {
-                        display.scroll_left(4);
-                    }
+            Instruction::ScrollLeft => {
+                display.scroll_left(4);
+            }
 
-                    // Exit
-                    // 0x00FD
-                    0x00FD => {
-                        // Note: The program counter is decremented by 2 to prevent the program from advancing
+            Instruction::Exit => {
+                // Note: The program counter is decremented by 2 to prevent the program from advancing
 
-                        self.requesting_exit = true;
-                        self.program_counter -= 2;
-                    }
+                self.requesting_exit = true;
+                self.program_counter = self.program_counter.wrapping_sub(2);
+            }
 
-                    // Enable low-res
-                    // 0x00FE
-                    0x00FE => {
-                        display.set_resolution(DisplayResolution::Low);
-                        message = Some(DeviceMessage::ChangeResolution(DisplayResolution::Low));
-                    }
+            Instruction::LowResolution => {
+                display.set_resolution(DisplayResolution::Low);
+                message = Some(DeviceMessage::ChangeResolution(DisplayResolution::Low));
+            }
 
-                    // Enable high-res
-                    // 0x00FF
-                    0x00FF => {
-                        display.set_resolution(DisplayResolution::High);
-                        message = Some(DeviceMessage::ChangeResolution(DisplayResolution::High));
-                    }
+            Instruction::HighResolution => {
+                display.set_resolution(DisplayResolution::High);
+                message = Some(DeviceMessage::ChangeResolution(DisplayResolution::High));
+            }
 
-                    _ => {
-                        message = unknown_opcode(opcode);
-                    }
-                },
+            Instruction::Jump(nnn) => {
+                self.program_counter = nnn;
+            }
 
-                _ => {
-                    message = unknown_opcode(opcode);
+            Instruction::Call(nnn) => {
+                if !stack.push(self.program_counter) {
+                    return Err(ExecutionError::StackOverflow {
+                        pc: self.program_counter.wrapping_sub(2),
+                    });
                 }
-            },
 
-            // Jump to address nnn
-            // 0x1NNN
-            0x1000 => {
                 self.program_counter = nnn;
             }
 
-            // Call subroutine at nnn
-            // 0x2NNN
-            0x2000 => {
-                stack.push(self.program_counter);
-                self.program_counter = nnn;
+            Instruction::SkipIfEqual { x, value } => {
+                if self.registers[usize::from(x)] == value {
+                    self.skip_next_instruction(memory);
+                }
             }
 
-            // Skip next instruction if Vx == nn
-            // 0x3XNN
-            0x3000 => {
-                if self.registers[reg_x] == nn {
+            Instruction::SkipIfNotEqual { x, value } => {
+                if self.registers[usize::from(x)] != value {
                     self.skip_next_instruction(memory);
                 }
             }
 
-            // Skip next instruction if Vx != nn
-            // 0x4XNN
-            0x4000 => {
-                if self.registers[reg_x] != nn {
+            Instruction::SkipIfRegistersEqual { x, y } => {
+                if self.registers[usize::from(x)] == self.registers[usize::from(y)] {
                     self.skip_next_instruction(memory);
                 }
             }
 
-            0x5000 => match opcode & 0xF00F {
-                // Skip next instruction if Vx == Vy
-                // 0x5XY0
-                0x5000 => {
-                    if self.registers[reg_x] == self.registers[reg_y] {
-                        self.skip_next_instruction(memory);
-                    }
-                }
+            Instruction::SaveRange { x, y } => {
+                let (reg_x, reg_y) = (usize::from(x), usize::from(y));
+                let distance = reg_x.abs_diff(reg_y);
+                let start = self.index_register as usize;
+                self.check_memory_range(memory, start, distance + 1, opcode)?;
 
-                // Save range
-                // 0x5XY2
-                0x5002 => {
-                    let distance = reg_x.abs_diff(reg_y);
-                    for z in 0..=distance {
-                        let index = (self.index_register + z as u16) as usize;
-                        memory.data[index] = if reg_x < reg_y {
-                            self.registers[reg_x + z]
-                        } else {
-                            self.registers[reg_x - z]
-                        };
-                    }
+                for z in 0..=distance {
+                    let index = self.memory_index(memory, start + z);
+                    memory.data[index] = if reg_x < reg_y {
+                        self.registers[reg_x + z]
+                    } else {
+                        self.registers[reg_x - z]
+                    };
                 }
+            }
 
-                // Load range
-                // 0x5XY3
-                0x5003 => {
-                    let distance = reg_x.abs_diff(reg_y);
+            Instruction::LoadRange { x, y } => {
+                let (reg_x, reg_y) = (usize::from(x), usize::from(y));
+                let distance = reg_x.abs_diff(reg_y);
+                let start = self.index_register as usize;
+                self.check_memory_range(memory, start, distance + 1, opcode)?;
 
-                    for z in 0..=distance {
-                        let index = if reg_x < reg_y { reg_x + z } else { reg_x - z };
-                        self.registers[index] =
-                            memory.data[(self.index_register + z as u16) as usize];
-                    }
-                }
-                _ => {
-                    message = unknown_opcode(opcode);
+                for z in 0..=distance {
+                    let index = if reg_x < reg_y { reg_x + z } else { reg_x - z };
+                    self.registers[index] = memory.data[self.memory_index(memory, start + z)];
                 }
-            },
+            }
 
-            // Set Vx = nn
-            // 0x6XNN
-            0x6000 => {
-                self.registers[reg_x] = nn;
+            Instruction::SetRegister { x, value } => {
+                self.registers[usize::from(x)] = value;
             }
 
-            // Set Vx = Vx + nn
-            // 0x7XNN
-            0x7000 => {
-                self.registers[reg_x] = self.registers[reg_x].wrapping_add(nn);
+            Instruction::AddValue { x, value } => {
+                let reg_x = usize::from(x);
+                self.registers[reg_x] = self.registers[reg_x].wrapping_add(value);
             }
 
-            0x8000 => match opcode & 0xF00F {
-                // Set Vx = Vy
-                // 0x8XY0
-                0x8000 => {
-                    self.registers[reg_x] = self.registers[reg_y];
-                }
+            Instruction::Copy { x, y } => {
+                self.registers[usize::from(x)] = self.registers[usize::from(y)];
+            }
 
-                // Set Vx = Vx OR Vy
-                // 0x8XY1
-                0x8001 => {
-                    self.registers[reg_x] |= self.registers[reg_y];
+            Instruction::Or { x, y } => {
+                self.registers[usize::from(x)] |= self.registers[usize::from(y)];
 
-                    // Quirk: Some programs expect VF to be 0
-                    if quirks.vf_zero {
-                        self.registers[Register::VF as usize] = 0;
-                    }
+                // Quirk: Some programs expect VF to be 0
+                if quirks.vf_zero {
+                    self.registers[Register::VF as usize] = 0;
                 }
+            }
 
-                // Set Vx = Vx AND Vy
-                // 0x8XY2
-                0x8002 => {
-                    self.registers[reg_x] &= self.registers[reg_y];
+            Instruction::And { x, y } => {
+                self.registers[usize::from(x)] &= self.registers[usize::from(y)];
 
-                    // Quirk: Some programs expect VF to be 0
-                    if quirks.vf_zero {
-                        self.registers[Register::VF as usize] = 0;
-                    }
+                // Quirk: Some programs expect VF to be 0
+                if quirks.vf_zero {
+                    self.registers[Register::VF as usize] = 0;
                 }
+            }
 
-                // Set Vx = Vx XOR Vy
-                // 0x8XY3
-                0x8003 => {
-                    self.registers[reg_x] ^= self.registers[reg_y];
+            Instruction::Xor { x, y } => {
+                self.registers[usize::from(x)] ^= self.registers[usize::from(y)];
 
-                    // Quirk: Some programs expect VF to be 0
-                    if quirks.vf_zero {
-                        self.registers[Register::VF as usize] = 0;
-                    }
+                // Quirk: Some programs expect VF to be 0
+                if quirks.vf_zero {
+                    self.registers[Register::VF as usize] = 0;
                 }
+            }
 
-                // Set Vx = Vx + Vy, set VF = carry
-                // 0x8XY4
-                0x8004 => {
-                    let (result, overflow) =
-                        self.registers[reg_x].overflowing_add(self.registers[reg_y]);
-                    self.registers[reg_x] = result;
-                    self.registers[Register::VF as usize] = overflow as u8;
-                }
+            Instruction::Add { x, y } => {
+                let reg_x = usize::from(x);
+                let (result, overflow) =
+                    self.registers[reg_x].overflowing_add(self.registers[usize::from(y)]);
+                self.registers[reg_x] = result;
+                self.registers[Register::VF as usize] = overflow as u8;
+            }
 
-                // Set Vx = Vx - Vy, set VF = NOT borrow
-                // 0x8XY5
-                0x8005 => {
-                    let (result, overflow) =
-                        self.registers[reg_x].overflowing_sub(self.registers[reg_y]);
-                    self.registers[reg_x] = result;
-                    self.registers[Register::VF as usize] = !overflow as u8;
-                }
+            Instruction::Subtract { x, y } => {
+                let reg_x = usize::from(x);
+                let (result, overflow) =
+                    self.registers[reg_x].overflowing_sub(self.registers[usize::from(y)]);
+                self.registers[reg_x] = result;
+                self.registers[Register::VF as usize] = !overflow as u8;
+            }
 
-                // Vx >>= 1
-                // 0x8XY6
-                0x8006 => {
-                    // Quirk: Some programs expect Vx to be shifted directly without assigning VY
-                    let quirk_y = if quirks.vx_shifted_directly {
-                        self.registers[reg_x]
-                    } else {
-                        self.registers[reg_y]
-                    };
+            Instruction::ShiftRight { x, y } => {
+                let reg_x = usize::from(x);
 
-                    self.registers[reg_x] = quirk_y >> 1;
-                    self.registers[Register::VF as usize] = quirk_y & 0x1;
-                }
+                // Quirk: Some programs expect Vx to be shifted directly without assigning VY
+                let quirk_y = if quirks.vx_shifted_directly {
+                    self.registers[reg_x]
+                } else {
+                    self.registers[usize::from(y)]
+                };
 
-                // Set Vx = Vy - Vx, set VF = NOT borrow
-                // 0x8XY7
-                0x8007 => {
-                    let (result, overflow) =
-                        self.registers[reg_y].overflowing_sub(self.registers[reg_x]);
-                    self.registers[reg_x] = result;
-                    self.registers[Register::VF as usize] = !overflow as u8;
-                }
+                self.registers[reg_x] = quirk_y >> 1;
+                self.registers[Register::VF as usize] = quirk_y & 0x1;
+            }
 
-                // Vx <<= 1
-                // 0x8XYE
-                0x800E => {
-                    // Quirk: Some programs expect Vx to be shifted directly without assigning VY
-                    let quirk_y = if quirks.vx_shifted_directly {
-                        self.registers[reg_x]
-                    } else {
-                        self.registers[reg_y]
-                    };
+            Instruction::SubtractReverse { x, y } => {
+                let reg_x = usize::from(x);
+                let (result, overflow) =
+                    self.registers[usize::from(y)].overflowing_sub(self.registers[reg_x]);
+                self.registers[reg_x] = result;
+                self.registers[Register::VF as usize] = !overflow as u8;
+            }
 
-                    self.registers[reg_x] = quirk_y << 1;
-                    self.registers[Register::VF as usize] = quirk_y >> 7;
-                }
-                _ => {
-                    message = unknown_opcode(opcode);
-                }
-            },
+            Instruction::ShiftLeft { x, y } => {
+                let reg_x = usize::from(x);
 
-            // Skip next instruction if Vx != Vy
-            // 0x9XY0
-            0x9000 => {
-                if self.registers[reg_x] != self.registers[reg_y] {
+                // Quirk: Some programs expect Vx to be shifted directly without assigning VY
+                let quirk_y = if quirks.vx_shifted_directly {
+                    self.registers[reg_x]
+                } else {
+                    self.registers[usize::from(y)]
+                };
+
+                self.registers[reg_x] = quirk_y << 1;
+                self.registers[Register::VF as usize] = quirk_y >> 7;
+            }
+
+            Instruction::SkipIfRegistersNotEqual { x, y } => {
+                if self.registers[usize::from(x)] != self.registers[usize::from(y)] {
                     self.skip_next_instruction(memory);
                 }
             }
 
-            // Set I = nnn
-            // 0xANNN
-            0xA000 => {
+            Instruction::SetIndex(nnn) => {
                 self.index_register = nnn;
             }
 
-            // Jump to location nnn + V0
-            // 0xBNNN
-            0xB000 => {
+            Instruction::JumpOffset(nnn) => {
                 self.program_counter = if quirks.jump_bits {
                     let index = (nnn >> 8) & 0xF;
                     nnn + self.registers[index as usize] as u16
@@ -518,224 +619,247 @@ impl CPU {
                 }
             }
 
-            // Set Vx = random byte AND nn
-            // 0xCXNN
-            0xC000 => {
-                let mut rng = rand::rng();
-                self.registers[reg_x] = rng.random::<u8>() & nn;
-            }
-
-            // Draw a sprite at position (Vx, Vy) with N bytes of sprite data starting at the address stored in the index register
-            // 0xDXYN
-            0xD000 => self.draw_sprite(
-                display,
-                memory,
-                self.registers[reg_x] as usize,
-                self.registers[reg_y] as usize,
-                n as usize,
-                quirks.clip_sprites,
-            ),
-
-            0xE000 => match opcode & 0xF0FF {
-                // Skip next instruction if key with the value of Vx is pressed
-                // 0xEX9E
-                0xE09E => {
-                    let key = self.registers[reg_x] as usize;
-
-                    if keypad.key(&key.into()) != 0 {
-                        self.skip_next_instruction(memory);
-                    }
-                }
+            Instruction::Random { x, mask } => {
+                self.registers[usize::from(x)] = self.random.next_byte() & mask;
+            }
 
-                // Skip next instruction if key with the value of Vx is not pressed
-                // 0xEXA1
-                0xE0A1 => {
-                    let key = self.registers[reg_x] as usize;
+            Instruction::Draw { x, y, height } => {
+                self.draw_sprite(
+                    display,
+                    memory,
+                    opcode,
+                    self.registers[usize::from(x)] as usize,
+                    self.registers[usize::from(y)] as usize,
+                    height as usize,
+                    quirks.clip_sprites,
+                )?;
+
+                // Quirk: The display waits for the vertical blank, so only one sprite is drawn per frame
+                self.v_blank_wait = quirks.v_blank;
+            }
 
-                    if keypad.key(&key.into()) == 0 {
-                        self.skip_next_instruction(memory);
-                    }
-                }
+            Instruction::SkipIfKeyPressed { x } => {
+                let key = self.registers[usize::from(x)] as usize;
 
-                _ => {
-                    message = unknown_opcode(opcode);
+                if keypad.key(&key.into()) != 0 {
+                    self.skip_next_instruction(memory);
                 }
-            },
+            }
 
-            0xF000 => match opcode & 0xF0FF {
-                // Load I extended
-                // 0xF000
-                0xF000 => {
-                    let pc: usize = self.program_counter as usize;
-                    let address = ((memory.data[pc] as u16) << 8) | (memory.data[pc + 1] as u16);
+            Instruction::SkipIfKeyNotPressed { x } => {
+                let key = self.registers[usize::from(x)] as usize;
 
-                    self.index_register = address;
-                    self.program_counter += 2;
+                if keypad.key(&key.into()) == 0 {
+                    self.skip_next_instruction(memory);
                 }
+            }
 
-                // Set active plane from Vx
-                // 0xFX01
-                0xF001 => display.set_active_plane(reg_x),
-
-                // Audio control
-                // 0xFX02
-                0xF002 => {
-                    self.audio_buffer = vec![0; 16];
-                    for offset in 0..16_u16 {
-                        let index = (self.index_register + offset) as usize;
-                        self.audio_buffer[offset as usize] = memory.data[index];
-                    }
-                    self.sound_dirty = true;
-                }
+            Instruction::SetIndexLong(address) => {
+                // The address is the next word, which has to be in memory
+                let pc = self.program_counter as usize;
+                self.check_memory_range(memory, pc, 2, opcode)?;
 
-                // Set Vx to the value of the delay timer
-                // 0xFX07
-                0xF007 => {
-                    self.registers[reg_x] = self.delay_timer;
-                }
+                self.index_register = address;
+                self.program_counter = self.program_counter.wrapping_add(2);
+            }
 
-                // Wait for a key press and store the result in Vx
-                // 0xFX0A
-                0xF00A => {
-                    message = Some(DeviceMessage::WaitingForKey(Some(reg_x)));
-                }
+            Instruction::SetPlanes(planes) => display.set_active_plane(usize::from(planes)),
 
-                // Set the delay timer to Vx
-                // 0xFX15
-                0xF015 => {
-                    self.delay_timer = self.registers[reg_x];
-                }
+            Instruction::LoadAudioPattern => {
+                let start = self.index_register as usize;
+                self.check_memory_range(memory, start, 16, opcode)?;
 
-                // Set the sound timer to Vx
-                // 0xFX18
-                0xF018 => {
-                    self.sound_timer = self.registers[reg_x];
+                self.audio_buffer = (0..16)
+                    .map(|offset| memory.data[self.memory_index(memory, start + offset)])
+                    .collect();
+                self.sound_dirty = true;
+            }
 
-                    if self.sound_timer == 0 {
-                        self.audio_buffer.clear();
-                    }
-                }
+            Instruction::GetDelayTimer { x } => {
+                self.registers[usize::from(x)] = self.delay_timer;
+            }
 
-                // Add Vx to the index register
-                // 0xFX1E
-                0xF01E => {
-                    self.index_register += self.registers[reg_x] as u16;
-                }
+            Instruction::WaitForKey { x } => {
+                message = Some(DeviceMessage::WaitingForKey(Some(usize::from(x))));
+            }
 
-                // Set I to the location of the sprite for the character in Vx
-                // 0xFX29
-                0xF029 => {
-                    self.index_register = (self.registers[reg_x] * 5) as u16;
-                }
+            Instruction::SetDelayTimer { x } => {
+                self.delay_timer = self.registers[usize::from(x)];
+            }
 
-                // Load I with big sprite
-                // 0xFX30
-                0xF030 => {
-                    let block = (self.registers[reg_x] & 0xF) * 10;
-                    let font_size = &FONT_DATA[memory.system_font as usize].small_data.len();
-                    self.index_register = (block + *font_size as u8) as u16;
-                }
+            Instruction::SetSoundTimer { x } => {
+                self.sound_timer = self.registers[usize::from(x)];
 
-                // Store the binary-coded decimal representation of Vx at the addresses I, I+1, and I+2
-                // 0xFX33
-                0xF033 => {
-                    memory.data[self.index_register as usize] = self.registers[reg_x] / 100;
-                    memory.data[(self.index_register + 1) as usize] =
-                        (self.registers[reg_x] / 10) % 10;
-                    memory.data[(self.index_register + 2) as usize] =
-                        (self.registers[reg_x] % 100) % 10;
+                if self.sound_timer == 0 {
+                    self.audio_buffer.clear();
                 }
+            }
 
-                // Buzz pitch
-                // 0xFX3A
-                0xF03A => {
-                    self.buffer_pitch = self.registers[reg_x];
-                }
+            Instruction::AddIndex { x } => {
+                self.index_register =
+                    self.offset_index(u16::from(self.registers[usize::from(x)]), opcode)?;
+            }
 
-                // Store V0 to Vx in memory starting at address I
-                // 0xFX55
-                0xF055 => {
-                    // TODO: Check if this is correct
-                    for i in 0..=reg_x {
-                        memory.data[(self.index_register + i as u16) as usize] = self.registers[i];
-                    }
+            Instruction::SmallFont { x } => {
+                self.index_register = u16::from(self.registers[usize::from(x)] & 0xF) * 5;
+            }
 
-                    // Quirk: Some programs expect I to be incremented
-                    if quirks.i_incremented {
-                        self.index_register += 1;
-                    }
+            Instruction::LargeFont { x } => {
+                let block = (self.registers[usize::from(x)] & 0xF) * 10;
+                let font_size = &FONT_DATA[memory.system_font as usize].small_data.len();
+                self.index_register = (block + *font_size as u8) as u16;
+            }
+
+            Instruction::BinaryCodedDecimal { x } => {
+                let start = self.index_register as usize;
+                self.check_memory_range(memory, start, 3, opcode)?;
+
+                let value = self.registers[usize::from(x)];
+                let digits = [value / 100, (value / 10) % 10, value % 10];
+
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    let index = self.memory_index(memory, start + offset);
+                    memory.data[index] = digit;
                 }
+            }
 
-                // Read V0 to Vx from memory starting at address I
-                // 0xFX65
-                0xF065 => {
-                    // TODO: Check if this is correct
-                    for i in 0..reg_x + 1 {
-                        self.registers[i] = memory.data[(self.index_register + i as u16) as usize];
-                    }
+            Instruction::SetPitch { x } => {
+                self.buffer_pitch = self.registers[usize::from(x)];
+            }
 
-                    // Quirk: Some programs expect I to be incremented
-                    if quirks.i_incremented {
-                        self.index_register += 1;
-                    }
+            Instruction::Store { x } => {
+                let reg_x = usize::from(x);
+                let start = self.index_register as usize;
+                self.check_memory_range(memory, start, reg_x + 1, opcode)?;
+
+                for i in 0..=reg_x {
+                    let index = self.memory_index(memory, start + i);
+                    memory.data[index] = self.registers[i];
                 }
 
-                // Save registers
-                // 0xFX75
-                0xF075 => {
-                    for i in 0..=reg_x {
-                        self.saved_registers[i] = self.registers[i];
-                    }
+                // Quirk: Some programs expect I to be incremented past the registers
+                if quirks.i_incremented {
+                    self.index_register = self.offset_index(u16::from(x) + 1, opcode)?;
                 }
+            }
 
-                // Load registers
-                // 0xFX85
-                0xF085 => {
-                    // Do not clear saved registers after loading
-                    for i in 0..=reg_x {
-                        self.registers[i] = self.saved_registers[i];
-                    }
+            Instruction::Load { x } => {
+                let reg_x = usize::from(x);
+                let start = self.index_register as usize;
+                self.check_memory_range(memory, start, reg_x + 1, opcode)?;
+
+                for i in 0..=reg_x {
+                    self.registers[i] = memory.data[self.memory_index(memory, start + i)];
                 }
 
-                _ => {
-                    message = unknown_opcode(opcode);
+                // Quirk: Some programs expect I to be incremented past the registers
+                if quirks.i_incremented {
+                    self.index_register = self.offset_index(u16::from(x) + 1, opcode)?;
                 }
-            },
+            }
+
+            Instruction::SaveFlags { x } => {
+                // Note: Registers past the flag count of the device are not saved
+                let count = (usize::from(x) + 1).min(self.compatibility_device.flag_count());
+                self.saved_registers[..count].copy_from_slice(&self.registers[..count]);
+                self.flags_saved = true;
+            }
+
+            Instruction::LoadFlags { x } => {
+                // Do not clear saved registers after loading
+                let count = (usize::from(x) + 1).min(self.compatibility_device.flag_count());
+                self.registers[..count].copy_from_slice(&self.saved_registers[..count]);
+            }
 
-            // Unknown opcode
-            _ => {
+            Instruction::Unknown(opcode) => {
                 message = unknown_opcode(opcode);
             }
         }
 
-        message
+        Ok(message)
     }
-
     #[inline]
     fn skip_next_instruction(&mut self, memory: &Memory) {
-        let pc = self.program_counter as usize;
-        let next_op = ((memory.data[pc] as u16) << 8) | (memory.data[pc + 1] as u16);
+        // Check if the next instruction is an XO instruction, a missing instruction is skipped
+        // normally so the error is reported when it is fetched
+        let next_op = self.read_word(memory, self.program_counter as usize);
+        let result = match next_op {
+            Some(opcode) if self.compatibility_device.supports_xo_chip() => {
+                decode(opcode, 0).size()
+            }
+            _ => 2,
+        };
+
+        self.program_counter = self.program_counter.wrapping_add(result);
+    }
+
+    /// Read a big endian word from memory, wrapping around if the error policy allows it
+    fn read_word(&self, memory: &Memory, address: usize) -> Option<u16> {
+        let length = memory.data.len();
+
+        if self.error_policy != ErrorPolicy::Wrap && address + 1 >= length {
+            return None;
+        }
+
+        let high = memory.data[address % length];
+        let low = memory.data[(address + 1) % length];
 
-        // Check if the next instruction is an XO instruction
-        let result = if next_op == 0xF000 { 4 } else { 2 };
+        Some((u16::from(high) << 8) | u16::from(low))
+    }
 
-        self.program_counter += result;
+    /// Check that a range of memory can be accessed
+    ///
+    /// Addresses are wrapped with `memory_index` when the error policy allows it.
+    fn check_memory_range(
+        &self,
+        memory: &Memory,
+        start: usize,
+        length: usize,
+        opcode: u16,
+    ) -> Result<(), ExecutionError> {
+        let memory_size = memory.data.len();
+
+        if self.error_policy == ErrorPolicy::Wrap || start + length <= memory_size {
+            Ok(())
+        } else {
+            Err(ExecutionError::MemoryOutOfBounds {
+                addr: start.max(memory_size),
+                opcode,
+            })
+        }
     }
 
+    /// Get the memory index of an address checked with `check_memory_range`
+    #[inline]
+    fn memory_index(&self, memory: &Memory, address: usize) -> usize {
+        address % memory.data.len()
+    }
+
+    /// Get the index register with an offset added
+    fn offset_index(&self, offset: u16, opcode: u16) -> Result<u16, ExecutionError> {
+        match self.index_register.checked_add(offset) {
+            Some(index) => Ok(index),
+            None if self.error_policy == ErrorPolicy::Wrap => {
+                Ok(self.index_register.wrapping_add(offset))
+            }
+            None => Err(ExecutionError::IndexOverflow { opcode }),
+        }
+    }
+
+    // Intentionally allowing too many arguments
+    #[allow(clippy::too_many_arguments)]
     fn draw_sprite(
         &mut self,
         display: &mut Display,
         memory: &mut Memory,
+        opcode: u16,
         x: usize,
         y: usize,
         height: usize,
         clip_sprites: bool,
-    ) {
+    ) -> Result<(), ExecutionError> {
         // Note: This is one of the more complex instructions.
 
-        // Quirk: The sprites are limited to 60 per second due to V-blank interrupt waiting.
-        // This may be implemented in the future with a toggle.
-
         let (screen_width, screen_height) = display.screen_size_xy();
         let x = x % screen_width;
         let y = y % screen_height;
@@ -750,17 +874,17 @@ impl CPU {
         let sprite_height = if height == 0 { 16 } else { height };
         let step = if height == 0 { 32 } else { height };
 
-        for layer in 0..display.plane_count() {
-            if display.active_plane() & (layer + 1) == 0 {
-                continue;
-            }
+        let active_planes = display.active_planes().count();
+        self.check_memory_range(memory, i, step * active_planes, opcode)?;
 
+        for layer in display.active_planes() {
             for a in 0..sprite_height {
                 let line: u16 = if height == 0 {
                     let read_index = (2 * a) + i;
-                    ((memory.data[read_index] as u16) << 8) | memory.data[read_index + 1] as u16
+                    (u16::from(memory.data[self.memory_index(memory, read_index)]) << 8)
+                        | u16::from(memory.data[self.memory_index(memory, read_index + 1)])
                 } else {
-                    memory.data[i + a] as u16
+                    u16::from(memory.data[self.memory_index(memory, i + a)])
                 };
 
                 for b in 0..sprite_width {
@@ -801,6 +925,8 @@ impl CPU {
         }
 
         self.registers[Register::VF as usize] = collision;
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,561 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::Range,
+};
+
+use crate::{
+    cpu::CPU,
+    display::Display,
+    expression::{Expression, LogMessage},
+    instruction::Instruction,
+    memory::Memory,
+    trace::TraceAction,
+};
+
+/// Maximum number of logpoint messages kept until they are taken, older messages are dropped
+pub const MAX_LOG_MESSAGES: usize = 1024;
+
+/// A register which can be watched for changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchedRegister {
+    /// One of the general purpose registers V0 to VF
+    V(u8),
+
+    /// The index register
+    Index,
+}
+
+impl WatchedRegister {
+    /// Get the value of the register
+    #[must_use]
+    pub fn value(&self, cpu: &CPU) -> u16 {
+        match self {
+            WatchedRegister::V(register) => u16::from(cpu.registers()[usize::from(*register)]),
+            WatchedRegister::Index => cpu.index_register(),
+        }
+    }
+}
+
+impl std::fmt::Display for WatchedRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchedRegister::V(register) => write!(f, "V{register:X}"),
+            WatchedRegister::Index => write!(f, "I"),
+        }
+    }
+}
+
+/// Kind of memory access which triggers a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    /// The memory is read
+    Read,
+
+    /// The memory is written
+    Write,
+
+    /// The memory is read or written
+    ReadWrite,
+}
+
+impl MemoryAccess {
+    /// Get the locale key of the access
+    #[must_use]
+    pub fn name_key(&self) -> &str {
+        match self {
+            MemoryAccess::Read => "watch_read",
+            MemoryAccess::Write => "watch_write",
+            MemoryAccess::ReadWrite => "watch_read_write",
+        }
+    }
+
+    /// Check if an access of this kind matches a read or write
+    fn matches(self, write: bool) -> bool {
+        match self {
+            MemoryAccess::Read => !write,
+            MemoryAccess::Write => write,
+            MemoryAccess::ReadWrite => true,
+        }
+    }
+}
+
+/// List of memory accesses for iteration
+pub const MEMORY_ACCESSES: [MemoryAccess; 3] = [
+    MemoryAccess::Read,
+    MemoryAccess::Write,
+    MemoryAccess::ReadWrite,
+];
+
+/// A program counter breakpoint
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Breakpoint {
+    /// Only hit the breakpoint while the condition is true
+    pub condition: Option<Expression>,
+
+    /// Only stop once the breakpoint has been hit this many times, 0 and 1 stop on every hit
+    pub hit_target: u32,
+
+    /// Log the message instead of stopping, which makes this a logpoint
+    pub log_message: Option<LogMessage>,
+
+    /// Start or stop the execution trace instead of stopping, which makes this a tracepoint
+    pub trace_action: Option<TraceAction>,
+}
+
+/// A range of memory watched for accesses
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWatchpoint {
+    /// Watched addresses
+    pub range: Range<usize>,
+
+    /// Accesses which trigger the watchpoint
+    pub access: MemoryAccess,
+}
+
+/// Reason the debugger stopped the device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakReason {
+    /// The program counter reached a breakpoint, the instruction hasn't run yet
+    Breakpoint {
+        /// Address of the breakpoint
+        pc: u16,
+    },
+
+    /// An instruction read watched memory
+    MemoryRead {
+        /// Address of the instruction
+        pc: u16,
+
+        /// First watched address which was read
+        address: usize,
+    },
+
+    /// An instruction wrote watched memory
+    MemoryWrite {
+        /// Address of the instruction
+        pc: u16,
+
+        /// First watched address which was written
+        address: usize,
+    },
+
+    /// An instruction changed a watched register
+    RegisterChanged {
+        /// Address of the instruction
+        pc: u16,
+
+        /// The register which changed
+        register: WatchedRegister,
+
+        /// Value before the instruction
+        old: u16,
+
+        /// Value after the instruction
+        new: u16,
+    },
+
+    /// A step into, over or out of a subroutine finished
+    StepComplete {
+        /// Address of the next instruction
+        pc: u16,
+    },
+}
+
+impl std::fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakReason::Breakpoint { pc } => write!(f, "Breakpoint at {pc:#06X}"),
+            BreakReason::MemoryRead { pc, address } => {
+                write!(f, "Memory read at {address:#06X} by {pc:#06X}")
+            }
+            BreakReason::MemoryWrite { pc, address } => {
+                write!(f, "Memory write at {address:#06X} by {pc:#06X}")
+            }
+            BreakReason::RegisterChanged {
+                pc,
+                register,
+                old,
+                new,
+            } => write!(
+                f,
+                "{register} changed from {old:#X} to {new:#X} by {pc:#06X}"
+            ),
+            BreakReason::StepComplete { pc } => write!(f, "Stepped to {pc:#06X}"),
+        }
+    }
+}
+
+/// How the debugger is stepping through the program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StepMode {
+    /// Stop after the next instruction
+    Into,
+
+    /// Stop once the stack is back to the depth, so calls run to completion
+    Over {
+        /// Stack depth before the step
+        depth: usize,
+    },
+
+    /// Stop once the stack is shallower than the depth
+    Out {
+        /// Stack depth before the step
+        depth: usize,
+    },
+}
+
+/// State of the device before an instruction, used to find what the instruction did
+#[derive(Debug, Clone)]
+pub(crate) struct InstructionState {
+    /// Address of the instruction
+    pc: u16,
+
+    /// Watched registers and their values
+    registers: Vec<(WatchedRegister, u16)>,
+
+    /// Memory the instruction accesses, and whether it writes it
+    memory_access: Option<(Range<usize>, bool)>,
+}
+
+/// Breakpoints, watchpoints and stepping for a device
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    /// Program counter breakpoints
+    breakpoints: BTreeMap<u16, Breakpoint>,
+
+    /// Number of times each breakpoint was hit with its condition true
+    hit_counts: BTreeMap<u16, u32>,
+
+    /// Watched memory ranges
+    memory_watchpoints: Vec<MemoryWatchpoint>,
+
+    /// Watched registers
+    register_watchpoints: Vec<WatchedRegister>,
+
+    /// The step in progress
+    step: Option<StepMode>,
+
+    /// Whether the device is stopped by the debugger
+    paused: bool,
+
+    /// Breakpoint which is ignored once, so resuming from a breakpoint doesn't stop straight away
+    resume_pc: Option<u16>,
+
+    /// Reason of the last break
+    last_break: Option<BreakReason>,
+
+    /// Messages logged by logpoints
+    logs: VecDeque<String>,
+
+    /// Trace action of the last tracepoint hit, until the device applies it
+    trace_action: Option<TraceAction>,
+}
+
+impl Debugger {
+    /// Get the breakpoints by address
+    #[must_use]
+    pub fn breakpoints(&self) -> &BTreeMap<u16, Breakpoint> {
+        &self.breakpoints
+    }
+
+    /// Get the breakpoint at an address
+    #[must_use]
+    pub fn breakpoint(&self, pc: u16) -> Option<&Breakpoint> {
+        self.breakpoints.get(&pc)
+    }
+
+    /// Get the number of times the breakpoint at an address was hit with its condition true
+    #[must_use]
+    pub fn hit_count(&self, pc: u16) -> u32 {
+        self.hit_counts.get(&pc).copied().unwrap_or_default()
+    }
+
+    /// Add an unconditional breakpoint at an address, an existing breakpoint is kept
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.entry(pc).or_default();
+    }
+
+    /// Add or replace the breakpoint at an address, which resets its hit count
+    pub fn set_breakpoint(&mut self, pc: u16, breakpoint: Breakpoint) {
+        self.breakpoints.insert(pc, breakpoint);
+        self.hit_counts.remove(&pc);
+    }
+
+    /// Remove the breakpoint at an address
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.remove(&pc);
+        self.hit_counts.remove(&pc);
+    }
+
+    /// Add or remove the breakpoint at an address
+    pub fn toggle_breakpoint(&mut self, pc: u16) {
+        if self.breakpoints.contains_key(&pc) {
+            self.remove_breakpoint(pc);
+        } else {
+            self.add_breakpoint(pc);
+        }
+    }
+
+    /// Get the memory watchpoints
+    #[must_use]
+    pub fn memory_watchpoints(&self) -> &[MemoryWatchpoint] {
+        &self.memory_watchpoints
+    }
+
+    /// Watch a range of memory
+    pub fn watch_memory(&mut self, range: Range<usize>, access: MemoryAccess) {
+        self.memory_watchpoints
+            .push(MemoryWatchpoint { range, access });
+    }
+
+    /// Remove a memory watchpoint
+    pub fn remove_memory_watchpoint(&mut self, index: usize) {
+        if index < self.memory_watchpoints.len() {
+            self.memory_watchpoints.remove(index);
+        }
+    }
+
+    /// Get the watched registers
+    #[must_use]
+    pub fn register_watchpoints(&self) -> &[WatchedRegister] {
+        &self.register_watchpoints
+    }
+
+    /// Check if a register is watched
+    #[must_use]
+    pub fn is_register_watched(&self, register: WatchedRegister) -> bool {
+        self.register_watchpoints.contains(&register)
+    }
+
+    /// Watch a register for changes
+    pub fn watch_register(&mut self, register: WatchedRegister) {
+        if !self.is_register_watched(register) {
+            self.register_watchpoints.push(register);
+        }
+    }
+
+    /// Stop watching a register
+    pub fn unwatch_register(&mut self, register: WatchedRegister) {
+        self.register_watchpoints
+            .retain(|watched| *watched != register);
+    }
+
+    /// Remove all breakpoints and watchpoints
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.hit_counts.clear();
+        self.memory_watchpoints.clear();
+        self.register_watchpoints.clear();
+    }
+
+    /// Check if the debugger has stopped the device
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop the device at the next tick
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.step = None;
+    }
+
+    /// Let the device run until the next break
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step = None;
+    }
+
+    /// Get the reason of the last break
+    #[must_use]
+    pub fn last_break(&self) -> Option<BreakReason> {
+        self.last_break
+    }
+
+    /// Take the messages logged by logpoints since the last call
+    pub fn take_logs(&mut self) -> Vec<String> {
+        self.logs.drain(..).collect()
+    }
+
+    /// Start a step and let the device run until it is complete
+    pub(crate) fn start_step(&mut self, step: StepMode) {
+        self.paused = false;
+        self.step = Some(step);
+    }
+
+    /// Forget the pause, the step in progress and the hit counts, breakpoints and watchpoints
+    /// are kept
+    pub(crate) fn reset_state(&mut self) {
+        self.paused = false;
+        self.step = None;
+        self.resume_pc = None;
+        self.last_break = None;
+        self.hit_counts.clear();
+        self.trace_action = None;
+    }
+
+    /// Check if instructions need to be inspected before and after they run
+    pub(crate) fn is_watching(&self) -> bool {
+        self.step.is_some()
+            || !self.memory_watchpoints.is_empty()
+            || !self.register_watchpoints.is_empty()
+    }
+
+    /// Check for a breakpoint before running the instruction at the program counter
+    ///
+    /// Logpoints add their message to the log and tracepoints start or stop the trace, neither
+    /// stops the device.
+    pub(crate) fn check_breakpoint(&mut self, cpu: &CPU, memory: &Memory) -> Option<BreakReason> {
+        let pc = cpu.program_counter();
+
+        // The breakpoint the device stopped at is skipped when resuming
+        if self.resume_pc.take() == Some(pc) {
+            return None;
+        }
+
+        let breakpoint = self.breakpoints.get(&pc)?;
+
+        if let Some(condition) = &breakpoint.condition {
+            if !condition.is_true(cpu, memory) {
+                return None;
+            }
+        }
+
+        let hits = self.hit_counts.entry(pc).or_default();
+        *hits = hits.saturating_add(1);
+        if *hits < breakpoint.hit_target {
+            return None;
+        }
+
+        if let Some(message) = &breakpoint.log_message {
+            if self.logs.len() >= MAX_LOG_MESSAGES {
+                self.logs.pop_front();
+            }
+
+            self.logs.push_back(message.format(cpu, memory));
+        }
+
+        if breakpoint.trace_action.is_some() {
+            self.trace_action = breakpoint.trace_action;
+        }
+
+        if breakpoint.log_message.is_some() || breakpoint.trace_action.is_some() {
+            return None;
+        }
+
+        self.resume_pc = Some(pc);
+        Some(self.stop(BreakReason::Breakpoint { pc }))
+    }
+
+    /// Take the trace action of the last tracepoint hit
+    pub(crate) fn take_trace_action(&mut self) -> Option<TraceAction> {
+        self.trace_action.take()
+    }
+
+    /// Record the state of the device before an instruction runs
+    pub(crate) fn instruction_state(
+        &self,
+        cpu: &CPU,
+        display: &Display,
+        instruction: Option<Instruction>,
+    ) -> InstructionState {
+        InstructionState {
+            pc: cpu.program_counter(),
+            registers: self
+                .register_watchpoints
+                .iter()
+                .map(|register| (*register, register.value(cpu)))
+                .collect(),
+            memory_access: instruction
+                .and_then(|instruction| memory_access(instruction, cpu, display)),
+        }
+    }
+
+    /// Check for watchpoints and the end of a step after an instruction runs
+    pub(crate) fn check_instruction(
+        &mut self,
+        state: &InstructionState,
+        cpu: &CPU,
+        stack_depth: usize,
+    ) -> Option<BreakReason> {
+        let pc = state.pc;
+
+        if let Some((range, write)) = &state.memory_access {
+            let watchpoint = self.memory_watchpoints.iter().find(|watchpoint| {
+                watchpoint.access.matches(*write)
+                    && watchpoint.range.start < range.end
+                    && range.start < watchpoint.range.end
+            });
+
+            if let Some(watchpoint) = watchpoint {
+                let address = watchpoint.range.start.max(range.start);
+                let reason = if *write {
+                    BreakReason::MemoryWrite { pc, address }
+                } else {
+                    BreakReason::MemoryRead { pc, address }
+                };
+
+                return Some(self.stop(reason));
+            }
+        }
+
+        for &(register, old) in &state.registers {
+            let new = register.value(cpu);
+            if new != old {
+                return Some(self.stop(BreakReason::RegisterChanged {
+                    pc,
+                    register,
+                    old,
+                    new,
+                }));
+            }
+        }
+
+        let step_complete = match self.step? {
+            StepMode::Into => true,
+            StepMode::Over { depth } => stack_depth <= depth,
+            StepMode::Out { depth } => stack_depth < depth,
+        };
+
+        step_complete.then(|| {
+            self.stop(BreakReason::StepComplete {
+                pc: cpu.program_counter(),
+            })
+        })
+    }
+
+    /// Pause the device for a break
+    fn stop(&mut self, reason: BreakReason) -> BreakReason {
+        self.paused = true;
+        self.step = None;
+        self.last_break = Some(reason);
+        reason
+    }
+}
+
+/// Get the memory an instruction accesses, and whether it writes it
+///
+/// Instruction fetches aren't included.
+fn memory_access(
+    instruction: Instruction,
+    cpu: &CPU,
+    display: &Display,
+) -> Option<(Range<usize>, bool)> {
+    let start = usize::from(cpu.index_register());
+    let range = |length: usize| start..start + length;
+
+    match instruction {
+        Instruction::SaveRange { x, y } => Some((range(usize::from(x.abs_diff(y)) + 1), true)),
+        Instruction::LoadRange { x, y } => Some((range(usize::from(x.abs_diff(y)) + 1), false)),
+        Instruction::Store { x } => Some((range(usize::from(x) + 1), true)),
+        Instruction::Load { x } => Some((range(usize::from(x) + 1), false)),
+        Instruction::BinaryCodedDecimal { .. } => Some((range(3), true)),
+        Instruction::LoadAudioPattern => Some((range(16), false)),
+        Instruction::Draw { height, .. } => {
+            // 16x16 sprites use 32 bytes for each plane
+            let bytes = if height == 0 { 32 } else { usize::from(height) };
+            Some((range(bytes * display.active_planes().count()), false))
+        }
+        _ => None,
+    }
+}
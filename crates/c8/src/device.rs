@@ -1,8 +1,33 @@
+use std::time::Duration;
+
 use crate::{
-    audio::AudioDevice, cpu::CPU, display::Display, keypad::Keypad, memory::Memory,
-    message::DeviceMessage, quirks::Quirks,
+    audio::{recorder::AudioRecorder, AudioDevice},
+    cpu::{SoundEvent, CPU},
+    debugger::{Debugger, StepMode},
+    display::Display,
+    error::{ErrorPolicy, ExecutionError},
+    flags::FlagStorage,
+    instruction::Instruction,
+    keypad::Keypad,
+    memory::Memory,
+    message::DeviceMessage,
+    quirks::{CompatibilityProfile, Quirks},
+    random::{RandomGenerator, RandomSource},
+    rom_sha1,
+    snapshot::{DeviceSnapshot, SnapshotError, SNAPSHOT_VERSION},
+    stack::Stack,
+    timing::{CpuTiming, VIP_INTERPRETER_CYCLES},
+    trace::Tracer,
 };
 
+/// Rate of the timers and the display in ticks per second
+pub const TICK_RATE: f64 = 60.0;
+
+/// Maximum number of ticks run for a single duration, time past this is dropped
+///
+/// This stops the device from running a long burst of ticks after the host stalls.
+pub const MAX_CATCH_UP_TICKS: u32 = 30;
+
 /// Chip-8 Device
 #[derive(Debug, Default)]
 pub struct C8 {
@@ -16,7 +41,7 @@ pub struct C8 {
     cpu: CPU,
 
     /// Stack memory
-    stack: Vec<u16>,
+    stack: Stack,
 
     /// Whether the device is running
     is_running: bool,
@@ -27,6 +52,40 @@ pub struct C8 {
     /// Quirks
     quirks: Quirks,
 
+    /// Source of random numbers
+    random_source: RandomSource,
+
+    /// The error which halted the device
+    execution_error: Option<ExecutionError>,
+
+    /// Fraction of a tick left over from previous durations
+    pending_ticks: f64,
+
+    /// How many instructions are run each tick
+    cpu_timing: CpuTiming,
+
+    /// COSMAC VIP machine cycles left in the current tick, negative when the last instruction
+    /// of the previous tick ran past its end
+    vip_cycles: i32,
+
+    /// Breakpoints, watchpoints and stepping
+    debugger: Debugger,
+
+    /// Records the instructions which run
+    tracer: Option<Tracer>,
+
+    /// Renders the sound to PCM
+    audio_recorder: Option<AudioRecorder>,
+
+    /// Keeps the RPL user flags between sessions
+    flag_storage: Option<Box<dyn FlagStorage>>,
+
+    /// Key of the loaded ROM in the flag storage
+    flags_key: String,
+
+    /// Set while recorded frames are replayed, which keeps the sound from playing
+    replaying: bool,
+
     /// Audio device for both web and non-web targets
     pub audio_device: AudioDevice,
 }
@@ -54,6 +113,66 @@ impl C8 {
         self.quirks = quirks;
     }
 
+    /// Set the quirks and stack depth of the device from a compatibility profile
+    pub fn set_compatibility_profile(&mut self, profile: &CompatibilityProfile) {
+        self.quirks = profile.quirks;
+        self.stack.set_depth(profile.device.stack_depth());
+        self.cpu.set_compatibility_device(profile.device);
+        self.set_cpu_timing(profile.timing);
+    }
+
+    /// Get the timing model of the device
+    #[must_use]
+    pub fn cpu_timing(&self) -> CpuTiming {
+        self.cpu_timing
+    }
+
+    /// Set the timing model of the device
+    pub fn set_cpu_timing(&mut self, cpu_timing: CpuTiming) {
+        self.cpu_timing = cpu_timing;
+        self.vip_cycles = 0;
+    }
+
+    /// Get the call stack of the device
+    #[must_use]
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    /// Set the call stack depth of the device
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack.set_depth(depth);
+    }
+
+    /// Get the random number source of the device
+    #[must_use]
+    pub fn random_source(&self) -> RandomSource {
+        self.random_source
+    }
+
+    /// Set the random number source of the device, this also reseeds the generator
+    pub fn set_random_source(&mut self, source: RandomSource) {
+        self.random_source = source;
+        self.cpu.set_random_generator(RandomGenerator::new(source));
+    }
+
+    /// Get the error policy of the device
+    #[must_use]
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.cpu.error_policy()
+    }
+
+    /// Set the error policy of the device
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.cpu.set_error_policy(error_policy);
+    }
+
+    /// Get the error which halted the device, if any
+    #[must_use]
+    pub fn execution_error(&self) -> Option<ExecutionError> {
+        self.execution_error
+    }
+
     /// Get the display of the device
     #[must_use] 
     pub fn display(&self) -> &Display {
@@ -76,18 +195,173 @@ impl C8 {
         &mut self.keypad
     }
 
+    /// Get the CPU of the device
+    #[must_use]
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    /// Get the debugger
+    #[must_use]
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Get the debugger mutably, to add breakpoints and watchpoints or pause the device
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Get the tracer
+    #[must_use]
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Get the tracer mutably
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Start recording the instructions which run, or stop with `None`
+    ///
+    /// The previous tracer is returned, so its lines can be read or its writer flushed.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// Get the audio recorder
+    #[must_use]
+    pub fn audio_recorder(&self) -> Option<&AudioRecorder> {
+        self.audio_recorder.as_ref()
+    }
+
+    /// Start rendering the sound of each frame, or stop with `None`
+    ///
+    /// The previous recorder is returned, so its samples can be saved. Sound is recorded even
+    /// when the audio settings are disabled.
+    pub fn set_audio_recorder(&mut self, recorder: Option<AudioRecorder>) -> Option<AudioRecorder> {
+        std::mem::replace(&mut self.audio_recorder, recorder)
+    }
+
+    /// Get the flag storage
+    #[must_use]
+    pub fn flag_storage(&self) -> Option<&dyn FlagStorage> {
+        self.flag_storage.as_deref()
+    }
+
+    /// Keep the RPL user flags in a storage, or only in the device with `None`
+    ///
+    /// The flags of a ROM are loaded from the storage when it is loaded, and written to it every
+    /// time the ROM saves them. The previous storage is returned.
+    pub fn set_flag_storage(
+        &mut self,
+        storage: Option<Box<dyn FlagStorage>>,
+    ) -> Option<Box<dyn FlagStorage>> {
+        std::mem::replace(&mut self.flag_storage, storage)
+    }
+
+    /// Write the flags of the loaded ROM to the flag storage, a failed write is returned as a message
+    fn save_flags(&mut self) -> Option<DeviceMessage> {
+        let storage = self.flag_storage.as_mut()?;
+
+        storage
+            .save_flags(&self.flags_key, self.cpu.saved_flags())
+            .err()
+            .map(|error| DeviceMessage::FlagsNotSaved(error.kind()))
+    }
+
+    /// Run the next instruction and pause again
+    pub fn step_into(&mut self) {
+        self.debugger.start_step(StepMode::Into);
+    }
+
+    /// Run the next instruction and pause again, a subroutine call runs until it returns
+    pub fn step_over(&mut self) {
+        let step = match self.cpu.next_instruction(&self.memory) {
+            Some(Instruction::Call(_)) => StepMode::Over {
+                depth: self.stack.len(),
+            },
+            _ => StepMode::Into,
+        };
+
+        self.debugger.start_step(step);
+    }
+
+    /// Run until the current subroutine returns and pause again
+    pub fn step_out(&mut self) {
+        self.debugger.start_step(StepMode::Out {
+            depth: self.stack.len(),
+        });
+    }
+
     /// Get if the device is running
     #[must_use] 
     pub fn is_running(&self) -> bool {
         self.is_running
     }
 
+    /// Capture the current state of the device
+    #[must_use]
+    pub fn snapshot(&self) -> DeviceSnapshot {
+        DeviceSnapshot {
+            version: SNAPSHOT_VERSION,
+            memory: self.memory.data.clone(),
+            system_font: self.memory.system_font,
+            display: self.display.snapshot(),
+            cpu: self.cpu.snapshot(),
+            stack: self.stack.addresses().to_vec(),
+            stack_depth: self.stack.depth(),
+            quirks: self.quirks,
+            compatibility_device: self.cpu.compatibility_device(),
+            cpu_timing: self.cpu_timing,
+            vip_cycles: self.vip_cycles,
+            is_running: self.is_running,
+        }
+    }
+
+    /// Restore the device to a previously captured state
+    ///
+    /// The device is left untouched if the snapshot is invalid.
+    pub fn restore(&mut self, snapshot: &DeviceSnapshot) -> Result<(), SnapshotError> {
+        snapshot.validate()?;
+
+        self.memory.data.clone_from(&snapshot.memory);
+        self.memory.system_font = snapshot.system_font;
+        self.display.restore(&snapshot.display);
+        self.cpu.restore(&snapshot.cpu);
+        self.stack.set_depth(snapshot.stack_depth);
+        self.stack.set_addresses(&snapshot.stack);
+        self.quirks = snapshot.quirks;
+        self.cpu
+            .set_compatibility_device(snapshot.compatibility_device);
+        self.cpu_timing = snapshot.cpu_timing;
+        self.vip_cycles = snapshot.vip_cycles;
+        self.is_running = snapshot.is_running;
+        self.execution_error = None;
+
+        // Stop any audio from the previous state, the timers will restart it if needed
+        self.audio_device.pause();
+
+        Ok(())
+    }
+
     /// Resets the device, loads ROM and font data into memory, and starts the device
     pub fn load_rom(&mut self, rom: &[u8]) {
         self.reset_device();
 
         self.memory.load_rom(rom);
 
+        // Restore the flags the ROM saved in previous sessions
+        self.flags_key = rom_sha1(rom);
+        if let Some(flags) = self
+            .flag_storage
+            .as_ref()
+            .and_then(|storage| storage.load_flags(&self.flags_key))
+        {
+            self.cpu.set_saved_flags(&flags);
+        }
+
         self.is_running = true;
     }
 
@@ -95,71 +369,290 @@ impl C8 {
     pub fn reset_device(&mut self) {
         self.audio_device.stop();
         let current_font = self.memory.system_font;
+        let random_source = self.random_source;
+        let error_policy = self.error_policy();
+        let stack_depth = self.stack.depth();
+        let compatibility_device = self.cpu.compatibility_device();
+        let quirks = self.quirks;
+        let cpu_timing = self.cpu_timing;
+        let mut debugger = std::mem::take(&mut self.debugger);
+        let tracer = self.tracer.take();
+        let audio_recorder = self.audio_recorder.take();
+        let flag_storage = self.flag_storage.take();
+        let replaying = self.replaying;
         *self = Self::default();
 
+        // Breakpoints and watchpoints are kept for the next run
+        debugger.reset_state();
+        self.debugger = debugger;
+        self.tracer = tracer;
+        self.audio_recorder = audio_recorder;
+        self.flag_storage = flag_storage;
+        self.replaying = replaying;
+        self.quirks = quirks;
+        self.cpu_timing = cpu_timing;
+        self.set_error_policy(error_policy);
+        self.stack.set_depth(stack_depth);
+        self.cpu.set_compatibility_device(compatibility_device);
+
         // Reload font data
         self.memory
             .load_font_name(current_font, &crate::fonts::FontSize::Small);
+
+        // Reseed the random number generator so runs can be reproduced
+        self.set_random_source(random_source);
+    }
+
+    /// Add elapsed time to the device and get the number of whole ticks which are due
+    ///
+    /// Fractions of a tick are kept for the next call. Use this with `step` when each tick
+    /// needs to be handled separately, otherwise use `run_for`.
+    pub fn accumulate_ticks(&mut self, elapsed: Duration) -> u32 {
+        self.pending_ticks += elapsed.as_secs_f64() * TICK_RATE;
+
+        let ticks = self.pending_ticks.floor();
+        self.pending_ticks -= ticks;
+
+        // Note: The float to int cast saturates, which is then capped anyway
+        (ticks as u32).min(MAX_CATCH_UP_TICKS)
+    }
+
+    /// Run the device for the given amount of time, using `cpu_speed` instructions per tick
+    pub fn run_for(&mut self, elapsed: Duration, cpu_speed: u32) -> Vec<DeviceMessage> {
+        let ticks = self.accumulate_ticks(elapsed);
+        self.advance_ticks(ticks, cpu_speed)
+    }
+
+    /// Run the device for a number of ticks, using `cpu_speed` instructions per tick
+    pub fn advance_ticks(&mut self, ticks: u32, cpu_speed: u32) -> Vec<DeviceMessage> {
+        let mut messages = Vec::new();
+
+        for _ in 0..ticks {
+            messages.extend(self.step(cpu_speed));
+        }
+
+        messages
     }
 
-    /// Step the device
+    /// Step the device by a single 60 Hz tick
+    ///
+    /// The timers are updated once at the start of the tick, followed by up to `cpu_speed`
+    /// instructions. With the COSMAC VIP timing, instructions run until the machine cycles of
+    /// the tick are used up and `cpu_speed` is ignored. The tick ends early if the program exits
+    /// or, with the display wait quirk enabled, after a sprite is drawn.
+    ///
+    /// The tick also ends early when the debugger stops at a breakpoint, a watchpoint or the end
+    /// of a step, which is reported with a `Break` message. Nothing runs while it is paused.
+    ///
+    /// Execution errors are handled according to the error policy. When an error halts the
+    /// device the tick ends with a `Halted` message, and the device does nothing until it is
+    /// reset or restored.
     pub fn step(&mut self, cpu_speed: u32) -> Vec<DeviceMessage> {
-        let mut messages: Vec<DeviceMessage> = Vec::new();
+        self.step_until(cpu_speed, None).0
+    }
 
-        if self.is_running {
-            // TODO: Move timers to CPU with events
+    /// Step the device by a single tick, which ends after `stop` instructions if it is set
+    ///
+    /// Waiting for a key counts as an instruction. Along with the messages, the number of
+    /// instructions which ran is returned if the debugger ended the tick early, so replaying
+    /// the tick can stop at the same point.
+    pub(crate) fn step_until(
+        &mut self,
+        cpu_speed: u32,
+        stop: Option<u32>,
+    ) -> (Vec<DeviceMessage>, Option<u32>) {
+        let mut messages: Vec<DeviceMessage> = Vec::new();
+        let mut stopped = None;
 
+        if self.is_running && self.execution_error.is_none() && !self.debugger.is_paused() {
             // Update timers
-            if self.cpu.delay_timer > 0 {
-                self.cpu.delay_timer = self.cpu.delay_timer.saturating_sub(1);
+            let sound_event = self.cpu.tick_timers();
+            let audio_enabled = self.audio_device.audio_settings().is_enabled();
+
+            match sound_event {
+                _ if self.replaying => {}
+                SoundEvent::Beep if audio_enabled => self.audio_device.play_beep(),
+                SoundEvent::PatternChanged if audio_enabled => self
+                    .audio_device
+                    .play_buffer(self.cpu.audio_buffer().clone(), self.cpu.buffer_pitch()),
+                // TODO: Make this more ergonomic (i.e. only pause if it's playing)
+                SoundEvent::Silence => self.audio_device.pause(),
+                _ => {}
             }
 
-            if self.cpu.sound_timer > 0 {
-                self.cpu.sound_timer = self.cpu.sound_timer.saturating_sub(1);
+            if let Some(recorder) = self.audio_recorder.as_mut() {
+                recorder.record_frame(
+                    sound_event,
+                    self.cpu.audio_buffer(),
+                    self.cpu.buffer_pitch(),
+                );
+            }
 
-                if self.audio_device.audio_settings().is_enabled() {
-                    if self.cpu.audio_buffer().is_empty() {
-                        self.audio_device.play_beep();
-                    } else if self.cpu.sound_dirty() {
-                        self.cpu.clear_sound_dirty();
-                        self.audio_device
-                            .play_buffer(self.cpu.audio_buffer().clone(), self.cpu.buffer_pitch());
-                    }
-                }
-            } else {
-                // TODO: Make this more ergonomic (i.e. only pause if it's playing)
-                self.cpu.clear_audio_buffer();
-                self.audio_device.pause();
+            if self.cpu_timing == CpuTiming::CosmacVip {
+                self.vip_cycles += VIP_INTERPRETER_CYCLES;
+            }
+
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.next_frame();
             }
 
             // Execute instructions
-            for _ in 0..cpu_speed {
-                let new_message = self.cpu.step(
+            let mut instructions = 0;
+            while self.has_cycles_left(instructions, cpu_speed)
+                && stop.is_none_or(|stop| instructions < stop)
+            {
+                instructions += 1;
+
+                // Note: Waiting for a key doesn't run an instruction
+                if !self.cpu.is_waiting_for_key() {
+                    if let Some(reason) = self.debugger.check_breakpoint(&self.cpu, &self.memory) {
+                        messages.push(DeviceMessage::Break(reason));
+                        stopped = Some(instructions - 1);
+                        break;
+                    }
+
+                    let trace_action = self.debugger.take_trace_action();
+                    if let Some(tracer) = self.tracer.as_mut() {
+                        tracer.record(&self.cpu, &self.memory, trace_action);
+                    }
+                }
+
+                // Note: Only record the state when needed, as this runs for every instruction
+                let debug_state = self.debugger.is_watching().then(|| {
+                    let instruction = if self.cpu.is_waiting_for_key() {
+                        None
+                    } else {
+                        self.cpu.next_instruction(&self.memory)
+                    };
+
+                    self.debugger
+                        .instruction_state(&self.cpu, &self.display, instruction)
+                });
+
+                let new_message = match self.cpu.step(
                     &mut self.memory,
                     &mut self.display,
                     &mut self.stack,
                     &self.quirks,
                     &self.keypad,
-                );
+                ) {
+                    Ok(message) => message,
+                    Err(error) => Some(self.handle_execution_error(error)),
+                };
+
+                // Collect messages that the device has
+                if let Some(message) = new_message {
+                    messages.push(message);
+                }
+
+                if self.execution_error.is_some() {
+                    break;
+                }
+
+                if self.cpu.take_flags_saved() {
+                    messages.extend(self.save_flags());
+                }
+
+                // Note: The cycle count is tiny, so the cast can't wrap
+                if self.cpu_timing == CpuTiming::CosmacVip {
+                    self.vip_cycles -= self.cpu.vip_cycles() as i32;
+                }
 
                 if self.cpu.is_requesting_exit() {
                     self.is_running = false;
                     self.reset_device();
+                    break;
                 }
 
-                // Collect messages that the device has
-                if let Some(message) = new_message {
-                    messages.push(message);
+                // Quirk: Drawing waits for the vertical blank, which is the start of the next frame
+                let v_blank_wait = self.cpu.take_v_blank_wait();
+                if v_blank_wait {
+                    self.vip_cycles = self.vip_cycles.min(0);
+                }
+
+                if let Some(state) = debug_state {
+                    if let Some(reason) =
+                        self.debugger
+                            .check_instruction(&state, &self.cpu, self.stack.len())
+                    {
+                        messages.push(DeviceMessage::Break(reason));
+                        stopped = Some(instructions);
+                        break;
+                    }
+                }
+
+                if v_blank_wait {
+                    break;
                 }
             }
         }
 
-        messages
+        (messages, stopped)
+    }
+
+    /// Step the device through recorded frames without effects outside of the device
+    ///
+    /// Each frame is the keypad state and CPU speed it was stepped with, and the number of
+    /// instructions after which the debugger stopped it. The tracer, audio recorder, flag
+    /// storage and debugger are detached while the frames run, and no sound is played. Errors
+    /// halt the device the same way they did when the frames were recorded.
+    pub(crate) fn replay(&mut self, frames: impl IntoIterator<Item = (Keypad, u32, Option<u32>)>) {
+        let tracer = self.tracer.take();
+        let audio_recorder = self.audio_recorder.take();
+        let flag_storage = self.flag_storage.take();
+        let debugger = std::mem::take(&mut self.debugger);
+        self.replaying = true;
+
+        for (keypad, cpu_speed, stop) in frames {
+            self.keypad = keypad;
+            self.step_until(cpu_speed, stop);
+        }
+
+        self.replaying = false;
+        self.tracer = tracer;
+        self.audio_recorder = audio_recorder;
+        self.flag_storage = flag_storage;
+        self.debugger = debugger;
+
+        // Start the audio pattern skipped while replaying on the next frame
+        self.cpu.restart_sound();
+    }
+
+    /// Check if another instruction can run in the current tick
+    fn has_cycles_left(&self, instructions: u32, cpu_speed: u32) -> bool {
+        match self.cpu_timing {
+            CpuTiming::Fixed => instructions < cpu_speed,
+            CpuTiming::CosmacVip => self.vip_cycles > 0,
+        }
+    }
+
+    /// Halt the device or turn the error into a message depending on the error policy
+    fn handle_execution_error(&mut self, error: ExecutionError) -> DeviceMessage {
+        match self.error_policy() {
+            ErrorPolicy::Report => DeviceMessage::ExecutionError(error),
+
+            // Note: The CPU wraps memory errors itself, so only stack errors reach this point
+            ErrorPolicy::Halt | ErrorPolicy::Wrap => {
+                self.execution_error = Some(error);
+                self.audio_device.pause();
+                DeviceMessage::Halted(error)
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{
+        debugger::{BreakReason, Breakpoint, MemoryAccess, WatchedRegister},
+        expression::{Expression, LogMessage},
+        flags::MemoryFlagStorage,
+        instruction::decode,
+        keypad::KeypadKey,
+        quirks::COMPATIBILITY_PROFILES,
+        timing::vip_instruction_cycles,
+    };
+
     use super::*;
 
     // Note: These tests were generated by GitHub Copilot
@@ -198,4 +691,461 @@ mod tests {
         assert_eq!(c8.cpu.delay_timer, 0);
         assert_eq!(c8.cpu.sound_timer, 0);
     }
+
+    #[test]
+    fn test_run_for() {
+        // delay := v0, with v0 := 0xFF, then loop forever
+        let rom = [0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.step(2);
+        assert_eq!(c8.cpu.delay_timer(), 0xFF);
+
+        // One second of time is 60 ticks, no matter how it is split up
+        for _ in 0..144 {
+            c8.run_for(Duration::from_secs_f64(1.0 / 144.0), 10);
+        }
+        assert!((0xFF - 61..=0xFF - 59).contains(&c8.cpu.delay_timer()));
+
+        // Fractions of a tick are kept
+        c8.load_rom(&rom);
+        assert_eq!(c8.accumulate_ticks(Duration::from_millis(10)), 0);
+        assert_eq!(c8.accumulate_ticks(Duration::from_millis(10)), 1);
+
+        // Long stalls don't cause a burst of ticks
+        assert_eq!(
+            c8.accumulate_ticks(Duration::from_secs(10)),
+            MAX_CATCH_UP_TICKS
+        );
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        // Fills v0 to v7 with random bytes
+        let rom = [
+            0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF, //
+            0xC4, 0xFF, 0xC5, 0xFF, 0xC6, 0xFF, 0xC7, 0xFF, //
+        ];
+
+        let run = |source: RandomSource| {
+            let mut c8 = C8::default();
+            c8.set_random_source(source);
+            c8.load_rom(&rom);
+            c8.step(8);
+            c8.cpu.registers().clone()
+        };
+
+        assert_eq!(run(RandomSource::Seeded(7)), run(RandomSource::Seeded(7)));
+        assert_ne!(run(RandomSource::Seeded(7)), run(RandomSource::Seeded(8)));
+        assert_eq!(
+            run(RandomSource::VipStyle(7)),
+            run(RandomSource::VipStyle(7))
+        );
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let mut c8 = C8::default();
+        c8.load_rom(&[0x00, 0xEE]);
+
+        let error = ExecutionError::StackUnderflow { pc: 0x200 };
+        assert_eq!(c8.step(1), vec![DeviceMessage::Halted(error)]);
+        assert_eq!(c8.execution_error(), Some(error));
+
+        // The device stays halted until it is reset
+        assert_eq!(c8.step(1), Vec::new());
+        assert_eq!(c8.cpu.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn test_halt_keeps_messages() {
+        // Unknown opcode, then return without a call
+        let mut c8 = C8::default();
+        c8.load_rom(&[0x00, 0x01, 0x00, 0xEE]);
+
+        let error = ExecutionError::StackUnderflow { pc: 0x202 };
+        assert_eq!(
+            c8.step(2),
+            vec![
+                DeviceMessage::UnknownOpCode(0x0001),
+                DeviceMessage::Halted(error)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // Recursive call to 0x200, jump back if the call is skipped
+        let rom = [0x22, 0x00, 0x12, 0x00];
+
+        let mut c8 = C8::default();
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[1]);
+        c8.set_cpu_timing(CpuTiming::Fixed);
+        c8.load_rom(&rom);
+
+        assert_eq!(
+            c8.step(13),
+            vec![DeviceMessage::Halted(ExecutionError::StackOverflow {
+                pc: 0x200
+            })]
+        );
+        assert_eq!(c8.stack().len(), 12);
+
+        // The depth is kept when the device is reset
+        c8.set_error_policy(ErrorPolicy::Report);
+        c8.load_rom(&rom);
+        let messages = c8.step(16);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(c8.stack().len(), c8.stack().depth());
+    }
+
+    #[test]
+    fn test_cosmac_vip_timing() {
+        // v0 += 1, jump 0x200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+
+        let mut c8 = C8::default();
+        c8.set_cpu_timing(CpuTiming::CosmacVip);
+        c8.load_rom(&rom);
+        c8.step(1);
+
+        // The CPU speed is ignored, the tick ends once its cycles are used up
+        let loop_cycles = vip_instruction_cycles(&decode(0x7001, 0), &[0; 16])
+            + vip_instruction_cycles(&decode(0x1200, 0), &[0; 16]);
+        let expected = VIP_INTERPRETER_CYCLES.unsigned_abs().div_ceil(loop_cycles);
+        assert!(c8.cpu.registers()[0].abs_diff(expected as u8) <= 1);
+        assert!(c8.vip_cycles <= 0);
+
+        // Leftover cycles are carried over to the next tick
+        let carried = c8.vip_cycles;
+        c8.step(1);
+        assert!(c8.vip_cycles <= 0 && c8.vip_cycles > carried - loop_cycles as i32);
+    }
+
+    #[test]
+    fn test_skip_long_instruction() {
+        // v0 := 1, if v0 != 1 then i := long 0x1234, v1 := 2
+        let rom = [0x60, 0x01, 0x30, 0x01, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x02];
+
+        // XO-Chip skips over the whole instruction
+        let mut c8 = C8::default();
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[3]);
+        c8.load_rom(&rom);
+        c8.step(3);
+        assert_eq!(c8.cpu.program_counter(), 0x20A);
+        assert_eq!(c8.cpu.registers()[1], 0x02);
+
+        // Other devices only skip the first half
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[2]);
+        c8.load_rom(&rom);
+        c8.step(2);
+        assert_eq!(c8.cpu.program_counter(), 0x206);
+
+        // The register bits of the long instruction are ignored
+        let mut rom = rom;
+        rom[4] = 0xF1;
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[3]);
+        c8.load_rom(&rom);
+        c8.step(3);
+        assert_eq!(c8.cpu.program_counter(), 0x20A);
+    }
+
+    #[test]
+    fn test_machine_call() {
+        // 0x0000, sys 0x123, 0x00E1
+        let rom = [0x00, 0x00, 0x01, 0x23, 0x00, 0xE1];
+
+        // Only the COSMAC VIP skips machine calls, other 0x00NN opcodes are still unknown
+        let mut c8 = C8::default();
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[1]);
+        c8.load_rom(&rom);
+        assert_eq!(c8.step(3), vec![DeviceMessage::UnknownOpCode(0x00E1)]);
+
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[0]);
+        c8.load_rom(&rom);
+        assert_eq!(c8.step(2), vec![DeviceMessage::UnknownOpCode(0x0123)]);
+    }
+
+    #[test]
+    fn test_display_wait() {
+        // v0 += 1, sprite v1 v1 1, jump 0x200
+        let rom = [0x70, 0x01, 0xD1, 0x11, 0x12, 0x00];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.step(30);
+        assert_eq!(c8.cpu.registers()[0], 10);
+
+        // Only a single sprite is drawn each frame
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[1]);
+        c8.load_rom(&rom);
+        for _ in 0..3 {
+            c8.step(30);
+        }
+        assert_eq!(c8.cpu.registers()[0], 3);
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        // v0 := 0xAB, i := long 0xFFFF, save v1
+        let rom = [0x60, 0xAB, 0xF0, 0x00, 0xFF, 0xFF, 0xF1, 0x55];
+        let error = ExecutionError::MemoryOutOfBounds {
+            addr: 0x10000,
+            opcode: 0xF155,
+        };
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        assert_eq!(c8.step(3), vec![DeviceMessage::Halted(error)]);
+
+        c8.set_error_policy(ErrorPolicy::Report);
+        c8.load_rom(&rom);
+        assert_eq!(c8.step(3), vec![DeviceMessage::ExecutionError(error)]);
+        assert_eq!(c8.memory.data[0xFFFF], 0x00);
+
+        c8.set_error_policy(ErrorPolicy::Wrap);
+        c8.load_rom(&rom);
+        assert_eq!(c8.step(3), Vec::new());
+        assert_eq!(c8.memory.data[0xFFFF], 0xAB);
+    }
+
+    #[test]
+    fn test_index_overflow() {
+        // i := long 0xFFFF, v0 := 1, i += v0
+        let rom = [0xF0, 0x00, 0xFF, 0xFF, 0x60, 0x01, 0xF0, 0x1E];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        assert_eq!(
+            c8.step(3),
+            vec![DeviceMessage::Halted(ExecutionError::IndexOverflow {
+                opcode: 0xF01E
+            })]
+        );
+
+        c8.set_error_policy(ErrorPolicy::Wrap);
+        c8.load_rom(&rom);
+        assert_eq!(c8.step(3), Vec::new());
+        assert_eq!(c8.cpu.index_register(), 0x0000);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        // v0 := 1, v0 += 1, jump 0x202
+        let rom = [0x60, 0x01, 0x70, 0x01, 0x12, 0x02];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.debugger_mut().add_breakpoint(0x202);
+
+        let reason = BreakReason::Breakpoint { pc: 0x202 };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[0], 1);
+
+        // Nothing runs while paused
+        assert_eq!(c8.step(10), Vec::new());
+        assert_eq!(c8.cpu.registers()[0], 1);
+
+        // Resuming runs the instruction at the breakpoint before stopping at it again
+        c8.debugger_mut().resume();
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[0], 2);
+        assert_eq!(c8.debugger().last_break(), Some(reason));
+    }
+
+    #[test]
+    fn test_breakpoint_waiting_for_key() {
+        // v0 := key, jump 0x200
+        let rom = [0xF0, 0x0A, 0x12, 0x00];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.debugger_mut().add_breakpoint(0x202);
+
+        // Waiting for a key doesn't reach the breakpoint after it
+        for _ in 0..3 {
+            let messages = c8.step(10);
+            assert!(!messages
+                .iter()
+                .any(|message| matches!(message, DeviceMessage::Break(_))));
+        }
+        assert_eq!(c8.debugger().hit_count(0x202), 0);
+
+        // Pressing and releasing a key ends the wait
+        let key = KeypadKey::Num5;
+        c8.keypad.set_key(&key, true);
+        c8.step(10);
+        c8.keypad.set_key(&key, false);
+
+        let reason = BreakReason::Breakpoint { pc: 0x202 };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[0], 5);
+        assert_eq!(c8.debugger().hit_count(0x202), 1);
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        // v0 += 1, jump 0x200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.debugger_mut().set_breakpoint(
+            0x202,
+            Breakpoint {
+                condition: Some(Expression::parse("v0 >= 3").unwrap()),
+                hit_target: 2,
+                ..Breakpoint::default()
+            },
+        );
+        c8.debugger_mut().set_breakpoint(
+            0x200,
+            Breakpoint {
+                log_message: Some(LogMessage::parse("v0 = {v0}").unwrap()),
+                ..Breakpoint::default()
+            },
+        );
+
+        // The condition is first true for v0 == 3, and the second hit stops the device
+        let reason = BreakReason::Breakpoint { pc: 0x202 };
+        assert_eq!(c8.step(20), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[0], 4);
+        assert_eq!(c8.debugger().hit_count(0x202), 2);
+
+        // Logpoints don't stop the device
+        assert_eq!(
+            c8.debugger_mut().take_logs(),
+            ["v0 = 0", "v0 = 1", "v0 = 2", "v0 = 3"]
+        );
+        assert!(c8.debugger_mut().take_logs().is_empty());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // i := 0x300, v0 := 123, bcd v0, v3 := 1, jump 0x206
+        let rom = [0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0x63, 0x01, 0x12, 0x06];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.debugger_mut()
+            .watch_memory(0x302..0x310, MemoryAccess::Write);
+        c8.debugger_mut().watch_register(WatchedRegister::V(3));
+
+        let reason = BreakReason::MemoryWrite {
+            pc: 0x204,
+            address: 0x302,
+        };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.memory.data[0x302], 3);
+
+        c8.debugger_mut().resume();
+        let reason = BreakReason::RegisterChanged {
+            pc: 0x206,
+            register: WatchedRegister::V(3),
+            old: 0,
+            new: 1,
+        };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+
+        // Reads don't trigger write watchpoints
+        c8.load_rom(&[0xA3, 0x02, 0xF0, 0x65, 0x12, 0x04]);
+        assert_eq!(c8.step(10), Vec::new());
+    }
+
+    #[test]
+    fn test_stepping() {
+        // call 0x206, v0 := 1, jump 0x204, v1 := 2, v2 := 3, return
+        let rom = [
+            0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x62, 0x03, 0x00, 0xEE,
+        ];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.debugger_mut().pause();
+
+        // The call runs to completion
+        c8.step_over();
+        let reason = BreakReason::StepComplete { pc: 0x202 };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[2], 3);
+
+        c8.load_rom(&rom);
+        c8.debugger_mut().pause();
+
+        c8.step_into();
+        let reason = BreakReason::StepComplete { pc: 0x206 };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+
+        c8.step_over();
+        let reason = BreakReason::StepComplete { pc: 0x208 };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[1], 2);
+
+        c8.step_out();
+        let reason = BreakReason::StepComplete { pc: 0x202 };
+        assert_eq!(c8.step(10), vec![DeviceMessage::Break(reason)]);
+        assert_eq!(c8.cpu.registers()[0], 0);
+    }
+
+    #[test]
+    fn test_flag_storage() {
+        // v0 := 1 to vF := 16, saveflags vF, then loop forever
+        let mut rom: Vec<u8> = (0..16).flat_map(|x| [0x60 | x, x + 1]).collect();
+        rom.extend([0xFF, 0x75, 0x12, 0x22]);
+
+        let mut c8 = C8::default();
+        c8.set_flag_storage(Some(Box::new(MemoryFlagStorage::default())));
+        c8.load_rom(&rom);
+        c8.step(17);
+
+        let saved: Vec<u8> = (1..=16).collect();
+        let key = rom_sha1(&rom);
+        assert_eq!(c8.flag_storage().unwrap().load_flags(&key), Some(saved));
+
+        // loadflags vF, then loop forever
+        let load_rom = [0xFF, 0x85, 0x12, 0x02];
+        let mut storage = MemoryFlagStorage::default();
+        storage.save_flags(&rom_sha1(&load_rom), &[7; 16]).unwrap();
+        c8.set_flag_storage(Some(Box::new(storage)));
+
+        // The flags are kept after a reset and loaded with the ROM
+        c8.reset_device();
+        c8.load_rom(&load_rom);
+        c8.step(1);
+        assert_eq!(c8.cpu.registers()[..], [7; 16]);
+
+        // The Super-Chip only has 8 flags
+        c8.set_compatibility_profile(&COMPATIBILITY_PROFILES[2]);
+        c8.set_flag_storage(Some(Box::new(MemoryFlagStorage::default())));
+        c8.load_rom(&rom);
+        c8.step(17);
+
+        let mut saved = vec![0; 16];
+        saved[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(c8.flag_storage().unwrap().load_flags(&key), Some(saved));
+
+        // A failed write is reported
+        c8.set_flag_storage(Some(Box::new(ReadOnlyFlagStorage)));
+        c8.load_rom(&rom);
+        assert_eq!(
+            c8.step(17),
+            vec![DeviceMessage::FlagsNotSaved(
+                std::io::ErrorKind::PermissionDenied
+            )]
+        );
+    }
+
+    #[derive(Debug)]
+    struct ReadOnlyFlagStorage;
+
+    impl FlagStorage for ReadOnlyFlagStorage {
+        fn load_flags(&self, _key: &str) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn save_flags(&mut self, _key: &str, _flags: &[u8]) -> std::io::Result<()> {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        }
+    }
 }
@@ -0,0 +1,507 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    ops::Range,
+};
+
+use crate::{
+    instruction::{decode, Instruction},
+    memory::Memory,
+    PROGRAM_START,
+};
+
+/// Maximum number of data bytes shown on a single line
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// Syntax used to write disassembled instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// Classic mnemonics, i.e. `LD V0, 0x05`
+    #[default]
+    Classic,
+
+    /// Octo `.8o` syntax, i.e. `v0 := 5`
+    Octo,
+}
+
+/// A single line of a disassembly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    /// Address of the first byte
+    pub address: u16,
+
+    /// Raw bytes of the line
+    pub bytes: Vec<u8>,
+
+    /// The decoded instruction, or None if the bytes are data
+    pub instruction: Option<Instruction>,
+}
+
+impl Line {
+    /// Check if an address is part of the line
+    #[must_use]
+    pub fn contains(&self, address: u16) -> bool {
+        let start = usize::from(self.address);
+        (start..start + self.bytes.len()).contains(&usize::from(address))
+    }
+}
+
+/// A disassembled program
+#[derive(Debug, Clone, Default)]
+pub struct Disassembly {
+    /// Lines of the program, in address order
+    lines: Vec<Line>,
+
+    /// Labels of jump and call targets
+    labels: BTreeMap<u16, String>,
+}
+
+/// Get the text of a single instruction, addresses are written as numbers
+#[must_use]
+pub fn instruction_text(instruction: Instruction, syntax: Syntax) -> String {
+    Disassembly::default().instruction_text(instruction, syntax)
+}
+
+/// Disassemble a ROM loaded at `PROGRAM_START`
+#[must_use]
+pub fn disassemble_rom(rom: &[u8]) -> Disassembly {
+    disassemble(rom, PROGRAM_START, &[PROGRAM_START])
+}
+
+/// Disassemble a range of device memory
+///
+/// Control flow is traced from `PROGRAM_START` if it is in the range, otherwise from the start
+/// of the range.
+#[must_use]
+pub fn disassemble_memory(memory: &Memory, range: Range<usize>) -> Disassembly {
+    let data = memory.data();
+    let end = range.end.min(data.len()).min(usize::from(u16::MAX) + 1);
+    let start = range.start.min(end);
+
+    let entry = if (start..end).contains(&usize::from(PROGRAM_START)) {
+        PROGRAM_START
+    } else {
+        start as u16
+    };
+
+    disassemble(&data[start..end], start as u16, &[entry])
+}
+
+/// Disassemble bytes loaded at `origin`
+///
+/// Control flow is traced from the entry points to tell code from data, bytes which aren't reached
+/// are shown as data.
+#[must_use]
+pub fn disassemble(data: &[u8], origin: u16, entry_points: &[u16]) -> Disassembly {
+    let end = usize::from(origin) + data.len();
+    let word_at = |address: usize| -> Option<u16> {
+        if address < usize::from(origin) || address + 2 > end {
+            return None;
+        }
+
+        let offset = address - usize::from(origin);
+        Some(u16::from_be_bytes([data[offset], data[offset + 1]]))
+    };
+    let instruction_at = |address: usize| -> Option<Instruction> {
+        let instruction = decode(word_at(address)?, word_at(address + 2).unwrap_or_default());
+        let fits = address + usize::from(instruction.size()) <= end;
+        let known = !matches!(
+            instruction,
+            Instruction::MachineCall(_) | Instruction::Unknown(_)
+        );
+
+        (fits && known).then_some(instruction)
+    };
+
+    let mut code = BTreeMap::new();
+    let mut jump_targets = BTreeSet::new();
+    let mut call_targets = BTreeSet::new();
+    let mut pending: Vec<usize> = entry_points
+        .iter()
+        .map(|&entry| usize::from(entry))
+        .collect();
+
+    while let Some(address) = pending.pop() {
+        if code.contains_key(&address) {
+            continue;
+        }
+
+        let Some(instruction) = instruction_at(address) else {
+            continue;
+        };
+
+        code.insert(address, instruction);
+        let next = address + usize::from(instruction.size());
+
+        match instruction {
+            Instruction::Jump(target) => {
+                jump_targets.insert(target);
+                pending.push(usize::from(target));
+            }
+            Instruction::Call(target) => {
+                call_targets.insert(target);
+                pending.push(usize::from(target));
+                pending.push(next);
+            }
+            // The target of a computed jump isn't known
+            Instruction::Return | Instruction::Exit | Instruction::JumpOffset(_) => {}
+            _ if instruction.is_skip() => {
+                pending.push(next);
+
+                if let Some(skipped) = instruction_at(next) {
+                    pending.push(next + usize::from(skipped.size()));
+                }
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // Only targets in the disassembled code get labels, so the listing defines every label it uses
+    let mut labels = BTreeMap::new();
+    let is_code = |target: &&u16| code.contains_key(&usize::from(**target));
+    for &target in jump_targets.iter().filter(is_code) {
+        labels.insert(target, format!("label_{target:03X}"));
+    }
+    for &target in call_targets.iter().filter(is_code) {
+        labels.insert(target, format!("sub_{target:03X}"));
+    }
+    if code.contains_key(&usize::from(PROGRAM_START)) {
+        labels.insert(PROGRAM_START, "main".to_string());
+    }
+
+    let mut lines = Vec::new();
+    let mut address = usize::from(origin);
+
+    while address < end {
+        let offset = address - usize::from(origin);
+
+        // Instructions which overlap another traced instruction are shown as data
+        let instruction = code.get(&address).filter(|instruction| {
+            let size = usize::from(instruction.size());
+            code.range(address + 1..address + size).next().is_none()
+        });
+
+        if let Some(&instruction) = instruction {
+            let size = usize::from(instruction.size());
+            lines.push(Line {
+                address: address as u16,
+                bytes: data[offset..offset + size].to_vec(),
+                instruction: Some(instruction),
+            });
+            address += size;
+            continue;
+        }
+
+        // Data runs until the next instruction or label
+        let mut length = 1;
+        while length < DATA_BYTES_PER_LINE && address + length < end {
+            let next = address + length;
+            if code.contains_key(&next) || labels.contains_key(&(next as u16)) {
+                break;
+            }
+            length += 1;
+        }
+
+        lines.push(Line {
+            address: address as u16,
+            bytes: data[offset..offset + length].to_vec(),
+            instruction: None,
+        });
+        address += length;
+    }
+
+    Disassembly { lines, labels }
+}
+
+impl Disassembly {
+    /// Get the lines of the program
+    #[must_use]
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Get the labels of jump and call targets
+    #[must_use]
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.labels
+    }
+
+    /// Get the label at an address
+    #[must_use]
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Get the index of the line containing an address
+    #[must_use]
+    pub fn line_index(&self, address: u16) -> Option<usize> {
+        let index = self
+            .lines
+            .partition_point(|line| line.address <= address)
+            .checked_sub(1)?;
+
+        self.lines[index].contains(address).then_some(index)
+    }
+
+    /// Get the mnemonic and operands of a line
+    #[must_use]
+    pub fn text(&self, line: &Line, syntax: Syntax) -> String {
+        match line.instruction {
+            Some(instruction) => self.instruction_text(instruction, syntax),
+            None => data_text(&line.bytes, syntax),
+        }
+    }
+
+    /// Get the annotated listing of the program, with the address and raw bytes of each line
+    #[must_use]
+    pub fn listing(&self, syntax: Syntax) -> String {
+        let mut listing = String::new();
+
+        for line in &self.lines {
+            let bytes: String = line
+                .bytes
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect();
+            let text = self.text(line, syntax);
+
+            // Writing to a string can't fail
+            let _ = match (syntax, self.label(line.address)) {
+                (Syntax::Classic, Some(label)) => writeln!(listing, "{label}:"),
+                (Syntax::Octo, Some(label)) => writeln!(listing, ": {label}"),
+                (_, None) => Ok(()),
+            };
+            let _ = match syntax {
+                Syntax::Classic => writeln!(listing, "{:#06X}  {bytes:<16}  {text}", line.address),
+                Syntax::Octo => writeln!(listing, "\t{text:<32} # {:#06X}  {bytes}", line.address),
+            };
+        }
+
+        listing
+    }
+
+    /// Get an address operand, using its label if there is one
+    fn address_text(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("{address:#05X}"),
+        }
+    }
+
+    // Intentionally allowing too many lines
+    #[allow(clippy::too_many_lines)]
+    fn instruction_text(&self, instruction: Instruction, syntax: Syntax) -> String {
+        if syntax == Syntax::Classic {
+            return self.classic_text(instruction);
+        }
+
+        match instruction {
+            Instruction::MachineCall(nnn) | Instruction::Unknown(nnn) => {
+                data_text(&nnn.to_be_bytes(), syntax)
+            }
+            Instruction::ScrollDown(n) => format!("scroll-down {n}"),
+            Instruction::ScrollUp(n) => format!("scroll-up {n}"),
+            Instruction::ClearScreen => "clear".to_string(),
+            Instruction::Return => "return".to_string(),
+            Instruction::ScrollRight => "scroll-right".to_string(),
+            Instruction::ScrollLeft => "scroll-left".to_string(),
+            Instruction::Exit => "exit".to_string(),
+            Instruction::LowResolution => "lores".to_string(),
+            Instruction::HighResolution => "hires".to_string(),
+            Instruction::Jump(nnn) => format!("jump {}", self.address_text(nnn)),
+            Instruction::Call(nnn) => match self.label(nnn) {
+                Some(label) => label.to_string(),
+                None => format!(":call {nnn:#05X}"),
+            },
+            // Octo writes skips as the condition for running the next instruction
+            Instruction::SkipIfEqual { x, value } => format!("if v{x:x} != {value} then"),
+            Instruction::SkipIfNotEqual { x, value } => format!("if v{x:x} == {value} then"),
+            Instruction::SkipIfRegistersEqual { x, y } => format!("if v{x:x} != v{y:x} then"),
+            Instruction::SkipIfRegistersNotEqual { x, y } => {
+                format!("if v{x:x} == v{y:x} then")
+            }
+            Instruction::SkipIfKeyPressed { x } => format!("if v{x:x} -key then"),
+            Instruction::SkipIfKeyNotPressed { x } => format!("if v{x:x} key then"),
+            Instruction::SaveRange { x, y } => format!("save v{x:x} - v{y:x}"),
+            Instruction::LoadRange { x, y } => format!("load v{x:x} - v{y:x}"),
+            Instruction::SetRegister { x, value } => format!("v{x:x} := {value}"),
+            Instruction::AddValue { x, value } => format!("v{x:x} += {value}"),
+            Instruction::Copy { x, y } => format!("v{x:x} := v{y:x}"),
+            Instruction::Or { x, y } => format!("v{x:x} |= v{y:x}"),
+            Instruction::And { x, y } => format!("v{x:x} &= v{y:x}"),
+            Instruction::Xor { x, y } => format!("v{x:x} ^= v{y:x}"),
+            Instruction::Add { x, y } => format!("v{x:x} += v{y:x}"),
+            Instruction::Subtract { x, y } => format!("v{x:x} -= v{y:x}"),
+            Instruction::ShiftRight { x, y } => format!("v{x:x} >>= v{y:x}"),
+            Instruction::SubtractReverse { x, y } => format!("v{x:x} =- v{y:x}"),
+            Instruction::ShiftLeft { x, y } => format!("v{x:x} <<= v{y:x}"),
+            Instruction::SetIndex(nnn) => format!("i := {}", self.address_text(nnn)),
+            Instruction::JumpOffset(nnn) => format!("jump0 {}", self.address_text(nnn)),
+            Instruction::Random { x, mask } => format!("v{x:x} := random {mask:#04X}"),
+            Instruction::Draw { x, y, height } => format!("sprite v{x:x} v{y:x} {height}"),
+            Instruction::SetIndexLong(nnnn) => format!("i := long {nnnn:#06X}"),
+            Instruction::SetPlanes(n) => format!("plane {n}"),
+            Instruction::LoadAudioPattern => "audio".to_string(),
+            Instruction::GetDelayTimer { x } => format!("v{x:x} := delay"),
+            Instruction::WaitForKey { x } => format!("v{x:x} := key"),
+            Instruction::SetDelayTimer { x } => format!("delay := v{x:x}"),
+            Instruction::SetSoundTimer { x } => format!("buzzer := v{x:x}"),
+            Instruction::AddIndex { x } => format!("i += v{x:x}"),
+            Instruction::SmallFont { x } => format!("i := hex v{x:x}"),
+            Instruction::LargeFont { x } => format!("i := bighex v{x:x}"),
+            Instruction::BinaryCodedDecimal { x } => format!("bcd v{x:x}"),
+            Instruction::SetPitch { x } => format!("pitch := v{x:x}"),
+            Instruction::Store { x } => format!("save v{x:x}"),
+            Instruction::Load { x } => format!("load v{x:x}"),
+            Instruction::SaveFlags { x } => format!("saveflags v{x:x}"),
+            Instruction::LoadFlags { x } => format!("loadflags v{x:x}"),
+        }
+    }
+
+    fn classic_text(&self, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::MachineCall(nnn) => format!("SYS {nnn:#05X}"),
+            Instruction::Unknown(opcode) => data_text(&opcode.to_be_bytes(), Syntax::Classic),
+            Instruction::ScrollDown(n) => format!("SCD {n}"),
+            Instruction::ScrollUp(n) => format!("SCU {n}"),
+            Instruction::ClearScreen => "CLS".to_string(),
+            Instruction::Return => "RET".to_string(),
+            Instruction::ScrollRight => "SCR".to_string(),
+            Instruction::ScrollLeft => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::LowResolution => "LOW".to_string(),
+            Instruction::HighResolution => "HIGH".to_string(),
+            Instruction::Jump(nnn) => format!("JP {}", self.address_text(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", self.address_text(nnn)),
+            Instruction::SkipIfEqual { x, value } => format!("SE V{x:X}, {value:#04X}"),
+            Instruction::SkipIfNotEqual { x, value } => format!("SNE V{x:X}, {value:#04X}"),
+            Instruction::SkipIfRegistersEqual { x, y } => format!("SE V{x:X}, V{y:X}"),
+            Instruction::SkipIfRegistersNotEqual { x, y } => format!("SNE V{x:X}, V{y:X}"),
+            Instruction::SkipIfKeyPressed { x } => format!("SKP V{x:X}"),
+            Instruction::SkipIfKeyNotPressed { x } => format!("SKNP V{x:X}"),
+            Instruction::SaveRange { x, y } => format!("SAVE V{x:X} - V{y:X}"),
+            Instruction::LoadRange { x, y } => format!("LOAD V{x:X} - V{y:X}"),
+            Instruction::SetRegister { x, value } => format!("LD V{x:X}, {value:#04X}"),
+            Instruction::AddValue { x, value } => format!("ADD V{x:X}, {value:#04X}"),
+            Instruction::Copy { x, y } => format!("LD V{x:X}, V{y:X}"),
+            Instruction::Or { x, y } => format!("OR V{x:X}, V{y:X}"),
+            Instruction::And { x, y } => format!("AND V{x:X}, V{y:X}"),
+            Instruction::Xor { x, y } => format!("XOR V{x:X}, V{y:X}"),
+            Instruction::Add { x, y } => format!("ADD V{x:X}, V{y:X}"),
+            Instruction::Subtract { x, y } => format!("SUB V{x:X}, V{y:X}"),
+            Instruction::ShiftRight { x, y } => format!("SHR V{x:X}, V{y:X}"),
+            Instruction::SubtractReverse { x, y } => format!("SUBN V{x:X}, V{y:X}"),
+            Instruction::ShiftLeft { x, y } => format!("SHL V{x:X}, V{y:X}"),
+            Instruction::SetIndex(nnn) => format!("LD I, {}", self.address_text(nnn)),
+            Instruction::JumpOffset(nnn) => format!("JP V0, {}", self.address_text(nnn)),
+            Instruction::Random { x, mask } => format!("RND V{x:X}, {mask:#04X}"),
+            Instruction::Draw { x, y, height } => format!("DRW V{x:X}, V{y:X}, {height}"),
+            Instruction::SetIndexLong(nnnn) => format!("LD I, LONG {nnnn:#06X}"),
+            Instruction::SetPlanes(n) => format!("PLANE {n}"),
+            Instruction::LoadAudioPattern => "AUDIO".to_string(),
+            Instruction::GetDelayTimer { x } => format!("LD V{x:X}, DT"),
+            Instruction::WaitForKey { x } => format!("LD V{x:X}, K"),
+            Instruction::SetDelayTimer { x } => format!("LD DT, V{x:X}"),
+            Instruction::SetSoundTimer { x } => format!("LD ST, V{x:X}"),
+            Instruction::AddIndex { x } => format!("ADD I, V{x:X}"),
+            Instruction::SmallFont { x } => format!("LD F, V{x:X}"),
+            Instruction::LargeFont { x } => format!("LD HF, V{x:X}"),
+            Instruction::BinaryCodedDecimal { x } => format!("LD B, V{x:X}"),
+            Instruction::SetPitch { x } => format!("PITCH V{x:X}"),
+            Instruction::Store { x } => format!("LD [I], V{x:X}"),
+            Instruction::Load { x } => format!("LD V{x:X}, [I]"),
+            Instruction::SaveFlags { x } => format!("LD R, V{x:X}"),
+            Instruction::LoadFlags { x } => format!("LD V{x:X}, R"),
+        }
+    }
+}
+
+/// Get the text of data bytes
+fn data_text(bytes: &[u8], syntax: Syntax) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{byte:#04X}")).collect();
+
+    match syntax {
+        Syntax::Classic => format!("DB {}", bytes.join(", ")),
+        Syntax::Octo => bytes.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A loop drawing a sprite, with a subroutine and the sprite data after the code
+    const ROM: &[u8] = &[
+        0x00, 0xE0, // 0x200 clear
+        0x22, 0x0A, // 0x202 call 0x20A
+        0x30, 0x01, // 0x204 skip if v0 == 1
+        0x12, 0x02, // 0x206 jump 0x202
+        0x00, 0xFD, // 0x208 exit
+        0xA2, 0x12, // 0x20A i := 0x212
+        0xF0, 0x00, 0x02, 0x12, // 0x20C i := long 0x212
+        0xD0, 0x11, // 0x210 sprite v0 v1 1
+        0x00, 0xEE, // 0x212 return, also used as sprite data
+        0xFF, 0x81, // 0x214 data
+    ];
+
+    #[test]
+    fn test_trace() {
+        let disassembly = disassemble_rom(ROM);
+        let lines = disassembly.lines();
+
+        let code: Vec<u16> = lines
+            .iter()
+            .filter(|line| line.instruction.is_some())
+            .map(|line| line.address)
+            .collect();
+        assert_eq!(
+            code,
+            [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C, 0x210, 0x212]
+        );
+
+        let data = lines.last().unwrap();
+        assert_eq!(data.address, 0x214);
+        assert_eq!(data.bytes, [0xFF, 0x81]);
+        assert_eq!(data.instruction, None);
+
+        assert_eq!(disassembly.label(0x200), Some("main"));
+        assert_eq!(disassembly.label(0x202), Some("label_202"));
+        assert_eq!(disassembly.label(0x20A), Some("sub_20A"));
+        assert_eq!(disassembly.line_index(0x20E), Some(6));
+    }
+
+    #[test]
+    fn test_syntax() {
+        let disassembly = disassemble_rom(ROM);
+        let text = |index: usize, syntax| disassembly.text(&disassembly.lines()[index], syntax);
+
+        assert_eq!(text(1, Syntax::Classic), "CALL sub_20A");
+        assert_eq!(text(1, Syntax::Octo), "sub_20A");
+        assert_eq!(text(2, Syntax::Classic), "SE V0, 0x01");
+        assert_eq!(text(2, Syntax::Octo), "if v0 != 1 then");
+        assert_eq!(text(6, Syntax::Octo), "i := long 0x0212");
+        assert_eq!(text(7, Syntax::Octo), "sprite v0 v1 1");
+        assert_eq!(text(9, Syntax::Octo), "0xFF 0x81");
+
+        let listing = disassembly.listing(Syntax::Octo);
+        assert!(listing.starts_with(": main\n\tclear"));
+        assert!(listing.contains(": sub_20A\n\ti := 0x212"));
+    }
+
+    #[test]
+    fn test_bundled_rom() {
+        let rom = include_bytes!("../../../assets/games/NYAN.xo8");
+        let disassembly = disassemble_rom(rom);
+
+        let size: usize = disassembly
+            .lines()
+            .iter()
+            .map(|line| line.bytes.len())
+            .sum();
+        assert_eq!(size, rom.len());
+        assert!(disassembly
+            .lines()
+            .iter()
+            .any(|line| line.instruction.is_some()));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::snapshot::DisplaySnapshot;
+
 /// Screen width constant
 pub(crate) const DEFAULT_SCREEN_WIDTH: usize = 64;
 
@@ -17,12 +19,16 @@ pub(crate) const SCREEN_SIZE_LOW_XY: (usize, usize) = (DEFAULT_SCREEN_WIDTH, DEF
 pub(crate) const SCREEN_SIZE_HIGH_XY: (usize, usize) =
     (DEFAULT_SCREEN_WIDTH * 2, DEFAULT_SCREEN_HEIGHT * 2);
 
+/// Characters of the pixel values in text output, with one bit for each plane
+const TEXT_PIXELS: [char; 4] = ['.', '#', '+', '*'];
+
 // Note: There are two planes, plane 0 and 1; drawing to plane 2 (i.e. plane 3) draws to both planes.
 // When retrieving pixels, plane 1 is superimposed on plane 0, allowing for more colors. Plane 1 is
 // used for regular monochrome displays (default chip-8 behavior).
 
 /// Display resolution
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum DisplayResolution {
     Low,
@@ -102,6 +108,24 @@ impl Display {
         self.plane_pixels(0).iter().zip(self.plane_pixels(1).iter())
     }
 
+    /// Format the display as text, one line for each row of pixels
+    ///
+    /// Unlit pixels are `.`, pixels of the first plane `#`, of the second plane `+` and of
+    /// both planes `*`.
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let (width, _) = self.resolution().resolution_size_xy();
+        let pixels: Vec<char> = self
+            .zipped_iterator()
+            .map(|(&p0, &p1)| TEXT_PIXELS[usize::from(p0 | (p1 << 1))])
+            .collect();
+
+        pixels
+            .chunks(width)
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
     /// Get the display resolution
     #[must_use] 
     pub fn resolution(&self) -> DisplayResolution {
@@ -177,15 +201,24 @@ impl Display {
         self.active_plane
     }
 
+    /// Get the indices of the planes selected by the active plane bitmask
+    pub(crate) fn active_planes(&self) -> impl Iterator<Item = usize> {
+        let active_plane = self.active_plane;
+        (0..self.plane_count()).filter(move |layer| active_plane & (layer + 1) != 0)
+    }
+
+    /// Clear the planes selected by the active plane bitmask
+    pub(crate) fn clear_active(&mut self) {
+        for layer in self.active_planes() {
+            self.clear(layer);
+        }
+    }
+
     /// Scroll planes left by the given number of pixels
     pub(crate) fn scroll_left(&mut self, pixels: u8) {
         let row_size = self.screen_size_xy().0;
 
-        for layer in 0..self.plane_count() {
-            if self.active_plane() & (layer + 1) == 0 {
-                continue;
-            }
-
+        for layer in self.active_planes() {
             for a in (0..self.planes[layer].pixels.len()).step_by(row_size) {
                 for b in 0..row_size {
                     let index = a + b;
@@ -203,11 +236,7 @@ impl Display {
     pub(crate) fn scroll_right(&mut self, pixels: u8) {
         let row_size = self.screen_size_xy().0;
 
-        for layer in 0..self.plane_count() {
-            if self.active_plane() & (layer + 1) == 0 {
-                continue;
-            }
-
+        for layer in self.active_planes() {
             for a in (0..self.planes[layer].pixels.len()).step_by(row_size) {
                 for b in (0..row_size).rev() {
                     let index = a + b;
@@ -226,11 +255,7 @@ impl Display {
         let size = self.screen_size_xy().0 * pixels as usize;
         let buffer_size = self.screen_size();
 
-        for layer in 0..self.plane_count() {
-            if self.active_plane() & (layer + 1) == 0 {
-                continue;
-            }
-
+        for layer in self.active_planes() {
             self.planes[layer].pixels = self.planes[layer]
                 .pixels
                 .iter()
@@ -250,11 +275,7 @@ impl Display {
     pub(crate) fn scroll_down(&mut self, pixels: u8) {
         let size = self.screen_size_xy().0 * pixels as usize;
 
-        for layer in 0..self.plane_count() {
-            if self.active_plane() & (layer + 1) == 0 {
-                continue;
-            }
-
+        for layer in self.active_planes() {
             self.planes[layer].pixels = self.planes[layer]
                 .pixels
                 .iter()
@@ -270,12 +291,38 @@ impl Display {
         }
     }
 
+    /// Capture the display state
+    pub(crate) fn snapshot(&self) -> DisplaySnapshot {
+        DisplaySnapshot {
+            planes: self
+                .planes
+                .iter()
+                .map(|plane| plane.pixels.clone())
+                .collect(),
+            resolution: self.resolution,
+            active_plane: self.active_plane,
+        }
+    }
+
+    /// Restore the display state from a validated snapshot
+    pub(crate) fn restore(&mut self, snapshot: &DisplaySnapshot) {
+        self.planes = snapshot
+            .planes
+            .iter()
+            .map(|pixels| Plane {
+                pixels: pixels.clone(),
+            })
+            .collect();
+        self.resolution = snapshot.resolution;
+        self.active_plane = snapshot.active_plane & 0x3;
+    }
+
     #[inline]
     const fn pixel_index(&self, x: usize, y: usize) -> usize {
-        let (width, _) = self.screen_size_xy();
+        let (width, height) = self.screen_size_xy();
 
-        //let x = x % width;
-        //let y = y % height;
+        let x = x % width;
+        let y = y % height;
 
         // Get the pixel index
         y * width + x
@@ -324,6 +371,23 @@ mod tests {
         assert_eq!(display.planes[active_plane].pixels, vec![0; screen_size]);
     }
 
+    #[test]
+    fn test_display_to_text() {
+        let mut display = Display::default();
+        display.set_plane_pixel(0, 0, 0);
+        display.set_plane_pixel(1, 1, 0);
+        display.set_plane_pixel(0, 2, 0);
+        display.set_plane_pixel(1, 2, 0);
+
+        let text = display.to_text();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), DEFAULT_SCREEN_HEIGHT);
+        assert_eq!(lines[0].len(), DEFAULT_SCREEN_WIDTH);
+        assert_eq!(&lines[0][..4], "#+*.");
+        assert_eq!(&lines[1][..4], "....");
+    }
+
     #[test]
     fn test_display_set_pixel() {
         let mut display = Display::default();
@@ -0,0 +1,88 @@
+/// Errors which can occur while executing instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecutionError {
+    /// Returned from a subroutine while the stack was empty
+    StackUnderflow {
+        /// Address of the return instruction
+        pc: u16,
+    },
+
+    /// Called a subroutine while the stack was full
+    StackOverflow {
+        /// Address of the call instruction
+        pc: u16,
+    },
+
+    /// An instruction accessed memory outside of the device memory
+    MemoryOutOfBounds {
+        /// First address outside of the device memory
+        addr: usize,
+
+        /// The instruction which accessed the memory
+        opcode: u16,
+    },
+
+    /// The next instruction is outside of the device memory
+    FetchOutOfBounds {
+        /// Program counter of the instruction
+        pc: u16,
+    },
+
+    /// The index register overflowed
+    IndexOverflow {
+        /// The instruction which modified the index register
+        opcode: u16,
+    },
+}
+
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionError::StackUnderflow { pc } => write!(f, "Stack underflow at {pc:#06X}"),
+            ExecutionError::StackOverflow { pc } => write!(f, "Stack overflow at {pc:#06X}"),
+            ExecutionError::MemoryOutOfBounds { addr, opcode } => write!(
+                f,
+                "Memory access out of bounds at {addr:#06X} by opcode {opcode:#06X}"
+            ),
+            ExecutionError::FetchOutOfBounds { pc } => {
+                write!(f, "Instruction fetch out of bounds at {pc:#06X}")
+            }
+            ExecutionError::IndexOverflow { opcode } => {
+                write!(f, "Index register overflow by opcode {opcode:#06X}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+/// How the device handles execution errors
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorPolicy {
+    /// Stop the device and return the error
+    #[default]
+    Halt,
+
+    /// Wrap memory addresses and the index register around, stack errors still halt the device
+    Wrap,
+
+    /// Skip the faulting instruction and report the error as a device message
+    Report,
+}
+
+impl ErrorPolicy {
+    /// Get the locale string key of the policy
+    #[must_use]
+    pub fn name_key(&self) -> &str {
+        match self {
+            ErrorPolicy::Halt => "error_policy_halt",
+            ErrorPolicy::Wrap => "error_policy_wrap",
+            ErrorPolicy::Report => "error_policy_report",
+        }
+    }
+}
+
+/// List of error policies for iteration
+pub const ERROR_POLICIES: [ErrorPolicy; 3] =
+    [ErrorPolicy::Halt, ErrorPolicy::Wrap, ErrorPolicy::Report];
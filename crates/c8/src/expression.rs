@@ -0,0 +1,696 @@
+use crate::{cpu::CPU, memory::Memory};
+
+/// Errors which can occur while parsing an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionError {
+    /// A character which isn't part of the language
+    UnexpectedCharacter {
+        /// Byte offset of the character
+        position: usize,
+
+        /// The character
+        character: char,
+    },
+
+    /// A number which can't be parsed
+    InvalidNumber {
+        /// Byte offset of the number
+        position: usize,
+    },
+
+    /// A name which isn't a register or timer
+    UnknownName {
+        /// Byte offset of the name
+        position: usize,
+
+        /// The name
+        name: String,
+    },
+
+    /// A token which can't appear at this position
+    UnexpectedToken {
+        /// Byte offset of the token
+        position: usize,
+    },
+
+    /// The expression ended early
+    UnexpectedEnd,
+
+    /// A log message placeholder which isn't closed
+    UnclosedPlaceholder {
+        /// Byte offset of the placeholder
+        position: usize,
+    },
+}
+
+impl std::fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionError::UnexpectedCharacter {
+                position,
+                character,
+            } => write!(f, "Unexpected character '{character}' at {position}"),
+            ExpressionError::InvalidNumber { position } => {
+                write!(f, "Invalid number at {position}")
+            }
+            ExpressionError::UnknownName { position, name } => {
+                write!(f, "Unknown name '{name}' at {position}")
+            }
+            ExpressionError::UnexpectedToken { position } => {
+                write!(f, "Unexpected token at {position}")
+            }
+            ExpressionError::UnexpectedEnd => write!(f, "Unexpected end of expression"),
+            ExpressionError::UnclosedPlaceholder { position } => {
+                write!(f, "Unclosed placeholder at {position}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+/// Machine state which can be read by an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    /// General purpose register, `v0` to `vf`
+    V(usize),
+
+    /// Index register, `i`
+    Index,
+
+    /// Program counter, `pc`
+    ProgramCounter,
+
+    /// Delay timer, `delay` or `dt`
+    DelayTimer,
+
+    /// Sound timer, `sound` or `st`
+    SoundTimer,
+}
+
+impl Variable {
+    /// Get the variable with a name, ignoring case
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+
+        match name.as_str() {
+            "i" => Some(Variable::Index),
+            "pc" => Some(Variable::ProgramCounter),
+            "delay" | "dt" => Some(Variable::DelayTimer),
+            "sound" | "st" => Some(Variable::SoundTimer),
+            _ => {
+                let register = name.strip_prefix('v')?;
+                if register.len() != 1 {
+                    return None;
+                }
+
+                usize::from_str_radix(register, 16).ok().map(Variable::V)
+            }
+        }
+    }
+
+    /// Read the variable
+    fn value(self, cpu: &CPU) -> i64 {
+        match self {
+            Variable::V(register) => i64::from(cpu.registers()[register]),
+            Variable::Index => i64::from(cpu.index_register()),
+            Variable::ProgramCounter => i64::from(cpu.program_counter()),
+            Variable::DelayTimer => i64::from(cpu.delay_timer()),
+            Variable::SoundTimer => i64::from(cpu.sound_timer()),
+        }
+    }
+}
+
+/// Operators which take two operands, in order of increasing precedence
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOperator {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOperator {
+    /// Operators of each precedence level, from lowest to highest
+    const LEVELS: [&'static [(&'static str, BinaryOperator)]; 10] = [
+        &[("||", BinaryOperator::Or)],
+        &[("&&", BinaryOperator::And)],
+        &[("|", BinaryOperator::BitOr)],
+        &[("^", BinaryOperator::BitXor)],
+        &[("&", BinaryOperator::BitAnd)],
+        &[
+            ("==", BinaryOperator::Equal),
+            ("!=", BinaryOperator::NotEqual),
+        ],
+        &[
+            ("<", BinaryOperator::Less),
+            ("<=", BinaryOperator::LessEqual),
+            (">", BinaryOperator::Greater),
+            (">=", BinaryOperator::GreaterEqual),
+        ],
+        &[
+            ("<<", BinaryOperator::ShiftLeft),
+            (">>", BinaryOperator::ShiftRight),
+        ],
+        &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)],
+        &[
+            ("*", BinaryOperator::Multiply),
+            ("/", BinaryOperator::Divide),
+            ("%", BinaryOperator::Remainder),
+        ],
+    ];
+
+    /// Apply the operator, `&&` and `||` are handled by the caller
+    fn apply(self, left: i64, right: i64) -> i64 {
+        // Note: Shift amounts are masked, so the casts can't wrap into a panic
+        match self {
+            BinaryOperator::Or => i64::from(left != 0 || right != 0),
+            BinaryOperator::And => i64::from(left != 0 && right != 0),
+            BinaryOperator::BitOr => left | right,
+            BinaryOperator::BitXor => left ^ right,
+            BinaryOperator::BitAnd => left & right,
+            BinaryOperator::Equal => i64::from(left == right),
+            BinaryOperator::NotEqual => i64::from(left != right),
+            BinaryOperator::Less => i64::from(left < right),
+            BinaryOperator::LessEqual => i64::from(left <= right),
+            BinaryOperator::Greater => i64::from(left > right),
+            BinaryOperator::GreaterEqual => i64::from(left >= right),
+            BinaryOperator::ShiftLeft => left.wrapping_shl((right & 63) as u32),
+            BinaryOperator::ShiftRight => left.wrapping_shr((right & 63) as u32),
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::Multiply => left.wrapping_mul(right),
+            BinaryOperator::Divide => left.checked_div(right).unwrap_or_default(),
+            BinaryOperator::Remainder => left.checked_rem(right).unwrap_or_default(),
+        }
+    }
+}
+
+/// Operators which take one operand
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryOperator {
+    Not,
+    Negate,
+    Complement,
+}
+
+/// Parsed expression tree
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(i64),
+    Variable(Variable),
+    Memory(Box<Node>),
+    Unary(UnaryOperator, Box<Node>),
+    Binary(BinaryOperator, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn evaluate(&self, cpu: &CPU, memory: &Memory) -> i64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Variable(variable) => variable.value(cpu),
+            Node::Memory(address) => {
+                let data = memory.data();
+                let length = data.len() as i64;
+                let address = address.evaluate(cpu, memory).rem_euclid(length);
+
+                // Note: The address is within the memory size, so the cast can't truncate
+                i64::from(data[address as usize])
+            }
+            Node::Unary(operator, operand) => {
+                let value = operand.evaluate(cpu, memory);
+                match operator {
+                    UnaryOperator::Not => i64::from(value == 0),
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Complement => !value,
+                }
+            }
+            Node::Binary(operator, left, right) => {
+                let left = left.evaluate(cpu, memory);
+
+                // Short circuit the logical operators
+                match operator {
+                    BinaryOperator::And if left == 0 => 0,
+                    BinaryOperator::Or if left != 0 => 1,
+                    _ => operator.apply(left, right.evaluate(cpu, memory)),
+                }
+            }
+        }
+    }
+}
+
+/// Token of the expression language
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// Symbols of the language, longer symbols first so they're matched before their prefixes
+const SYMBOLS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+/// Split an expression into tokens and their byte offsets
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while let Some(character) = source[position..].chars().next() {
+        let rest = &source[position..];
+
+        if character.is_whitespace() {
+            position += character.len_utf8();
+        } else if character.is_ascii_alphanumeric() || character == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let text = &rest[..length];
+
+            let token = if character.is_ascii_digit() {
+                Token::Number(
+                    parse_number(text).ok_or(ExpressionError::InvalidNumber { position })?,
+                )
+            } else {
+                Token::Name(text.to_string())
+            };
+
+            tokens.push((token, position));
+            position += length;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push((Token::Symbol(symbol), position));
+            position += symbol.len();
+        } else {
+            return Err(ExpressionError::UnexpectedCharacter {
+                position,
+                character,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parse a decimal, `0x` hexadecimal or `0b` binary number
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Recursive descent parser over the tokens of an expression
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+impl Parser {
+    /// Parse an expression, starting at the lowest precedence level
+    fn expression(&mut self) -> Result<Node, ExpressionError> {
+        self.binary(0)
+    }
+
+    /// Parse the binary operators of a precedence level
+    fn binary(&mut self, level: usize) -> Result<Node, ExpressionError> {
+        let Some(operators) = BinaryOperator::LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let mut left = self.binary(level + 1)?;
+
+        while let Some(operator) = self.peek_symbol().and_then(|symbol| {
+            operators
+                .iter()
+                .find(|(text, _)| *text == symbol)
+                .map(|(_, operator)| *operator)
+        }) {
+            self.index += 1;
+            let right = self.binary(level + 1)?;
+            left = Node::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    /// Parse a unary operator or an operand
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        let operator = match self.peek_symbol() {
+            Some("!") => UnaryOperator::Not,
+            Some("-") => UnaryOperator::Negate,
+            Some("~") => UnaryOperator::Complement,
+            _ => return self.primary(),
+        };
+
+        self.index += 1;
+        Ok(Node::Unary(operator, Box::new(self.unary()?)))
+    }
+
+    /// Parse a number, variable, memory access or parenthesized expression
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        let (token, position) = self
+            .tokens
+            .get(self.index)
+            .cloned()
+            .ok_or(ExpressionError::UnexpectedEnd)?;
+        self.index += 1;
+
+        match token {
+            Token::Number(value) => Ok(Node::Number(value)),
+            Token::Name(name) if name.eq_ignore_ascii_case("mem") => {
+                self.expect("[")?;
+                let address = self.expression()?;
+                self.expect("]")?;
+
+                Ok(Node::Memory(Box::new(address)))
+            }
+            Token::Name(name) => Variable::from_name(&name)
+                .map(Node::Variable)
+                .ok_or(ExpressionError::UnknownName { position, name }),
+            Token::Symbol("(") => {
+                let node = self.expression()?;
+                self.expect(")")?;
+
+                Ok(node)
+            }
+            Token::Symbol(_) => Err(ExpressionError::UnexpectedToken { position }),
+        }
+    }
+
+    /// Get the next token if it's a symbol
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.index) {
+            Some((Token::Symbol(symbol), _)) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    /// Consume a symbol or fail
+    fn expect(&mut self, symbol: &str) -> Result<(), ExpressionError> {
+        match self.tokens.get(self.index) {
+            Some((Token::Symbol(found), _)) if *found == symbol => {
+                self.index += 1;
+                Ok(())
+            }
+            Some((_, position)) => Err(ExpressionError::UnexpectedToken {
+                position: *position,
+            }),
+            None => Err(ExpressionError::UnexpectedEnd),
+        }
+    }
+}
+
+/// Expression over the machine state, used for breakpoint conditions and watches
+///
+/// Expressions use C-like operators on the registers `v0` to `vf`, `i`, `pc`, the timers
+/// `delay` and `sound`, and memory bytes `mem[address]`. Comparisons and logical operators give
+/// 1 or 0, and any value other than 0 is true. Division by zero gives 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    /// The text the expression was parsed from
+    source: String,
+
+    /// The parsed expression
+    node: Node,
+}
+
+impl Expression {
+    /// Parse an expression, e.g. `v3 == 0x10 && i >= 0x300`
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        Self::parse_at(source, 0)
+    }
+
+    /// Parse an expression with error positions offset from the start of a larger text
+    fn parse_at(source: &str, offset: usize) -> Result<Self, ExpressionError> {
+        let offset_error = |error| match error {
+            ExpressionError::UnexpectedCharacter {
+                position,
+                character,
+            } => ExpressionError::UnexpectedCharacter {
+                position: position + offset,
+                character,
+            },
+            ExpressionError::InvalidNumber { position } => ExpressionError::InvalidNumber {
+                position: position + offset,
+            },
+            ExpressionError::UnknownName { position, name } => ExpressionError::UnknownName {
+                position: position + offset,
+                name,
+            },
+            ExpressionError::UnexpectedToken { position } => ExpressionError::UnexpectedToken {
+                position: position + offset,
+            },
+            error => error,
+        };
+
+        let mut parser = Parser {
+            tokens: tokenize(source).map_err(offset_error)?,
+            index: 0,
+        };
+
+        let node = parser.expression().map_err(offset_error)?;
+
+        // The whole text has to be used
+        if let Some((_, position)) = parser.tokens.get(parser.index) {
+            return Err(offset_error(ExpressionError::UnexpectedToken {
+                position: *position,
+            }));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            node,
+        })
+    }
+
+    /// Get the text the expression was parsed from
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluate the expression
+    #[must_use]
+    pub fn evaluate(&self, cpu: &CPU, memory: &Memory) -> i64 {
+        self.node.evaluate(cpu, memory)
+    }
+
+    /// Evaluate the expression as a condition
+    #[must_use]
+    pub fn is_true(&self, cpu: &CPU, memory: &Memory) -> bool {
+        self.evaluate(cpu, memory) != 0
+    }
+}
+
+/// Part of a log message
+#[derive(Debug, Clone, PartialEq)]
+enum LogPart {
+    /// Text which is copied as is
+    Text(String),
+
+    /// Expression which is replaced by its value
+    Value {
+        /// The expression
+        expression: Expression,
+
+        /// Whether the value is shown as hexadecimal
+        hex: bool,
+    },
+}
+
+/// Message printed by a logpoint
+///
+/// Expressions in braces are replaced by their value, e.g. `x = {v0}, sprite = {mem[i]:x}`.
+/// Values are decimal unless the expression is followed by `:x`, and `{{` and `}}` are
+/// literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogMessage {
+    /// The text the message was parsed from
+    source: String,
+
+    /// Text and values of the message
+    parts: Vec<LogPart>,
+}
+
+impl LogMessage {
+    /// Parse a log message
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut position = 0;
+
+        while let Some(character) = source[position..].chars().next() {
+            let rest = &source[position..];
+
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                text.push(character);
+                position += 2;
+            } else if character == '{' {
+                let length = rest
+                    .find('}')
+                    .ok_or(ExpressionError::UnclosedPlaceholder { position })?;
+                let placeholder = &rest[1..length];
+
+                let (expression, hex) = match placeholder.strip_suffix(":x") {
+                    Some(expression) => (expression, true),
+                    None => (placeholder, false),
+                };
+
+                if !text.is_empty() {
+                    parts.push(LogPart::Text(std::mem::take(&mut text)));
+                }
+
+                parts.push(LogPart::Value {
+                    expression: Expression::parse_at(expression, position + 1)?,
+                    hex,
+                });
+                position += length + 1;
+            } else {
+                text.push(character);
+                position += character.len_utf8();
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(LogPart::Text(text));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    /// Get the text the message was parsed from
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Format the message with the current machine state
+    #[must_use]
+    pub fn format(&self, cpu: &CPU, memory: &Memory) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                LogPart::Text(text) => text.clone(),
+                LogPart::Value { expression, hex } => {
+                    let value = expression.evaluate(cpu, memory);
+                    if *hex {
+                        format!("{value:#X}")
+                    } else {
+                        value.to_string()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, cpu: &CPU, memory: &Memory) -> i64 {
+        Expression::parse(source).unwrap().evaluate(cpu, memory)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.data[0x400] = 0x12;
+
+        assert_eq!(evaluate("1 + 2 * 3", &cpu, &memory), 7);
+        assert_eq!(evaluate("(1 + 2) * 3", &cpu, &memory), 9);
+        assert_eq!(evaluate("0x10 | 0b11 << 1", &cpu, &memory), 0x16);
+        assert_eq!(evaluate("-1 < 0 && !0", &cpu, &memory), 1);
+        assert_eq!(evaluate("5 / 0 + 5 % 0", &cpu, &memory), 0);
+        assert_eq!(evaluate("mem[0x400] == 0x12", &cpu, &memory), 1);
+        assert_eq!(evaluate("MEM[0x3FF + 1] != 0", &cpu, &memory), 1);
+        assert_eq!(
+            evaluate("v3 == 0 && i >= 0 && delay == 0", &cpu, &memory),
+            1
+        );
+        assert_eq!(
+            evaluate("pc", &cpu, &memory),
+            i64::from(crate::PROGRAM_START)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Expression::parse("v3 == $"),
+            Err(ExpressionError::UnexpectedCharacter {
+                position: 6,
+                character: '$'
+            })
+        );
+        assert_eq!(
+            Expression::parse("vg + 1"),
+            Err(ExpressionError::UnknownName {
+                position: 0,
+                name: "vg".to_string()
+            })
+        );
+        assert_eq!(
+            Expression::parse("0xZZ"),
+            Err(ExpressionError::InvalidNumber { position: 0 })
+        );
+        assert_eq!(
+            Expression::parse("(1 + 2"),
+            Err(ExpressionError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Expression::parse("1 2"),
+            Err(ExpressionError::UnexpectedToken { position: 2 })
+        );
+    }
+
+    #[test]
+    fn test_log_message() {
+        let cpu = CPU::default();
+        let memory = Memory::default();
+
+        let message = LogMessage::parse("pc = {pc:x}, {{v0}} = {v0 + 1}").unwrap();
+        assert_eq!(message.format(&cpu, &memory), "pc = 0x200, {v0} = 1");
+
+        assert_eq!(
+            LogMessage::parse("x = {v0"),
+            Err(ExpressionError::UnclosedPlaceholder { position: 4 })
+        );
+        assert_eq!(
+            LogMessage::parse("x = {v0 + }"),
+            Err(ExpressionError::UnexpectedEnd)
+        );
+        assert_eq!(
+            LogMessage::parse("x = {v0 ? 1}"),
+            Err(ExpressionError::UnexpectedCharacter {
+                position: 8,
+                character: '?'
+            })
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+/// Number of flags of most devices
+pub const FLAG_COUNT: usize = 16;
+
+/// Number of flags of the Super-Chip, which only saves `v0` to `v7`
+pub const SUPER_CHIP_FLAG_COUNT: usize = 8;
+
+/// Storage which keeps the RPL user flags saved by `FX75` between sessions
+///
+/// Flags are stored per ROM, the key is the SHA-1 of the ROM.
+pub trait FlagStorage: std::fmt::Debug {
+    /// Load the flags of a ROM, `None` if none were saved
+    fn load_flags(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Save the flags of a ROM
+    fn save_flags(&mut self, key: &str, flags: &[u8]) -> std::io::Result<()>;
+}
+
+/// Flag storage which only lasts as long as it is kept around
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryFlagStorage {
+    /// Flags by ROM key
+    pub flags: HashMap<String, Vec<u8>>,
+}
+
+impl FlagStorage for MemoryFlagStorage {
+    fn load_flags(&self, key: &str) -> Option<Vec<u8>> {
+        self.flags.get(key).cloned()
+    }
+
+    fn save_flags(&mut self, key: &str, flags: &[u8]) -> std::io::Result<()> {
+        self.flags.insert(key.to_string(), flags.to_vec());
+        Ok(())
+    }
+}
+
+/// Flag storage which keeps the flags of each ROM in a file of a directory
+///
+/// The file is named after the ROM key and holds the raw flag bytes.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileFlagStorage {
+    directory: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileFlagStorage {
+    /// Create a storage in a directory, which is created when the first flags are saved
+    #[must_use]
+    pub fn new(directory: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Get the directory of the flag files
+    #[must_use]
+    pub fn directory(&self) -> &std::path::Path {
+        &self.directory
+    }
+
+    fn flag_path(&self, key: &str) -> std::path::PathBuf {
+        self.directory.join(format!("{key}.flags"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FlagStorage for FileFlagStorage {
+    fn load_flags(&self, key: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.flag_path(key)).ok()
+    }
+
+    fn save_flags(&mut self, key: &str, flags: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(self.flag_path(key), flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() {
+        let mut storage = MemoryFlagStorage::default();
+        assert_eq!(storage.load_flags("rom"), None);
+
+        storage.save_flags("rom", &[1, 2, 3]).unwrap();
+        assert_eq!(storage.load_flags("rom"), Some(vec![1, 2, 3]));
+        assert_eq!(storage.load_flags("other"), None);
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let directory = std::env::temp_dir().join(format!("c8-flags-{}", std::process::id()));
+        let mut storage = FileFlagStorage::new(&directory);
+        assert_eq!(storage.load_flags("rom"), None);
+
+        storage.save_flags("rom", &[4, 5, 6, 7]).unwrap();
+        assert_eq!(storage.load_flags("rom"), Some(vec![4, 5, 6, 7]));
+
+        // Another storage in the same directory sees the same flags
+        assert_eq!(
+            FileFlagStorage::new(&directory).load_flags("rom"),
+            Some(vec![4, 5, 6, 7])
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -10,6 +10,7 @@ pub struct FontData {
 /// Font name accessors
 #[allow(missing_docs)]
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FontName {
     CHIP8 = 0,
     VIP = 1,
@@ -39,7 +40,29 @@ impl From<FontName> for String {
     }
 }
 
-impl FontName {}
+impl FontName {
+    /// Get the name of the font in Octo's `fontStyle` option
+    #[must_use]
+    pub const fn octo_name(&self) -> &'static str {
+        match self {
+            FontName::CHIP8 => "octo",
+            FontName::VIP => "vip",
+            FontName::DREAM6800 => "dream6800",
+            FontName::ETI660 => "eti660",
+            FontName::FISHIE => "fish",
+            FontName::SUPERCHIP => "schip",
+        }
+    }
+
+    /// Find a font by its name in Octo's `fontStyle` option
+    #[must_use]
+    pub fn from_octo_name(name: &str) -> Option<FontName> {
+        FONT_DATA
+            .iter()
+            .map(|font| font.name)
+            .find(|font| font.octo_name() == name)
+    }
+}
 
 /// Font sizes
 #[allow(missing_docs)]
@@ -0,0 +1,511 @@
+/// A decoded Chip-8, SuperChip or XO-Chip instruction
+///
+/// Register operands are stored as register indices (0 to 15).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Call a machine code routine, only ignored on devices which support them
+    /// 0x0NNN
+    MachineCall(u16),
+
+    /// Scroll the display down n pixels
+    /// 0x00CN
+    ScrollDown(u8),
+
+    /// Scroll the display up n pixels
+    /// 0x00DN
+    ScrollUp(u8),
+
+    /// Clear the display
+    /// 0x00E0
+    ClearScreen,
+
+    /// Return from a subroutine
+    /// 0x00EE
+    Return,
+
+    /// Scroll the display right 4 pixels
+    /// 0x00FB
+    ScrollRight,
+
+    /// Scroll the display left 4 pixels
+    /// 0x00FC
+    ScrollLeft,
+
+    /// Exit the interpreter
+    /// 0x00FD
+    Exit,
+
+    /// Enable low resolution mode
+    /// 0x00FE
+    LowResolution,
+
+    /// Enable high resolution mode
+    /// 0x00FF
+    HighResolution,
+
+    /// Jump to address nnn
+    /// 0x1NNN
+    Jump(u16),
+
+    /// Call the subroutine at address nnn
+    /// 0x2NNN
+    Call(u16),
+
+    /// Skip the next instruction if Vx == nn
+    /// 0x3XNN
+    SkipIfEqual {
+        /// Register x
+        x: u8,
+        /// Value nn
+        value: u8,
+    },
+
+    /// Skip the next instruction if Vx != nn
+    /// 0x4XNN
+    SkipIfNotEqual {
+        /// Register x
+        x: u8,
+        /// Value nn
+        value: u8,
+    },
+
+    /// Skip the next instruction if Vx == Vy
+    /// 0x5XY0
+    SkipIfRegistersEqual {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Save the registers Vx to Vy to memory starting at I
+    /// 0x5XY2
+    SaveRange {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Load the registers Vx to Vy from memory starting at I
+    /// 0x5XY3
+    LoadRange {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = nn
+    /// 0x6XNN
+    SetRegister {
+        /// Register x
+        x: u8,
+        /// Value nn
+        value: u8,
+    },
+
+    /// Set Vx = Vx + nn
+    /// 0x7XNN
+    AddValue {
+        /// Register x
+        x: u8,
+        /// Value nn
+        value: u8,
+    },
+
+    /// Set Vx = Vy
+    /// 0x8XY0
+    Copy {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vx OR Vy
+    /// 0x8XY1
+    Or {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vx AND Vy
+    /// 0x8XY2
+    And {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vx XOR Vy
+    /// 0x8XY3
+    Xor {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vx + Vy, set VF = carry
+    /// 0x8XY4
+    Add {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vx - Vy, set VF = NOT borrow
+    /// 0x8XY5
+    Subtract {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vy >> 1, set VF = the shifted out bit
+    /// 0x8XY6
+    ShiftRight {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vy - Vx, set VF = NOT borrow
+    /// 0x8XY7
+    SubtractReverse {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set Vx = Vy << 1, set VF = the shifted out bit
+    /// 0x8XYE
+    ShiftLeft {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Skip the next instruction if Vx != Vy
+    /// 0x9XY0
+    SkipIfRegistersNotEqual {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+    },
+
+    /// Set I = nnn
+    /// 0xANNN
+    SetIndex(u16),
+
+    /// Jump to address nnn + V0
+    /// 0xBNNN
+    JumpOffset(u16),
+
+    /// Set Vx = random byte AND nn
+    /// 0xCXNN
+    Random {
+        /// Register x
+        x: u8,
+        /// Mask nn
+        mask: u8,
+    },
+
+    /// Draw a sprite at (Vx, Vy) with n rows of data starting at I, 16x16 when n is 0
+    /// 0xDXYN
+    Draw {
+        /// Register x
+        x: u8,
+        /// Register y
+        y: u8,
+        /// Height n
+        height: u8,
+    },
+
+    /// Skip the next instruction if the key in Vx is pressed
+    /// 0xEX9E
+    SkipIfKeyPressed {
+        /// Register x
+        x: u8,
+    },
+
+    /// Skip the next instruction if the key in Vx is not pressed
+    /// 0xEXA1
+    SkipIfKeyNotPressed {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set I = nnnn, this instruction is 4 bytes long
+    /// 0xF000 0xNNNN
+    SetIndexLong(u16),
+
+    /// Select the drawing planes with the bitmask n
+    /// 0xFN01
+    SetPlanes(u8),
+
+    /// Load the 16 byte audio pattern starting at I
+    /// 0xF002
+    LoadAudioPattern,
+
+    /// Set Vx = delay timer
+    /// 0xFX07
+    GetDelayTimer {
+        /// Register x
+        x: u8,
+    },
+
+    /// Wait for a key to be pressed and released, and store it in Vx
+    /// 0xFX0A
+    WaitForKey {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set the delay timer = Vx
+    /// 0xFX15
+    SetDelayTimer {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set the sound timer = Vx
+    /// 0xFX18
+    SetSoundTimer {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set I = I + Vx
+    /// 0xFX1E
+    AddIndex {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set I to the small font character in Vx
+    /// 0xFX29
+    SmallFont {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set I to the large font character in Vx
+    /// 0xFX30
+    LargeFont {
+        /// Register x
+        x: u8,
+    },
+
+    /// Store the binary-coded decimal representation of Vx at I, I+1 and I+2
+    /// 0xFX33
+    BinaryCodedDecimal {
+        /// Register x
+        x: u8,
+    },
+
+    /// Set the audio pattern pitch = Vx
+    /// 0xFX3A
+    SetPitch {
+        /// Register x
+        x: u8,
+    },
+
+    /// Store V0 to Vx in memory starting at I
+    /// 0xFX55
+    Store {
+        /// Register x
+        x: u8,
+    },
+
+    /// Load V0 to Vx from memory starting at I
+    /// 0xFX65
+    Load {
+        /// Register x
+        x: u8,
+    },
+
+    /// Save V0 to Vx to the RPL flags
+    /// 0xFX75
+    SaveFlags {
+        /// Register x
+        x: u8,
+    },
+
+    /// Load V0 to Vx from the RPL flags
+    /// 0xFX85
+    LoadFlags {
+        /// Register x
+        x: u8,
+    },
+
+    /// An opcode which isn't a known instruction
+    Unknown(u16),
+}
+
+/// Decode an opcode into an instruction
+///
+/// `next_word` is the word following the opcode in memory. It is only used by the 4 byte
+/// `F000 NNNN` instruction.
+#[must_use]
+pub fn decode(opcode: u16, next_word: u16) -> Instruction {
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00C0..=0x00CF => Instruction::ScrollDown(n),
+            0x00D0..=0x00DF => Instruction::ScrollUp(n),
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::LowResolution,
+            0x00FF => Instruction::HighResolution,
+            // Note: Other 0x00NN opcodes are reserved for extensions, they aren't machine calls
+            0x0001..=0x00FF => Instruction::Unknown(opcode),
+            _ => Instruction::MachineCall(nnn),
+        },
+        0x1000 => Instruction::Jump(nnn),
+        0x2000 => Instruction::Call(nnn),
+        0x3000 => Instruction::SkipIfEqual { x, value: nn },
+        0x4000 => Instruction::SkipIfNotEqual { x, value: nn },
+        0x5000 => match n {
+            0x0 => Instruction::SkipIfRegistersEqual { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0x6000 => Instruction::SetRegister { x, value: nn },
+        0x7000 => Instruction::AddValue { x, value: nn },
+        0x8000 => match n {
+            0x0 => Instruction::Copy { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::Add { x, y },
+            0x5 => Instruction::Subtract { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubtractReverse { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0x9000 => match n {
+            0x0 => Instruction::SkipIfRegistersNotEqual { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0xA000 => Instruction::SetIndex(nnn),
+        0xB000 => Instruction::JumpOffset(nnn),
+        0xC000 => Instruction::Random { x, mask: nn },
+        0xD000 => Instruction::Draw { x, y, height: n },
+        0xE000 => match nn {
+            0x9E => Instruction::SkipIfKeyPressed { x },
+            0xA1 => Instruction::SkipIfKeyNotPressed { x },
+            _ => Instruction::Unknown(opcode),
+        },
+        _ => match nn {
+            0x00 => Instruction::SetIndexLong(next_word),
+            0x01 => Instruction::SetPlanes(x),
+            0x02 => Instruction::LoadAudioPattern,
+            0x07 => Instruction::GetDelayTimer { x },
+            0x0A => Instruction::WaitForKey { x },
+            0x15 => Instruction::SetDelayTimer { x },
+            0x18 => Instruction::SetSoundTimer { x },
+            0x1E => Instruction::AddIndex { x },
+            0x29 => Instruction::SmallFont { x },
+            0x30 => Instruction::LargeFont { x },
+            0x33 => Instruction::BinaryCodedDecimal { x },
+            0x3A => Instruction::SetPitch { x },
+            0x55 => Instruction::Store { x },
+            0x65 => Instruction::Load { x },
+            0x75 => Instruction::SaveFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => Instruction::Unknown(opcode),
+        },
+    }
+}
+
+impl Instruction {
+    /// Get the size of the instruction in bytes
+    #[must_use]
+    pub const fn size(&self) -> u16 {
+        match self {
+            Instruction::SetIndexLong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Check if the instruction conditionally skips the next instruction
+    #[must_use]
+    pub const fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. }
+                | Instruction::SkipIfKeyPressed { .. }
+                | Instruction::SkipIfKeyNotPressed { .. }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(0x00E0, 0), Instruction::ClearScreen);
+        assert_eq!(decode(0x00C4, 0), Instruction::ScrollDown(4));
+        assert_eq!(decode(0x0000, 0), Instruction::MachineCall(0));
+        assert_eq!(decode(0x0123, 0), Instruction::MachineCall(0x123));
+        assert_eq!(decode(0x2ABC, 0), Instruction::Call(0xABC));
+        assert_eq!(decode(0x5122, 0), Instruction::SaveRange { x: 0x1, y: 0x2 });
+        assert_eq!(decode(0x8ABE, 0), Instruction::ShiftLeft { x: 0xA, y: 0xB });
+        assert_eq!(
+            decode(0xD12F, 0),
+            Instruction::Draw {
+                x: 0x1,
+                y: 0x2,
+                height: 0xF
+            }
+        );
+        assert_eq!(decode(0xF201, 0), Instruction::SetPlanes(2));
+        assert_eq!(decode(0xF533, 0), Instruction::BinaryCodedDecimal { x: 5 });
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        for opcode in [0x00E1, 0x00B0, 0x5124, 0x8128, 0x9121, 0xE1FF, 0xF1FF] {
+            assert_eq!(decode(opcode, 0), Instruction::Unknown(opcode));
+        }
+    }
+
+    #[test]
+    fn test_decode_long() {
+        let instruction = decode(0xF000, 0x1234);
+
+        assert_eq!(instruction, Instruction::SetIndexLong(0x1234));
+        assert_eq!(instruction.size(), 4);
+        assert_eq!(decode(0xA123, 0xFFFF).size(), 2);
+
+        // The register nibble is ignored, like the audio instruction
+        assert_eq!(decode(0xF100, 0x1234), Instruction::SetIndexLong(0x1234));
+        assert_eq!(decode(0xF302, 0), Instruction::LoadAudioPattern);
+    }
+}
@@ -1,6 +1,7 @@
 /// Keypad definitions for the Chip 8 buttons
 #[allow(missing_docs)]
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeypadKey {
     Num1 = 0x1,
     Num2 = 0x2,
@@ -96,7 +97,7 @@ impl From<usize> for KeypadKey {
 }
 
 /// Current state of the keypad
-#[derive(Debug, PartialEq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Keypad([u8; 16]);
 
 impl Keypad {
@@ -27,5 +27,65 @@ pub mod memory;
 /// System messages
 pub mod message;
 
+/// Device save states
+pub mod snapshot;
+
+/// Device history for stepping backwards
+pub mod rewind;
+
+/// Random number sources
+pub mod random;
+
+/// Persistent RPL user flags
+pub mod flags;
+
+/// Execution errors
+pub mod error;
+
+/// Chip-8 call stack
+pub mod stack;
+
+/// Instruction timing models
+pub mod timing;
+
+/// Instruction decoding
+pub mod instruction;
+
+/// Program disassembly
+pub mod disassembler;
+
+/// Breakpoints, watchpoints and stepping
+pub mod debugger;
+
+/// Debugger expressions
+pub mod expression;
+
+/// Execution traces
+pub mod trace;
+
+/// Display screenshots
+pub mod screenshot;
+
+/// Gameplay recordings
+pub mod recording;
+
+/// Octo `options.json` import and export
+#[cfg(feature = "octo")]
+pub mod octo_options;
+
+/// Octo cartridge images
+#[cfg(all(feature = "octo", feature = "gif"))]
+pub mod cartridge;
+
+/// ROM metadata database
+#[cfg(feature = "database")]
+pub mod rom_database;
+
 /// Program entry point
 pub const PROGRAM_START: u16 = 0x200;
+
+/// Get the lowercase hex SHA-1 of a ROM, which identifies the ROM in databases and storage
+#[must_use]
+pub fn rom_sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
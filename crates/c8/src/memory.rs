@@ -9,7 +9,7 @@ use crate::{
 // Changing the underlying container may be necessary if the memory usage is a concern.
 const ENABLE_XO: bool = true;
 
-const MAX_MEMORY: usize = if ENABLE_XO { 65536 } else { 4096 };
+pub(crate) const MAX_MEMORY: usize = if ENABLE_XO { 65536 } else { 4096 };
 
 const MAX_ROM_SIZE: usize = MAX_MEMORY - PROGRAM_START as usize;
 
@@ -112,6 +112,162 @@ impl Memory {
     }
 }
 
+/// Errors which can occur while applying a memory delta
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeltaError {
+    /// The target memory image is larger than the device memory
+    InvalidLength(usize),
+
+    /// A run is cut off or ends past the target memory image
+    InvalidRun {
+        /// Offset of the run header in the encoded runs
+        offset: usize,
+    },
+}
+
+impl std::fmt::Display for DeltaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeltaError::InvalidLength(length) => {
+                write!(f, "Invalid memory length {length} (at most {MAX_MEMORY})")
+            }
+            DeltaError::InvalidRun { offset } => write!(f, "Invalid run at offset {offset}"),
+        }
+    }
+}
+
+impl std::error::Error for DeltaError {}
+
+/// Compact encoding of the changes between two memory images
+///
+/// Only the modified runs of bytes are stored, each with a small header containing
+/// the offset and length of the run.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryDelta {
+    /// Length of the target memory image
+    length: usize,
+
+    /// Encoded runs: big endian u16 offset, big endian u16 length, then the run bytes
+    runs: Vec<u8>,
+}
+
+// Note: Small gaps between runs are merged since a run header costs 4 bytes
+const DELTA_MERGE_GAP: usize = 4;
+
+const DELTA_HEADER_SIZE: usize = 4;
+
+impl MemoryDelta {
+    /// Create the delta which turns `from` into `to`
+    ///
+    /// Both images must fit inside the device memory.
+    #[must_use]
+    pub fn between(from: &[u8], to: &[u8]) -> Self {
+        debug_assert!(to.len() <= MAX_MEMORY);
+
+        let mut runs = Vec::new();
+        let differs = |index: usize| from.get(index) != Some(&to[index]);
+
+        let mut index = 0;
+        while index < to.len() {
+            if !differs(index) {
+                index += 1;
+                continue;
+            }
+
+            let start = index;
+            let mut end = index + 1;
+            let mut scan = end;
+
+            // Extend the run until there is a large enough gap of unchanged bytes
+            while scan < to.len() && scan - end < DELTA_MERGE_GAP && end - start < 0xFFFF {
+                if differs(scan) {
+                    end = scan + 1;
+                }
+                scan += 1;
+            }
+
+            runs.extend_from_slice(&(start as u16).to_be_bytes());
+            runs.extend_from_slice(&((end - start) as u16).to_be_bytes());
+            runs.extend_from_slice(&to[start..end]);
+
+            index = end;
+        }
+
+        Self {
+            length: to.len(),
+            runs,
+        }
+    }
+
+    /// Apply the delta to a memory image
+    ///
+    /// The delta is checked first, so the image is left untouched if it is malformed.
+    pub fn apply(&self, data: &mut Vec<u8>) -> Result<(), DeltaError> {
+        self.validate()?;
+
+        data.resize(self.length, 0);
+        for (start, run) in self.iter_runs() {
+            data[start..start + run.len()].copy_from_slice(run);
+        }
+
+        Ok(())
+    }
+
+    /// Check that the runs are complete and fit inside the target memory image
+    pub fn validate(&self) -> Result<(), DeltaError> {
+        if self.length > MAX_MEMORY {
+            return Err(DeltaError::InvalidLength(self.length));
+        }
+
+        let mut cursor = 0;
+        while cursor < self.runs.len() {
+            let header = self
+                .runs
+                .get(cursor..cursor + DELTA_HEADER_SIZE)
+                .ok_or(DeltaError::InvalidRun { offset: cursor })?;
+            let start = usize::from(u16::from_be_bytes([header[0], header[1]]));
+            let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+
+            if start + length > self.length || cursor + DELTA_HEADER_SIZE + length > self.runs.len()
+            {
+                return Err(DeltaError::InvalidRun { offset: cursor });
+            }
+
+            cursor += DELTA_HEADER_SIZE + length;
+        }
+
+        Ok(())
+    }
+
+    /// Iterate over the runs of a validated delta as the start address and the run bytes
+    fn iter_runs(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        let mut cursor = 0;
+        std::iter::from_fn(move || {
+            let header = self.runs.get(cursor..cursor + DELTA_HEADER_SIZE)?;
+            let start = usize::from(u16::from_be_bytes([header[0], header[1]]));
+            let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+            cursor += DELTA_HEADER_SIZE;
+
+            let run = &self.runs[cursor..cursor + length];
+            cursor += length;
+            Some((start, run))
+        })
+    }
+
+    /// Check if the delta contains no changes
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Get the size of the encoded delta in bytes
+    #[must_use]
+    pub fn encoded_size(&self) -> usize {
+        self.runs.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,4 +301,66 @@ mod tests {
         assert_eq!(memory.data[0x202], 0x00);
         assert_eq!(memory.data[0x203], 0xEE);
     }
+
+    #[test]
+    fn test_memory_delta() {
+        let from = Memory::default();
+        let mut to = Memory::default();
+        to.load_rom(&[0x00, 0xE0, 0x00, 0xEE]);
+        to.data[0x300] = 0xAB;
+        to.data[MAX_MEMORY - 1] = 0xCD;
+
+        let delta = MemoryDelta::between(&from.data, &to.data);
+        assert!(!delta.is_empty());
+        assert!(delta.encoded_size() < 32);
+
+        let mut data = from.data.clone();
+        delta.apply(&mut data).unwrap();
+        assert_eq!(data, to.data);
+
+        // Applying the reverse delta restores the original memory
+        MemoryDelta::between(&to.data, &from.data)
+            .apply(&mut data)
+            .unwrap();
+        assert_eq!(data, from.data);
+
+        assert!(MemoryDelta::between(&from.data, &from.data).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_memory_delta() {
+        let mut data = vec![0; 16];
+        let invalid = |length: usize, runs: &[u8]| MemoryDelta {
+            length,
+            runs: runs.to_vec(),
+        };
+
+        // A run past the end of the image
+        assert_eq!(
+            invalid(16, &[0x00, 0x0F, 0x00, 0x02, 0xAA, 0xBB]).apply(&mut data),
+            Err(DeltaError::InvalidRun { offset: 0 })
+        );
+
+        // A run with fewer bytes than its length, and a cut off header
+        assert_eq!(
+            invalid(
+                16,
+                &[0x00, 0x00, 0x00, 0x01, 0xAA, 0x00, 0x01, 0x00, 0x02, 0xBB]
+            )
+            .apply(&mut data),
+            Err(DeltaError::InvalidRun { offset: 5 })
+        );
+        assert_eq!(
+            invalid(16, &[0x00, 0x00, 0x00]).apply(&mut data),
+            Err(DeltaError::InvalidRun { offset: 0 })
+        );
+
+        assert_eq!(
+            invalid(MAX_MEMORY + 1, &[]).apply(&mut data),
+            Err(DeltaError::InvalidLength(MAX_MEMORY + 1))
+        );
+
+        // The image is untouched
+        assert_eq!(data, [0; 16]);
+    }
 }
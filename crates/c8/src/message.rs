@@ -1,4 +1,4 @@
-use crate::display::DisplayResolution;
+use crate::{debugger::BreakReason, display::DisplayResolution, error::ExecutionError};
 
 /// Device messages which the emulator should be notified of
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -11,4 +11,16 @@ pub enum DeviceMessage {
 
     /// Waiting for a key to be pressed
     WaitingForKey(Option<usize>),
+
+    /// An instruction failed and was skipped
+    ExecutionError(ExecutionError),
+
+    /// An instruction failed and halted the device, which does nothing until it is reset
+    Halted(ExecutionError),
+
+    /// The debugger stopped the device
+    Break(BreakReason),
+
+    /// The flags of the ROM couldn't be written to the flag storage
+    FlagsNotSaved(std::io::ErrorKind),
 }
@@ -0,0 +1,406 @@
+use serde_json::Value;
+
+use crate::{
+    fonts::{FontName, FONT_DATA},
+    quirks::Quirks,
+};
+
+/// An RGB color
+pub type Rgb = [u8; 3];
+
+/// Accessor of an option in a struct
+type Accessor<T, V> = fn(&mut T) -> &mut V;
+
+/// Color keys of `options.json` and the colors they set
+const COLOR_KEYS: [(&str, Accessor<OctoColors, Option<Rgb>>); 6] = [
+    ("fillColor", |colors| &mut colors.foreground1),
+    ("fillColor2", |colors| &mut colors.foreground2),
+    ("blendColor", |colors| &mut colors.blended),
+    ("backgroundColor", |colors| &mut colors.background),
+    ("buzzColor", |colors| &mut colors.buzzer),
+    ("quietColor", |colors| &mut colors.silence),
+];
+
+/// Quirk keys of `options.json`, the quirks they set and whether the quirk is the opposite flag
+///
+/// Note: `loadStoreQuirks` leaves I unchanged, while `i_incremented` moves it past the registers
+const QUIRK_KEYS: [(&str, Accessor<Quirks, bool>, bool); 6] = [
+    (
+        "shiftQuirks",
+        |quirks| &mut quirks.vx_shifted_directly,
+        false,
+    ),
+    ("loadStoreQuirks", |quirks| &mut quirks.i_incremented, true),
+    ("clipQuirks", |quirks| &mut quirks.clip_sprites, false),
+    ("jumpQuirks", |quirks| &mut quirks.jump_bits, false),
+    ("logicQuirks", |quirks| &mut quirks.vf_zero, false),
+    ("vBlankQuirks", |quirks| &mut quirks.v_blank, false),
+];
+
+/// Quirks of Octo when none of the quirk flags are set
+const OCTO_QUIRKS: Quirks = Quirks {
+    vf_zero: false,
+    i_incremented: true,
+    vx_shifted_directly: false,
+    v_blank: false,
+    clip_sprites: false,
+    jump_bits: false,
+};
+
+/// Errors which can occur while reading an `options.json` file
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionsError {
+    /// The file isn't valid JSON
+    InvalidJson(String),
+
+    /// The file doesn't hold a JSON object
+    NotAnObject,
+}
+
+impl std::fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionsError::InvalidJson(error) => write!(f, "Invalid JSON: {error}"),
+            OptionsError::NotAnObject => write!(f, "The options are not a JSON object"),
+        }
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+/// Options which were skipped while reading an `options.json` file
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionsWarning {
+    /// A key without an equivalent setting
+    UnsupportedKey(String),
+
+    /// A value which can't be converted to the setting of its key
+    UnsupportedValue {
+        /// The key of the option
+        key: String,
+
+        /// The value as JSON
+        value: String,
+    },
+}
+
+impl std::fmt::Display for OptionsWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionsWarning::UnsupportedKey(key) => write!(f, "Unsupported option '{key}'"),
+            OptionsWarning::UnsupportedValue { key, value } => {
+                write!(f, "Unsupported value {value} for option '{key}'")
+            }
+        }
+    }
+}
+
+/// Display colors of an Octo palette
+///
+/// Colors which aren't set keep the current color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OctoColors {
+    /// Pixels which are off in both planes, `backgroundColor`
+    pub background: Option<Rgb>,
+
+    /// Pixels which are only on in the first plane, `fillColor`
+    pub foreground1: Option<Rgb>,
+
+    /// Pixels which are only on in the second plane, `fillColor2`
+    pub foreground2: Option<Rgb>,
+
+    /// Pixels which are on in both planes, `blendColor`
+    pub blended: Option<Rgb>,
+
+    /// Background while the buzzer sounds, `buzzColor`
+    pub buzzer: Option<Rgb>,
+
+    /// Background while the buzzer is silent, `quietColor`
+    pub silence: Option<Rgb>,
+}
+
+/// Settings of an Octo `options.json` file
+///
+/// Octo saves the file alongside exported programs and the Chip-8 archive ships one with each
+/// game. Quirk flags which are missing from a file are off, as they are in Octo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OctoOptions {
+    /// Instructions per frame, `tickrate`
+    pub tickrate: Option<u32>,
+
+    /// Display colors
+    pub colors: OctoColors,
+
+    /// Quirks, from the `*Quirks` flags
+    pub quirks: Quirks,
+
+    /// Small font, `fontStyle`
+    pub font: Option<FontName>,
+}
+
+impl Default for OctoOptions {
+    fn default() -> Self {
+        Self {
+            tickrate: None,
+            colors: OctoColors::default(),
+            quirks: OCTO_QUIRKS,
+            font: None,
+        }
+    }
+}
+
+impl OctoOptions {
+    /// Parse an `options.json` file
+    ///
+    /// Options without an equivalent setting, or with values which can't be converted, are
+    /// skipped and returned as warnings.
+    pub fn parse(json: &str) -> Result<(Self, Vec<OptionsWarning>), OptionsError> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|error| OptionsError::InvalidJson(error.to_string()))?;
+
+        Self::from_value(&value)
+    }
+
+    /// Read the options from a parsed JSON value, see `parse`
+    pub(crate) fn from_value(value: &Value) -> Result<(Self, Vec<OptionsWarning>), OptionsError> {
+        let Value::Object(object) = value else {
+            return Err(OptionsError::NotAnObject);
+        };
+
+        let mut options = Self::default();
+        let warnings = object
+            .iter()
+            .filter_map(|(key, value)| options.parse_option(key, value).err())
+            .collect();
+
+        Ok((options, warnings))
+    }
+
+    /// Write the options as an `options.json` file, in the key order Octo uses
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let mut object = Vec::new();
+
+        if let Some(tickrate) = self.tickrate {
+            object.push(format!("\"tickrate\": {tickrate}"));
+        }
+
+        let mut colors = self.colors;
+        for (key, color) in COLOR_KEYS {
+            if let Some([r, g, b]) = *color(&mut colors) {
+                object.push(format!("\"{key}\": \"#{r:02X}{g:02X}{b:02X}\""));
+            }
+        }
+
+        let mut quirks = self.quirks;
+        for (key, quirk, inverted) in QUIRK_KEYS {
+            object.push(format!("\"{key}\": {}", *quirk(&mut quirks) != inverted));
+        }
+
+        if let Some(font) = self.font {
+            object.push(format!("\"fontStyle\": \"{}\"", font.octo_name()));
+        }
+
+        format!("{{\n  {}\n}}\n", object.join(",\n  "))
+    }
+
+    /// Set the option of a key
+    fn parse_option(&mut self, key: &str, value: &Value) -> Result<(), OptionsWarning> {
+        let unsupported_value = || OptionsWarning::UnsupportedValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+
+        if key == "tickrate" {
+            // Note: Octo saves the tick rate as a string
+            let tickrate = match value {
+                Value::Number(number) => number.as_u64(),
+                Value::String(text) => text.trim().parse().ok(),
+                _ => None,
+            };
+
+            self.tickrate = Some(
+                tickrate
+                    .and_then(|tickrate| u32::try_from(tickrate).ok())
+                    .filter(|&tickrate| tickrate > 0)
+                    .ok_or_else(unsupported_value)?,
+            );
+        } else if key == "fontStyle" {
+            // Fonts without small glyphs can't be the system font
+            let font = value
+                .as_str()
+                .and_then(FontName::from_octo_name)
+                .filter(|&font| !FONT_DATA[usize::from(font)].small_data.is_empty())
+                .ok_or_else(unsupported_value)?;
+
+            self.font = Some(font);
+        } else if let Some((_, color)) = COLOR_KEYS.iter().find(|(name, _)| *name == key) {
+            let rgb = value
+                .as_str()
+                .and_then(parse_color)
+                .ok_or_else(unsupported_value)?;
+
+            *color(&mut self.colors) = Some(rgb);
+        } else if let Some((_, quirk, inverted)) =
+            QUIRK_KEYS.iter().find(|(name, _, _)| *name == key)
+        {
+            *quirk(&mut self.quirks) = value.as_bool().ok_or_else(unsupported_value)? != *inverted;
+        } else {
+            return Err(OptionsWarning::UnsupportedKey(key.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse an HTML color, i.e. `#FFCC00`
+pub(crate) fn parse_color(text: &str) -> Option<Rgb> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Some([r, g, b])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::*;
+
+    const GLITCH_GHOST: &str = include_str!("../../../assets/games/glitch-ghost/options.json");
+
+    /// Get the keys of an object which aren't `options.json` keys
+    fn unknown_keys(object: &Map<String, Value>) -> Vec<&str> {
+        object
+            .keys()
+            .map(String::as_str)
+            .filter(|key| {
+                !matches!(*key, "tickrate" | "fontStyle")
+                    && !COLOR_KEYS.iter().any(|(name, _)| name == key)
+                    && !QUIRK_KEYS.iter().any(|(name, _, _)| name == key)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_glitch_ghost() {
+        let (options, warnings) = OctoOptions::parse(GLITCH_GHOST).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        assert_eq!(options.tickrate, Some(200));
+        assert_eq!(
+            options.colors,
+            OctoColors {
+                background: Some([0x55, 0x55, 0x55]),
+                foreground1: Some([0xFF, 0xFF, 0xFF]),
+                foreground2: None,
+                blended: None,
+                buzzer: Some([0xFF, 0xFF, 0xFF]),
+                silence: Some([0x00, 0x00, 0x00]),
+            }
+        );
+        assert_eq!(options.quirks, OCTO_QUIRKS);
+        assert_eq!(options.font, None);
+    }
+
+    #[test]
+    fn test_quirks_and_font() {
+        let json = r#"{
+            "tickrate": 1000,
+            "shiftQuirks": true,
+            "loadStoreQuirks": true,
+            "clipQuirks": true,
+            "jumpQuirks": false,
+            "logicQuirks": true,
+            "vBlankQuirks": true,
+            "fontStyle": "vip"
+        }"#;
+
+        let (options, warnings) = OctoOptions::parse(json).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(options.tickrate, Some(1000));
+        assert_eq!(
+            options.quirks,
+            Quirks {
+                vf_zero: true,
+                i_incremented: false,
+                vx_shifted_directly: true,
+                v_blank: true,
+                clip_sprites: true,
+                jump_bits: false,
+            }
+        );
+        assert_eq!(options.font, Some(FontName::VIP));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let json = r##"{
+            "tickrate": "fast",
+            "fillColor": "yellow",
+            "blendColor": "#662200",
+            "vfOrderQuirks": false,
+            "clipQuirks": 1,
+            "screenRotation": 0,
+            "fontStyle": "schip"
+        }"##;
+
+        let (options, warnings) = OctoOptions::parse(json).unwrap();
+
+        // The valid options are still read
+        assert_eq!(options.colors.blended, Some([0x66, 0x22, 0x00]));
+        assert_eq!(options.tickrate, None);
+        assert_eq!(options.font, None);
+
+        // Note: The keys are sorted
+        let value = |key: &str, value: &str| OptionsWarning::UnsupportedValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        assert_eq!(
+            warnings,
+            [
+                value("clipQuirks", "1"),
+                value("fillColor", "\"yellow\""),
+                value("fontStyle", "\"schip\""),
+                OptionsWarning::UnsupportedKey("screenRotation".to_string()),
+                value("tickrate", "\"fast\""),
+                OptionsWarning::UnsupportedKey("vfOrderQuirks".to_string()),
+            ]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "Unsupported value 1 for option 'clipQuirks'"
+        );
+
+        assert_eq!(OctoOptions::parse("[]"), Err(OptionsError::NotAnObject));
+        assert!(matches!(
+            OctoOptions::parse("{"),
+            Err(OptionsError::InvalidJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_export() {
+        let (mut options, _) = OctoOptions::parse(GLITCH_GHOST).unwrap();
+        options.quirks.jump_bits = true;
+        options.font = Some(FontName::FISHIE);
+
+        let json = options.to_json();
+        assert!(json.starts_with("{\n  \"tickrate\": 200,\n  \"fillColor\": \"#FFFFFF\",\n"));
+        assert!(json.ends_with("  \"fontStyle\": \"fish\"\n}\n"));
+
+        // Every quirk is written, along with the colors which are set
+        let object: Map<String, Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(object.len(), 1 + 4 + 6 + 1);
+        assert!(unknown_keys(&object).is_empty());
+
+        let (imported, warnings) = OctoOptions::parse(&json).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(imported, options);
+    }
+}
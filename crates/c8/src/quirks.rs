@@ -1,3 +1,9 @@
+use crate::{
+    flags::{FLAG_COUNT, SUPER_CHIP_FLAG_COUNT},
+    stack::{CHIP8_STACK_DEPTH, DEFAULT_STACK_DEPTH, SUPER_CHIP_STACK_DEPTH},
+    timing::CpuTiming,
+};
+
 /// Quirks for the Chip-8 device
 // TODO: Refactor this
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
@@ -16,7 +22,6 @@ pub struct Quirks {
     pub vx_shifted_directly: bool,
 
     /// Quirk: Wait for the display to finish drawing before continuing, caps drawing at 60 sprites per second
-    /// Not implemented
     /// Octo: compat-vblank
     pub v_blank: bool,
 
@@ -38,7 +43,48 @@ pub enum CompatibilityDevice {
     Default,
     Chip8,
     SuperChip,
-    //XOChip,
+    XOChip,
+}
+
+impl CompatibilityDevice {
+    /// Get the call stack depth of the device
+    #[must_use]
+    pub const fn stack_depth(&self) -> usize {
+        match self {
+            CompatibilityDevice::Default => DEFAULT_STACK_DEPTH,
+            CompatibilityDevice::Chip8 => CHIP8_STACK_DEPTH,
+            CompatibilityDevice::SuperChip => SUPER_CHIP_STACK_DEPTH,
+            CompatibilityDevice::XOChip => DEFAULT_STACK_DEPTH,
+        }
+    }
+
+    /// Get the number of RPL user flags of the device, saved by `FX75`
+    #[must_use]
+    pub const fn flag_count(&self) -> usize {
+        match self {
+            CompatibilityDevice::SuperChip => SUPER_CHIP_FLAG_COUNT,
+            _ => FLAG_COUNT,
+        }
+    }
+
+    /// Check if programs of the device call machine code routines with `0NNN`
+    ///
+    /// The routines can't run, so the calls are skipped instead of reported as unknown.
+    #[must_use]
+    pub const fn supports_machine_calls(&self) -> bool {
+        matches!(self, CompatibilityDevice::Chip8)
+    }
+
+    /// Check if the device supports the XO-Chip extensions
+    ///
+    /// This changes how skip instructions treat the 4 byte `F000 NNNN` instruction.
+    #[must_use]
+    pub const fn supports_xo_chip(&self) -> bool {
+        matches!(
+            self,
+            CompatibilityDevice::Default | CompatibilityDevice::XOChip
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
@@ -47,6 +93,7 @@ pub enum CompatibilityDevice {
 pub struct CompatibilityProfile {
     pub device: CompatibilityDevice,
     pub quirks: Quirks,
+    pub timing: CpuTiming,
 }
 
 impl CompatibilityProfile {
@@ -57,7 +104,7 @@ impl CompatibilityProfile {
             CompatibilityDevice::Default => "default",
             CompatibilityDevice::Chip8 => "chip8",
             CompatibilityDevice::SuperChip => "super_chip",
-            //CompatibilityDevice::XOChip => "xo_chip",
+            CompatibilityDevice::XOChip => "xo_chip",
         }
     }
 
@@ -71,54 +118,73 @@ impl CompatibilityProfile {
         }
         "custom"
     }
+
+    /// Find the profile name based on the device and quirks
+    ///
+    /// Profiles with the same quirks are told apart by the device.
+    #[must_use]
+    pub fn find_device_profile_name_key(
+        device: CompatibilityDevice,
+        quirks: Quirks,
+    ) -> &'static str {
+        COMPATIBILITY_PROFILES
+            .iter()
+            .find(|profile| profile.device == device && profile.quirks == quirks)
+            .map_or_else(
+                || Self::find_profile_name_key(quirks),
+                CompatibilityProfile::name_key,
+            )
+    }
 }
 
 /// Compatibility profiles for different devices
-pub const COMPATIBILITY_PROFILES: [CompatibilityProfile; 3] = [
+pub const COMPATIBILITY_PROFILES: [CompatibilityProfile; 4] = [
     CompatibilityProfile {
         device: CompatibilityDevice::Default,
         quirks: Quirks {
             vf_zero: false,
-            i_incremented: false,
+            i_incremented: true,
             vx_shifted_directly: false,
             v_blank: false,
             clip_sprites: false,
             jump_bits: false,
         },
+        timing: CpuTiming::Fixed,
     },
     CompatibilityProfile {
         device: CompatibilityDevice::Chip8,
         quirks: Quirks {
             vf_zero: true,
-            i_incremented: false,
+            i_incremented: true,
             vx_shifted_directly: false,
             v_blank: true,
             clip_sprites: true,
             jump_bits: false,
         },
+        timing: CpuTiming::CosmacVip,
     },
     CompatibilityProfile {
         device: CompatibilityDevice::SuperChip,
         quirks: Quirks {
             vf_zero: false,
-            i_incremented: true,
-            vx_shifted_directly: false,
+            i_incremented: false,
+            vx_shifted_directly: true,
             v_blank: false,
             clip_sprites: true,
             jump_bits: true,
         },
+        timing: CpuTiming::Fixed,
     },
-    /*
     CompatibilityProfile {
         device: CompatibilityDevice::XOChip,
         quirks: Quirks {
             vf_zero: false,
-            i_incremented: false,
+            i_incremented: true,
             vx_shifted_directly: false,
             v_blank: false,
             clip_sprites: false,
             jump_bits: false,
         },
+        timing: CpuTiming::Fixed,
     },
-     */
 ];
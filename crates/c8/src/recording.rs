@@ -0,0 +1,368 @@
+use crate::{
+    display::Display,
+    screenshot::{pixel_value, Screenshot, ScreenshotColors},
+};
+
+/// Frames per second of the emulated display
+pub const RECORDING_FPS: u32 = 60;
+
+/// Shortest frame delay of a GIF in hundredths of a second, browsers slow down shorter delays
+#[cfg(feature = "gif")]
+const MIN_GIF_DELAY: u64 = 2;
+
+/// A display frame which was shown for one or more emulated frames in a row
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RecordedFrame {
+    /// Width of the display
+    width: usize,
+
+    /// Height of the display
+    height: usize,
+
+    /// Value of each pixel, with a bit for each plane
+    values: Vec<u8>,
+
+    /// Palette the frame was shown in
+    colors: ScreenshotColors,
+
+    /// Number of emulated frames it was shown for
+    length: u64,
+}
+
+/// Records the display every emulated frame, for animated GIFs and frame sequences
+///
+/// Identical frames in a row are stored once, so idle stretches take little memory. A recording
+/// which changes resolution is exported at the highest resolution, with the lower resolution
+/// frames upscaled to match.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    /// The distinct frames in order
+    frames: Vec<RecordedFrame>,
+
+    /// Number of emulated frames recorded
+    length: u64,
+}
+
+impl Recording {
+    /// Create an empty recording
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the display for one emulated frame
+    pub fn record(&mut self, display: &Display, colors: &ScreenshotColors) {
+        let (width, height) = display.resolution().resolution_size_xy();
+        let values: Vec<u8> = display
+            .zipped_iterator()
+            .map(|(&p0, &p1)| pixel_value(p0, p1))
+            .collect();
+
+        self.length += 1;
+
+        if let Some(last) = self.frames.last_mut() {
+            if last.width == width && last.values == values && last.colors == *colors {
+                last.length += 1;
+                return;
+            }
+        }
+
+        self.frames.push(RecordedFrame {
+            width,
+            height,
+            values,
+            colors: *colors,
+            length: 1,
+        });
+    }
+
+    /// Get the number of emulated frames recorded
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Check if nothing was recorded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Get the number of distinct frames, after identical frames in a row were merged
+    #[must_use]
+    pub fn distinct_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Remove all recorded frames
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.length = 0;
+    }
+
+    /// Get a screenshot of every emulated frame, i.e. 60 per second
+    ///
+    /// `scale` applies to the highest resolution of the recording.
+    pub fn screenshots(&self, scale: usize) -> impl Iterator<Item = Screenshot> + '_ {
+        let (width, _) = self.size(scale);
+
+        self.frames.iter().flat_map(move |frame| {
+            let screenshot = frame.screenshot(width);
+            std::iter::repeat_n(screenshot, frame.length as usize)
+        })
+    }
+
+    /// Write every emulated frame as a numbered PNG file, i.e. `frame_000001.png`
+    ///
+    /// The files can be turned into a video with
+    /// `ffmpeg -framerate 60 -i frame_%06d.png recording.mp4`. Returns the number of files.
+    #[cfg(feature = "png")]
+    pub fn write_png_frames(
+        &self,
+        directory: &std::path::Path,
+        scale: usize,
+    ) -> std::io::Result<u64> {
+        let (width, _) = self.size(scale);
+        let mut count = 0;
+
+        for frame in &self.frames {
+            // Encode each distinct frame once and write it for each emulated frame
+            let data = frame.screenshot(width).to_png();
+
+            for _ in 0..frame.length {
+                count += 1;
+                std::fs::write(directory.join(format!("frame_{count:06}.png")), &data)?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Write the recording as an animated GIF which loops forever
+    ///
+    /// GIF delays are counted in hundredths of a second, so frame times are rounded to them and
+    /// frames shown for less than two hundredths are dropped, as browsers slow those down.
+    #[cfg(feature = "gif")]
+    pub fn write_gif(&self, writer: impl std::io::Write, scale: usize) -> std::io::Result<()> {
+        let (width, height) = self.size(scale);
+
+        // Note: The size is at most 2048x1024 pixels, so the casts can't truncate
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &[])
+            .map_err(std::io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(std::io::Error::other)?;
+
+        // Time of an emulated frame in hundredths of a second
+        let time = |frame: u64| frame * 100 / u64::from(RECORDING_FPS);
+
+        let mut write_frame = |frame: &RecordedFrame, delay: u64| {
+            let palette: Vec<u8> = frame
+                .colors
+                .value_colors()
+                .iter()
+                .flat_map(|color| &color[..3])
+                .copied()
+                .collect();
+
+            let gif_frame = gif::Frame {
+                width: width as u16,
+                height: height as u16,
+                delay: delay.min(u64::from(u16::MAX)) as u16,
+                palette: Some(palette),
+                buffer: frame.scaled_values(width / frame.width).into(),
+                ..gif::Frame::default()
+            };
+
+            encoder
+                .write_frame(&gif_frame)
+                .map_err(std::io::Error::other)
+        };
+
+        // The frame waiting to be written, with the time it starts
+        let mut pending: Option<(&RecordedFrame, u64)> = None;
+        let mut start = 0;
+
+        for frame in &self.frames {
+            let frame_time = time(start);
+            start += frame.length;
+
+            pending = match pending {
+                Some((previous, previous_time)) if frame_time - previous_time >= MIN_GIF_DELAY => {
+                    write_frame(previous, frame_time - previous_time)?;
+                    Some((frame, frame_time))
+                }
+                // The previous frame is too short, this frame replaces it
+                Some((_, previous_time)) => Some((frame, previous_time)),
+                None => Some((frame, frame_time)),
+            };
+        }
+
+        if let Some((frame, frame_time)) = pending {
+            write_frame(frame, (time(start) - frame_time).max(MIN_GIF_DELAY))?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the size of exported frames
+    fn size(&self, scale: usize) -> (usize, usize) {
+        let scale = scale.clamp(1, crate::screenshot::MAX_SCREENSHOT_SCALE);
+
+        self.frames
+            .iter()
+            .max_by_key(|frame| frame.width)
+            .map_or((0, 0), |frame| (frame.width * scale, frame.height * scale))
+    }
+}
+
+impl RecordedFrame {
+    /// Get the pixel values upscaled by `scale`
+    fn scaled_values(&self, scale: usize) -> Vec<u8> {
+        let mut values = Vec::with_capacity(self.values.len() * scale * scale);
+
+        for row in self.values.chunks(self.width) {
+            let scaled_row: Vec<u8> = row
+                .iter()
+                .flat_map(|&value| std::iter::repeat_n(value, scale))
+                .collect();
+
+            for _ in 0..scale {
+                values.extend_from_slice(&scaled_row);
+            }
+        }
+
+        values
+    }
+
+    /// Get a screenshot upscaled to an exported width
+    fn screenshot(&self, width: usize) -> Screenshot {
+        let scale = width / self.width;
+
+        Screenshot::from_values(
+            self.width * scale,
+            self.height * scale,
+            &self.scaled_values(scale),
+            &self.colors,
+            1,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::device::C8;
+
+    use super::*;
+
+    /// Run a program which draws the 0 glyph, erases it and switches to high resolution
+    fn record() -> Recording {
+        // i := hex v0, sprite v0 v0 5, sprite v0 v0 5, hires, jump 0x208
+        let rom = [0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0x00, 0xFF, 0x12, 0x08];
+        let colors = ScreenshotColors::default();
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+
+        let mut recording = Recording::new();
+        for cycles in [2, 0, 0, 1, 1, 0, 0, 0, 0] {
+            c8.step(cycles);
+            recording.record(c8.display(), &colors);
+        }
+
+        recording
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let mut recording = record();
+
+        // Drawn for 3 frames, erased for 1, then high resolution for 5
+        assert_eq!(recording.len(), 9);
+        assert_eq!(recording.distinct_frames(), 3);
+
+        let lengths: Vec<u64> = recording.frames.iter().map(|frame| frame.length).collect();
+        assert_eq!(lengths, [3, 1, 5]);
+
+        // A new palette is a new frame
+        let display = Display::default();
+        recording.record(&display, &ScreenshotColors::default());
+        let colors = ScreenshotColors {
+            background: [1, 2, 3, 255],
+            ..ScreenshotColors::default()
+        };
+        recording.record(&display, &colors);
+        assert_eq!(recording.distinct_frames(), 5);
+
+        recording.clear();
+        assert!(recording.is_empty());
+        assert_eq!(recording.distinct_frames(), 0);
+    }
+
+    #[test]
+    fn test_screenshots() {
+        let recording = record();
+        let screenshots: Vec<Screenshot> = recording.screenshots(1).collect();
+
+        // Every frame has the size of the high resolution frames
+        assert_eq!(screenshots.len(), 9);
+        assert!(screenshots
+            .iter()
+            .all(|screenshot| (screenshot.width(), screenshot.height()) == (128, 64)));
+
+        // The low resolution 0 glyph is upscaled, its top row is 8 pixels wide
+        let colors = ScreenshotColors::default();
+        let pixel =
+            |index: usize, x: usize, y: usize| &screenshots[index].rgba()[(y * 128 + x) * 4..][..4];
+        assert_eq!(pixel(0, 7, 1), colors.foreground1);
+        assert_eq!(pixel(0, 8, 1), colors.background);
+        assert_eq!(pixel(3, 0, 0), colors.background);
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn test_gif() {
+        let recording = record();
+
+        let mut data = Vec::new();
+        recording.write_gif(&mut data, 2).unwrap();
+
+        assert_eq!(&data[..6], b"GIF89a");
+        // The logical screen size is little endian
+        assert_eq!(&data[6..10], &[0, 1, 128, 0]);
+        assert_eq!(data.last(), Some(&0x3B));
+
+        // The frames start at 0, 5 and 6 hundredths and the recording ends at 15, the erased
+        // frame is too short and dropped
+        let delays: Vec<u16> = data
+            .windows(6)
+            .filter(|window| window[..3] == [0x21, 0xF9, 0x04])
+            .map(|window| u16::from_le_bytes([window[4], window[5]]))
+            .collect();
+        assert_eq!(delays, [5, 10]);
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(std::io::Cursor::new(data))
+            .unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (256, 128));
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png_frames() {
+        let directory = std::env::temp_dir().join(format!("c8_png_frames_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let count = record().write_png_frames(&directory, 1).unwrap();
+        assert_eq!(count, 9);
+        assert!(directory.join("frame_000009.png").exists());
+        assert!(!directory.join("frame_000010.png").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
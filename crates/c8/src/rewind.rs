@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+
+use crate::{
+    device::C8, keypad::Keypad, memory::MemoryDelta, message::DeviceMessage,
+    snapshot::DeviceSnapshot,
+};
+
+/// Default memory budget of the rewind buffer (16 MiB)
+pub const DEFAULT_REWIND_BUDGET: usize = 16 * 1024 * 1024;
+
+/// Default number of frames between keyframes
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 30;
+
+// Estimated size of the fixed parts of a keyframe (registers, stack, etc.)
+const KEYFRAME_OVERHEAD: usize = 256;
+
+/// Input used to step the device for a single frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FrameInput {
+    keypad: Keypad,
+    cpu_speed: u32,
+
+    /// Number of instructions which ran when the debugger ended the frame early
+    stop: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Keyframe {
+    /// Device state at the start of the keyframe, without the memory data
+    snapshot: DeviceSnapshot,
+
+    /// Changes which turn the memory of the next keyframe into the memory of this keyframe
+    memory_undo: MemoryDelta,
+
+    /// Input for every frame stepped after the keyframe was captured
+    inputs: Vec<FrameInput>,
+}
+
+impl Keyframe {
+    fn size(&self) -> usize {
+        let planes: usize = self.snapshot.display.planes.iter().map(Vec::len).sum();
+
+        KEYFRAME_OVERHEAD
+            + planes
+            + self.snapshot.stack.len() * 2
+            + self.snapshot.cpu.audio_buffer.len()
+            + self.memory_undo.encoded_size()
+            + self.inputs.len() * std::mem::size_of::<FrameInput>()
+    }
+}
+
+/// History of the device which allows stepping backwards in time
+///
+/// The buffer stores a keyframe of the device every few frames along with the keypad input
+/// of every frame. Stepping backwards restores the closest keyframe and replays the recorded
+/// input up to the previous frame. Only the newest keyframe keeps a full copy of the memory,
+/// older keyframes store the changes needed to reach their memory from the next keyframe.
+///
+/// Replaying is deterministic unless the device uses `RandomSource::Thread`, in which case
+/// programs using random numbers may not end up in the exact same state.
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    /// Keyframes, oldest first
+    keyframes: VecDeque<Keyframe>,
+
+    /// Memory of the newest keyframe
+    memory: Vec<u8>,
+
+    /// Memory budget in bytes
+    budget: usize,
+
+    /// Number of frames between keyframes
+    keyframe_interval: usize,
+
+    /// Estimated size of the keyframes in bytes
+    size: usize,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_BUDGET, DEFAULT_KEYFRAME_INTERVAL)
+    }
+}
+
+impl RewindBuffer {
+    /// Create a new rewind buffer with a memory budget in bytes and the number of frames between keyframes
+    #[must_use]
+    pub fn new(budget: usize, keyframe_interval: usize) -> Self {
+        Self {
+            keyframes: VecDeque::new(),
+            memory: Vec::new(),
+            budget,
+            keyframe_interval: keyframe_interval.max(1),
+            size: 0,
+        }
+    }
+
+    /// Get the memory budget in bytes
+    #[must_use]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Set the memory budget in bytes, old history is discarded if needed
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.enforce_budget();
+    }
+
+    /// Get the estimated memory usage in bytes
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size + self.memory.len()
+    }
+
+    /// Get the number of frames which can be rewound
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.keyframes
+            .iter()
+            .map(|keyframe| keyframe.inputs.len())
+            .sum()
+    }
+
+    /// Check if there is no history to rewind
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.frame_count() == 0
+    }
+
+    /// Discard all history
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.memory.clear();
+        self.size = 0;
+    }
+
+    /// Record the device state and input, then step the device for a frame like `C8::step`
+    ///
+    /// When the debugger ends the frame early, replaying the frame stops at the same point.
+    pub fn step(&mut self, device: &mut C8, cpu_speed: u32) -> Vec<DeviceMessage> {
+        self.record(device, cpu_speed);
+
+        let (messages, stop) = device.step_until(cpu_speed, None);
+        if let Some(input) = self
+            .keyframes
+            .back_mut()
+            .and_then(|keyframe| keyframe.inputs.last_mut())
+        {
+            input.stop = stop;
+        }
+
+        messages
+    }
+
+    /// Record the device state and input before stepping it for a frame
+    fn record(&mut self, device: &C8, cpu_speed: u32) {
+        let needs_keyframe = self
+            .keyframes
+            .back()
+            .is_none_or(|keyframe| keyframe.inputs.len() >= self.keyframe_interval);
+
+        if needs_keyframe {
+            self.push_keyframe(device);
+        }
+
+        let input = FrameInput {
+            keypad: *device.keypad(),
+            cpu_speed,
+            stop: None,
+        };
+
+        if let Some(keyframe) = self.keyframes.back_mut() {
+            keyframe.inputs.push(input);
+            self.size += std::mem::size_of::<FrameInput>();
+        }
+
+        self.enforce_budget();
+    }
+
+    /// Step the device backwards by one frame
+    ///
+    /// Returns false if there is no history left.
+    pub fn rewind(&mut self, device: &mut C8) -> bool {
+        let Some(keyframe) = self.keyframes.back_mut() else {
+            return false;
+        };
+
+        if keyframe.inputs.pop().is_none() {
+            return false;
+        }
+
+        let mut snapshot = keyframe.snapshot.clone();
+        let inputs = keyframe.inputs.clone();
+
+        self.size -= std::mem::size_of::<FrameInput>();
+        snapshot.memory.clone_from(&self.memory);
+
+        if device.restore(&snapshot).is_err() {
+            // The history doesn't belong to this device anymore
+            self.clear();
+            return false;
+        }
+
+        // Note: Replaying doesn't trace, record, play sound, save flags or stop at breakpoints,
+        // all of which already happened when the frames were stepped
+        device.replay(
+            inputs
+                .iter()
+                .map(|input| (input.keypad, input.cpu_speed, input.stop)),
+        );
+
+        // The device is now at the keyframe itself, so the frames before it belong to the
+        // previous keyframe
+        if inputs.is_empty() {
+            self.pop_keyframe();
+        }
+
+        true
+    }
+
+    fn push_keyframe(&mut self, device: &C8) {
+        let mut snapshot = device.snapshot();
+        let memory = std::mem::take(&mut snapshot.memory);
+
+        if let Some(previous) = self.keyframes.back_mut() {
+            self.size -= previous.size();
+            previous.memory_undo = MemoryDelta::between(&memory, &self.memory);
+            self.size += previous.size();
+        }
+
+        let keyframe = Keyframe {
+            snapshot,
+            memory_undo: MemoryDelta::default(),
+            inputs: Vec::with_capacity(self.keyframe_interval),
+        };
+
+        self.size += keyframe.size();
+        self.keyframes.push_back(keyframe);
+        self.memory = memory;
+    }
+
+    fn pop_keyframe(&mut self) {
+        if let Some(keyframe) = self.keyframes.pop_back() {
+            self.size -= keyframe.size();
+        }
+
+        match self.keyframes.back_mut() {
+            Some(previous) => {
+                self.size -= previous.size();
+                let undo = std::mem::take(&mut previous.memory_undo);
+                self.size += previous.size();
+
+                if undo.apply(&mut self.memory).is_err() {
+                    // The memory of the previous keyframe can't be rebuilt
+                    self.clear();
+                }
+            }
+            None => self.memory.clear(),
+        }
+    }
+
+    fn enforce_budget(&mut self) {
+        // Always keep the newest keyframe so the latest frames can be rewound
+        while self.size() > self.budget && self.keyframes.len() > 1 {
+            if let Some(keyframe) = self.keyframes.pop_front() {
+                self.size -= keyframe.size();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        debugger::{Breakpoint, WatchedRegister},
+        expression::LogMessage,
+        keypad::KeypadKey,
+        trace::Tracer,
+    };
+
+    use super::*;
+
+    // Counts up in v0 and stores it in memory, v1 holds the state of key 5
+    const ROM: &[u8] = &[
+        0x70, 0x01, // v0 += 1
+        0xA3, 0x00, // i := 0x300
+        0xF0, 0x55, // save v0
+        0x61, 0x00, // v1 := 0
+        0x62, 0x05, // v2 := 5
+        0xE2, 0xA1, // if v2 key then
+        0x61, 0x01, // v1 := 1
+        0x12, 0x00, // jump 0x200
+    ];
+
+    fn run_frames(
+        device: &mut C8,
+        rewind: &mut RewindBuffer,
+        frames: usize,
+    ) -> Vec<DeviceSnapshot> {
+        let mut history = Vec::new();
+
+        for frame in 0..frames {
+            device
+                .keypad_mut()
+                .set_key(&KeypadKey::Num5, frame % 3 == 0);
+
+            history.push(device.snapshot());
+            rewind.step(device, 8);
+
+            if device.debugger().is_paused() {
+                device.debugger_mut().resume();
+            }
+        }
+
+        history
+    }
+
+    #[test]
+    fn test_rewind_frames() {
+        let mut device = C8::default();
+        device.load_rom(ROM);
+
+        let mut rewind = RewindBuffer::new(DEFAULT_REWIND_BUDGET, 4);
+        let history = run_frames(&mut device, &mut rewind, 10);
+        assert_eq!(rewind.frame_count(), 10);
+
+        for expected in history.iter().rev() {
+            assert!(rewind.rewind(&mut device));
+            assert_eq!(&device.snapshot(), expected);
+        }
+
+        assert!(rewind.is_empty());
+        assert!(!rewind.rewind(&mut device));
+    }
+
+    #[test]
+    fn test_rewind_across_breaks() {
+        // Stop before an instruction at the breakpoint, and after one when v1 changes
+        let mut device = C8::default();
+        device.load_rom(ROM);
+        device.debugger_mut().add_breakpoint(0x206);
+        device.debugger_mut().watch_register(WatchedRegister::V(1));
+
+        let mut rewind = RewindBuffer::new(DEFAULT_REWIND_BUDGET, 4);
+        let history = run_frames(&mut device, &mut rewind, 10);
+
+        // Replaying a frame stops where the debugger stopped it
+        for expected in history.iter().rev() {
+            assert!(rewind.rewind(&mut device));
+            assert_eq!(&device.snapshot(), expected);
+        }
+    }
+
+    #[test]
+    fn test_rewind_budget() {
+        let mut device = C8::default();
+        device.load_rom(ROM);
+
+        let mut rewind = RewindBuffer::new(DEFAULT_REWIND_BUDGET, 2);
+        run_frames(&mut device, &mut rewind, 20);
+
+        let budget = rewind.size() - 1;
+        rewind.set_budget(budget);
+
+        assert!(rewind.size() <= budget);
+        assert!(rewind.frame_count() < 20);
+
+        // The newest frames can still be rewound
+        assert!(rewind.rewind(&mut device));
+    }
+
+    #[test]
+    fn test_rewind_side_effects() {
+        let mut device = C8::default();
+        device.set_tracer(Some(Tracer::buffer(1000)));
+        device.load_rom(ROM);
+        device.debugger_mut().set_breakpoint(
+            0x20E,
+            Breakpoint {
+                log_message: Some(LogMessage::parse("v0 = {v0}").unwrap()),
+                ..Breakpoint::default()
+            },
+        );
+
+        let mut rewind = RewindBuffer::new(DEFAULT_REWIND_BUDGET, 4);
+        run_frames(&mut device, &mut rewind, 10);
+
+        let lines = device.tracer().unwrap().lines().count();
+        let hits = device.debugger().hit_count(0x20E);
+        let logs = device.debugger_mut().take_logs();
+        assert_eq!(logs.len(), 10);
+
+        for _ in 0..10 {
+            assert!(rewind.rewind(&mut device));
+        }
+
+        // Replaying the frames doesn't trace, log or count breakpoint hits again
+        assert_eq!(device.tracer().unwrap().lines().count(), lines);
+        assert_eq!(device.debugger().hit_count(0x20E), hits);
+        assert!(device.debugger_mut().take_logs().is_empty());
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::{
+    fonts::{FontName, FONT_DATA},
+    octo_options::{parse_color, OctoColors, Rgb},
+    quirks::{CompatibilityDevice, CompatibilityProfile, Quirks, COMPATIBILITY_PROFILES},
+    rom_sha1,
+};
+
+/// Errors which can occur while reading a ROM database
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatabaseError {
+    /// The file isn't valid JSON
+    InvalidJson(String),
+
+    /// The file doesn't hold a list of programs
+    NotAnArray,
+}
+
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseError::InvalidJson(error) => write!(f, "Invalid JSON: {error}"),
+            DatabaseError::NotAnArray => write!(f, "The database is not a list of programs"),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+/// A key a program uses for an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyHint {
+    /// Name of the action, i.e. `up` or `a`
+    pub action: String,
+
+    /// Chip-8 key of the action
+    pub key: u8,
+}
+
+/// Metadata of a ROM
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomInfo {
+    /// Title of the program
+    pub title: String,
+
+    /// Authors of the program
+    pub authors: Vec<String>,
+
+    /// Platform ids the ROM runs on, in order of preference
+    pub platforms: Vec<String>,
+
+    /// Profile of the first supported platform, with the quirks the ROM needs on it
+    pub profile: Option<CompatibilityProfile>,
+
+    /// Instructions per frame
+    pub tickrate: Option<u32>,
+
+    /// Display colors
+    pub colors: OctoColors,
+
+    /// Small font
+    pub font: Option<FontName>,
+
+    /// Keys the program uses
+    pub keys: Vec<KeyHint>,
+}
+
+/// ROM metadata keyed by the SHA-1 of the ROM bytes
+///
+/// Databases are read from the `programs.json` layout of the community CHIP-8 database, a list of
+/// programs which each hold their ROMs by hash. Unknown fields are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    /// Metadata by lowercase hex SHA-1
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    /// Create an empty database
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a database in the `programs.json` layout
+    pub fn parse(json: &str) -> Result<Self, DatabaseError> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|error| DatabaseError::InvalidJson(error.to_string()))?;
+        let Value::Array(programs) = value else {
+            return Err(DatabaseError::NotAnArray);
+        };
+
+        let mut database = Self::new();
+
+        for program in programs.iter().filter_map(Value::as_object) {
+            let title = program
+                .get("title")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let authors: Vec<String> = program
+                .get("authors")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect();
+
+            let roms = program.get("roms").and_then(Value::as_object);
+            for (hash, rom) in roms.into_iter().flatten() {
+                let Some(rom) = rom.as_object() else {
+                    continue;
+                };
+
+                let mut info = parse_rom(rom);
+                info.title = title.to_string();
+                info.authors.clone_from(&authors);
+                database.roms.insert(hash.to_lowercase(), info);
+            }
+        }
+
+        Ok(database)
+    }
+
+    /// Add the ROMs of another database, replacing the metadata of ROMs in both
+    pub fn merge(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    /// Get the metadata of a ROM by its hex SHA-1
+    #[must_use]
+    pub fn get(&self, sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(&sha1.to_lowercase())
+    }
+
+    /// Get the metadata of a ROM by its bytes
+    #[must_use]
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&rom_sha1(rom))
+    }
+
+    /// Get the number of ROMs
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    /// Check if the database has no ROMs
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+/// Get the device of a platform id, `None` if it isn't emulated
+fn platform_device(platform: &str) -> Option<CompatibilityDevice> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(CompatibilityDevice::Chip8),
+        "modernChip8" => Some(CompatibilityDevice::Default),
+        "chip48" | "superchip1" | "superchip" => Some(CompatibilityDevice::SuperChip),
+        "xochip" => Some(CompatibilityDevice::XOChip),
+        _ => None,
+    }
+}
+
+/// Override quirks with the quirks of a `quirkyPlatforms` entry
+///
+/// `memoryIncrementByX` has no equivalent quirk and is ignored.
+fn apply_quirks(quirks: &mut Quirks, overrides: &Map<String, Value>) {
+    for (name, value) in overrides {
+        let Some(value) = value.as_bool() else {
+            continue;
+        };
+
+        match name.as_str() {
+            "shift" => quirks.vx_shifted_directly = value,
+            "memoryLeaveIUnchanged" => quirks.i_incremented = !value,
+            "wrap" => quirks.clip_sprites = !value,
+            "jump" => quirks.jump_bits = value,
+            "vblank" => quirks.v_blank = value,
+            "logic" => quirks.vf_zero = value,
+            _ => {}
+        }
+    }
+}
+
+/// Read the metadata of a ROM entry, without the program metadata
+fn parse_rom(rom: &Map<String, Value>) -> RomInfo {
+    let platforms: Vec<String> = rom
+        .get("platforms")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect();
+
+    let profile = platforms.iter().find_map(|platform| {
+        let device = platform_device(platform)?;
+        let mut profile = *COMPATIBILITY_PROFILES
+            .iter()
+            .find(|profile| profile.device == device)?;
+
+        let overrides = rom
+            .get("quirkyPlatforms")
+            .and_then(|quirky| quirky.get(platform))
+            .and_then(Value::as_object);
+        if let Some(overrides) = overrides {
+            apply_quirks(&mut profile.quirks, overrides);
+        }
+
+        Some(profile)
+    });
+
+    // Note: The tick rate is a string in some entries
+    let tickrate = rom
+        .get("tickrate")
+        .and_then(|tickrate| match tickrate {
+            Value::Number(number) => number.as_u64(),
+            Value::String(text) => text.trim().parse().ok(),
+            _ => None,
+        })
+        .and_then(|tickrate| u32::try_from(tickrate).ok())
+        .filter(|&tickrate| tickrate > 0);
+
+    let colors = rom.get("colors").map(parse_colors).unwrap_or_default();
+
+    // Fonts without small glyphs can't be the system font
+    let font = rom
+        .get("fontStyle")
+        .and_then(Value::as_str)
+        .and_then(FontName::from_octo_name)
+        .filter(|&font| !FONT_DATA[usize::from(font)].small_data.is_empty());
+
+    let keys = rom
+        .get("keys")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(action, key)| {
+            let key = u8::try_from(key.as_u64()?).ok().filter(|&key| key < 16)?;
+            Some(KeyHint {
+                action: action.clone(),
+                key,
+            })
+        })
+        .collect();
+
+    RomInfo {
+        platforms,
+        profile,
+        tickrate,
+        colors,
+        font,
+        keys,
+        ..RomInfo::default()
+    }
+}
+
+/// Read the `colors` of a ROM entry, the `pixels` go from the background to the blended color
+fn parse_colors(colors: &Value) -> OctoColors {
+    let color = |value: &Value| value.as_str().and_then(parse_color);
+    let pixels: Vec<Option<Rgb>> = colors
+        .get("pixels")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(color)
+        .collect();
+    let pixel = |index: usize| pixels.get(index).copied().flatten();
+
+    OctoColors {
+        background: pixel(0),
+        foreground1: pixel(1),
+        foreground2: pixel(2),
+        blended: pixel(3),
+        buzzer: colors.get("buzzer").and_then(color),
+        silence: colors.get("silence").and_then(color),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = r##"[
+        {
+            "title": "Test Program",
+            "authors": ["Someone", "Someone Else"],
+            "release": "2024",
+            "roms": {
+                "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+                    "file": "abc.ch8",
+                    "platforms": ["megachip8", "superchip", "xochip"],
+                    "quirkyPlatforms": {
+                        "superchip": {
+                            "wrap": true,
+                            "vblank": true,
+                            "memoryLeaveIUnchanged": false,
+                            "memoryIncrementByX": true
+                        }
+                    },
+                    "tickrate": "30",
+                    "fontStyle": "vip",
+                    "keys": { "up": 5, "a": 6, "b": 16 },
+                    "colors": {
+                        "pixels": ["#000000", "#FFCC00", "bad"],
+                        "buzzer": "#FF0000"
+                    }
+                },
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709": {
+                    "platforms": ["chip8x"]
+                }
+            }
+        },
+        "not a program"
+    ]"##;
+
+    #[test]
+    fn test_parse() {
+        let database = RomDatabase::parse(DATABASE).unwrap();
+        assert_eq!(database.len(), 2);
+
+        // The SHA-1 of "abc", looked up by either case
+        let info = database.lookup(b"abc").unwrap();
+        assert_eq!(
+            Some(info),
+            database.get("a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+
+        assert_eq!(info.title, "Test Program");
+        assert_eq!(info.authors, ["Someone", "Someone Else"]);
+        assert_eq!(info.platforms, ["megachip8", "superchip", "xochip"]);
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(info.font, Some(FontName::VIP));
+        assert_eq!(
+            info.keys,
+            [
+                KeyHint {
+                    action: "a".to_string(),
+                    key: 6
+                },
+                KeyHint {
+                    action: "up".to_string(),
+                    key: 5
+                },
+            ]
+        );
+        assert_eq!(
+            info.colors,
+            OctoColors {
+                background: Some([0x00, 0x00, 0x00]),
+                foreground1: Some([0xFF, 0xCC, 0x00]),
+                buzzer: Some([0xFF, 0x00, 0x00]),
+                ..OctoColors::default()
+            }
+        );
+
+        // Mega-Chip isn't emulated, the Super-Chip profile is used with the quirk overrides
+        let profile = info.profile.unwrap();
+        assert_eq!(profile.device, CompatibilityDevice::SuperChip);
+        assert!(!profile.quirks.clip_sprites);
+        assert!(profile.quirks.v_blank);
+        assert!(profile.quirks.jump_bits);
+        assert!(profile.quirks.i_incremented);
+
+        // No supported platform
+        let empty = database.lookup(b"").unwrap();
+        assert_eq!(empty.title, "Test Program");
+        assert_eq!(empty.profile, None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut database = RomDatabase::parse(DATABASE).unwrap();
+        let other = RomDatabase::parse(
+            r#"[{ "title": "Replaced", "roms": { "a9993e364706816aba3e25717850c26c9cd0d89d": {} } }]"#,
+        )
+        .unwrap();
+
+        database.merge(other);
+        assert_eq!(database.len(), 2);
+        assert_eq!(database.lookup(b"abc").unwrap().title, "Replaced");
+        assert_eq!(database.lookup(b"abc").unwrap().profile, None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            RomDatabase::parse("{}").unwrap_err(),
+            DatabaseError::NotAnArray
+        );
+        assert!(matches!(
+            RomDatabase::parse("["),
+            Err(DatabaseError::InvalidJson(_))
+        ));
+        assert!(RomDatabase::new().is_empty());
+    }
+}
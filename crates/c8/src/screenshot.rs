@@ -0,0 +1,249 @@
+use crate::display::Display;
+
+/// Largest upscale of a screenshot, a 128x64 display becomes 2048x1024 pixels
+pub const MAX_SCREENSHOT_SCALE: usize = 16;
+
+/// An RGBA color
+pub type Rgba = [u8; 4];
+
+/// Colors of the pixels in a screenshot
+///
+/// Pixels are colored by the planes they are set in, the same way the app draws the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenshotColors {
+    /// Pixels which are off in both planes
+    pub background: Rgba,
+
+    /// Pixels which are only on in the first plane
+    pub foreground1: Rgba,
+
+    /// Pixels which are only on in the second plane
+    pub foreground2: Rgba,
+
+    /// Pixels which are on in both planes
+    pub blended: Rgba,
+}
+
+impl Default for ScreenshotColors {
+    /// The default palette of the app
+    fn default() -> Self {
+        Self {
+            background: [0x00, 0x00, 0x00, 0xFF],
+            foreground1: [0xFF, 0xFF, 0xFF, 0xFF],
+            foreground2: [0x90, 0xEE, 0x90, 0xFF],
+            blended: [0x00, 0x64, 0x00, 0xFF],
+        }
+    }
+}
+
+impl ScreenshotColors {
+    /// Get the color of a pixel from its value in each plane
+    #[must_use]
+    pub fn pixel_color(&self, plane0: u8, plane1: u8) -> Rgba {
+        self.value_color(pixel_value(plane0, plane1))
+    }
+
+    /// Get the color of a pixel value, see `pixel_value`
+    pub(crate) fn value_color(&self, value: u8) -> Rgba {
+        match value {
+            1 => self.foreground1,
+            2 => self.foreground2,
+            3 => self.blended,
+            _ => self.background,
+        }
+    }
+
+    /// Get the colors in the order of the pixel values, see `pixel_value`
+    #[cfg(feature = "gif")]
+    pub(crate) fn value_colors(&self) -> [Rgba; 4] {
+        [
+            self.background,
+            self.foreground1,
+            self.foreground2,
+            self.blended,
+        ]
+    }
+}
+
+/// Get the value of a pixel, with a bit for each plane it is set in
+pub(crate) fn pixel_value(plane0: u8, plane1: u8) -> u8 {
+    u8::from(plane0 != 0) | (u8::from(plane1 != 0) << 1)
+}
+
+/// An RGBA image of the display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    /// Width in pixels
+    width: usize,
+
+    /// Height in pixels
+    height: usize,
+
+    /// RGBA bytes of the pixels, row by row
+    pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    /// Capture the display at its resolution times `scale`
+    ///
+    /// The scale is clamped to `1..=MAX_SCREENSHOT_SCALE`.
+    #[must_use]
+    pub fn capture(display: &Display, colors: &ScreenshotColors, scale: usize) -> Self {
+        let (width, height) = display.resolution().resolution_size_xy();
+        let values: Vec<u8> = display
+            .zipped_iterator()
+            .map(|(&p0, &p1)| pixel_value(p0, p1))
+            .collect();
+
+        Self::from_values(width, height, &values, colors, scale)
+    }
+
+    /// Create a screenshot from pixel values, see `pixel_value`
+    pub(crate) fn from_values(
+        display_width: usize,
+        display_height: usize,
+        values: &[u8],
+        colors: &ScreenshotColors,
+        scale: usize,
+    ) -> Self {
+        let scale = scale.clamp(1, MAX_SCREENSHOT_SCALE);
+        let width = display_width * scale;
+        let height = display_height * scale;
+        let mut pixels = Vec::with_capacity(width * height * 4);
+
+        for row in values.chunks(display_width) {
+            let mut scaled_row = Vec::with_capacity(width * 4);
+            for &value in row {
+                let color = colors.value_color(value);
+                for _ in 0..scale {
+                    scaled_row.extend_from_slice(&color);
+                }
+            }
+
+            for _ in 0..scale {
+                pixels.extend_from_slice(&scaled_row);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Get the width in pixels
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Get the height in pixels
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the RGBA bytes of the pixels, row by row
+    #[must_use]
+    pub fn rgba(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Take the RGBA bytes of the pixels, row by row
+    #[must_use]
+    pub fn into_rgba(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    /// Write the screenshot as a PNG file
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, writer: impl std::io::Write) -> std::io::Result<()> {
+        // Note: The size is at most 2048x1024 pixels, so the casts can't truncate
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Encode the screenshot as a PNG file
+    #[cfg(feature = "png")]
+    #[must_use]
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        // Note: Writing to a vector can't fail
+        let _ = self.write_png(&mut data);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::device::C8;
+
+    use super::*;
+
+    /// Create a device which drew a 0 from the font at the top left
+    fn device() -> C8 {
+        // i := hex v0, sprite v0 v0 5
+        let rom = [0xF0, 0x29, 0xD0, 0x05];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.step(2);
+        c8
+    }
+
+    #[test]
+    fn test_capture() {
+        let c8 = device();
+        let colors = ScreenshotColors::default();
+
+        let screenshot = Screenshot::capture(c8.display(), &colors, 1);
+        assert_eq!((screenshot.width(), screenshot.height()), (64, 32));
+        assert_eq!(screenshot.rgba().len(), 64 * 32 * 4);
+
+        // The top row of the 0 is 0xF0
+        let pixel = |x: usize, y: usize| &screenshot.rgba()[(y * 64 + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), colors.foreground1);
+        assert_eq!(pixel(3, 0), colors.foreground1);
+        assert_eq!(pixel(4, 0), colors.background);
+        assert_eq!(pixel(1, 1), colors.background);
+
+        let scaled = Screenshot::capture(c8.display(), &colors, 3);
+        assert_eq!((scaled.width(), scaled.height()), (192, 96));
+        let pixel = |x: usize, y: usize| &scaled.rgba()[(y * 192 + x) * 4..][..4];
+        assert_eq!(pixel(11, 2), colors.foreground1);
+        assert_eq!(pixel(12, 2), colors.background);
+        assert_eq!(pixel(2, 3), colors.foreground1);
+        assert_eq!(pixel(3, 4), colors.background);
+
+        assert_eq!(Screenshot::capture(c8.display(), &colors, 0).width(), 64);
+    }
+
+    #[test]
+    fn test_pixel_color() {
+        let colors = ScreenshotColors::default();
+        assert_eq!(colors.pixel_color(0, 0), colors.background);
+        assert_eq!(colors.pixel_color(1, 0), colors.foreground1);
+        assert_eq!(colors.pixel_color(0, 1), colors.foreground2);
+        assert_eq!(colors.pixel_color(1, 1), colors.blended);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png() {
+        let c8 = device();
+        let data = Screenshot::capture(c8.display(), &ScreenshotColors::default(), 2).to_png();
+
+        assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+        // The IHDR chunk holds the size
+        assert_eq!(&data[16..24], &[0, 0, 0, 128, 0, 0, 0, 64]);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::{
+    display::DisplayResolution,
+    fonts::FontName,
+    keypad::KeypadKey,
+    memory::MAX_MEMORY,
+    quirks::{CompatibilityDevice, Quirks},
+    random::RandomGenerator,
+    timing::CpuTiming,
+};
+
+/// Current version of the snapshot format
+///
+/// This must be incremented whenever the layout or meaning of the snapshot changes.
+pub const SNAPSHOT_VERSION: u32 = 5;
+
+/// Number of general purpose registers (and RPL flags) stored in a snapshot
+const REGISTER_COUNT: usize = 16;
+
+/// Number of display planes stored in a snapshot
+const PLANE_COUNT: usize = 2;
+
+/// A full copy of the device state which can be restored later
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceSnapshot {
+    /// Snapshot format version
+    pub(crate) version: u32,
+
+    /// Memory data
+    pub memory: Vec<u8>,
+
+    /// System font loaded into memory
+    pub system_font: FontName,
+
+    /// Display state
+    pub display: DisplaySnapshot,
+
+    /// CPU state
+    pub cpu: CpuSnapshot,
+
+    /// Stack memory
+    pub stack: Vec<u16>,
+
+    /// Maximum depth of the stack
+    pub stack_depth: usize,
+
+    /// Quirks
+    pub quirks: Quirks,
+
+    /// Emulated compatibility device
+    pub compatibility_device: CompatibilityDevice,
+
+    /// Timing model
+    pub cpu_timing: CpuTiming,
+
+    /// COSMAC VIP machine cycles left in the current tick
+    pub vip_cycles: i32,
+
+    /// Whether the device is running
+    pub is_running: bool,
+}
+
+/// Display state stored in a snapshot
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisplaySnapshot {
+    /// Pixels of each plane
+    pub planes: Vec<Vec<u8>>,
+
+    /// Display resolution
+    pub resolution: DisplayResolution,
+
+    /// Active drawing plane
+    pub active_plane: usize,
+}
+
+/// CPU state stored in a snapshot
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuSnapshot {
+    /// General purpose registers
+    pub registers: Vec<u8>,
+
+    /// Index register
+    pub index_register: u16,
+
+    /// Program counter
+    pub program_counter: u16,
+
+    /// Delay timer
+    pub delay_timer: u8,
+
+    /// Sound timer
+    pub sound_timer: u8,
+
+    /// Register waiting for a key press, and the key that was pressed (if any)
+    pub waiting_for_key: Option<(usize, Option<KeypadKey>)>,
+
+    /// Saved RPL flags
+    pub saved_registers: Vec<u8>,
+
+    /// XO-Chip audio pattern buffer
+    pub audio_buffer: Vec<u8>,
+
+    /// XO-Chip audio pitch
+    pub buffer_pitch: u8,
+
+    /// Random number generator state
+    pub random: RandomGenerator,
+}
+
+/// Errors which can occur when restoring a snapshot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotError {
+    /// The snapshot was created with a different format version
+    UnsupportedVersion(u32),
+
+    /// The memory size doesn't match the device memory size
+    InvalidMemorySize(usize),
+
+    /// The display planes don't match the display resolution
+    InvalidDisplay,
+
+    /// The register count is incorrect
+    InvalidRegisters,
+
+    /// The program counter or index register is outside of memory
+    InvalidAddress(u16),
+
+    /// The stack holds more addresses than its depth allows
+    InvalidStack,
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported snapshot version {version} (expected {SNAPSHOT_VERSION})"
+            ),
+            SnapshotError::InvalidMemorySize(size) => {
+                write!(f, "Invalid memory size {size} (expected {MAX_MEMORY})")
+            }
+            SnapshotError::InvalidDisplay => write!(f, "Invalid display data"),
+            SnapshotError::InvalidRegisters => write!(f, "Invalid register data"),
+            SnapshotError::InvalidAddress(address) => write!(f, "Invalid address {address:#06X}"),
+            SnapshotError::InvalidStack => write!(f, "Invalid stack data"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl DeviceSnapshot {
+    /// Get the format version of the snapshot
+    #[must_use]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Check that the snapshot can be safely restored
+    pub fn validate(&self) -> Result<(), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+
+        if self.memory.len() != MAX_MEMORY {
+            return Err(SnapshotError::InvalidMemorySize(self.memory.len()));
+        }
+
+        let screen_size = self.display.resolution.resolution_size();
+        if self.display.planes.len() != PLANE_COUNT
+            || self
+                .display
+                .planes
+                .iter()
+                .any(|plane| plane.len() != screen_size)
+        {
+            return Err(SnapshotError::InvalidDisplay);
+        }
+
+        if self.cpu.registers.len() != REGISTER_COUNT
+            || self.cpu.saved_registers.len() != REGISTER_COUNT
+        {
+            return Err(SnapshotError::InvalidRegisters);
+        }
+
+        if let Some((register, _)) = self.cpu.waiting_for_key {
+            if register >= REGISTER_COUNT {
+                return Err(SnapshotError::InvalidRegisters);
+            }
+        }
+
+        // The program counter must leave room for a full instruction
+        if self.cpu.program_counter as usize + 1 >= MAX_MEMORY {
+            return Err(SnapshotError::InvalidAddress(self.cpu.program_counter));
+        }
+
+        if self.stack.len() > self.stack_depth {
+            return Err(SnapshotError::InvalidStack);
+        }
+
+        for address in &self.stack {
+            if *address as usize + 1 >= MAX_MEMORY {
+                return Err(SnapshotError::InvalidAddress(*address));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::device::C8;
+
+    use super::*;
+
+    // Small program which draws a sprite and calls a subroutine
+    const ROM: &[u8] = &[
+        0x60, 0x05, // v0 := 5
+        0xA2, 0x0A, // i := 0x20A
+        0xD0, 0x05, // sprite v0 v0 5
+        0x22, 0x0C, // call 0x20C
+        0x12, 0x08, // jump 0x208
+        0xF0, 0x90, // sprite data
+        0xF1, 0x15, // delay := v1
+        0x00, 0xEE, // return
+    ];
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let mut c8 = C8::default();
+        c8.load_rom(ROM);
+        c8.step(3);
+
+        let snapshot = c8.snapshot();
+        let pixels = c8.display().plane_pixels(0).clone();
+        let pc = c8.cpu().program_counter();
+
+        c8.step(10);
+        c8.reset_device();
+
+        assert!(c8.restore(&snapshot).is_ok());
+        assert_eq!(c8.display().plane_pixels(0), &pixels);
+        assert_eq!(c8.cpu().program_counter(), pc);
+        assert_eq!(c8.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_snapshot_version() {
+        let mut c8 = C8::default();
+        c8.load_rom(ROM);
+
+        let mut snapshot = c8.snapshot();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+
+        assert_eq!(
+            c8.restore(&snapshot),
+            Err(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_invalid_data() {
+        let c8 = C8::default();
+
+        let mut snapshot = c8.snapshot();
+        snapshot.memory.truncate(16);
+        assert_eq!(
+            snapshot.validate(),
+            Err(SnapshotError::InvalidMemorySize(16))
+        );
+
+        let mut snapshot = c8.snapshot();
+        snapshot.display.resolution = DisplayResolution::High;
+        assert_eq!(snapshot.validate(), Err(SnapshotError::InvalidDisplay));
+
+        let mut snapshot = c8.snapshot();
+        snapshot.cpu.registers.pop();
+        assert_eq!(snapshot.validate(), Err(SnapshotError::InvalidRegisters));
+
+        let mut snapshot = c8.snapshot();
+        snapshot.stack = vec![0x200; snapshot.stack_depth + 1];
+        assert_eq!(snapshot.validate(), Err(SnapshotError::InvalidStack));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_serde() {
+        let mut c8 = C8::default();
+        c8.load_rom(ROM);
+        c8.step(4);
+
+        let snapshot = c8.snapshot();
+        let serialized = ron::to_string(&snapshot).unwrap();
+        let deserialized: DeviceSnapshot = ron::from_str(&serialized).unwrap();
+
+        assert_eq!(snapshot, deserialized);
+    }
+}
@@ -0,0 +1,116 @@
+/// Default stack depth, used by Octo and XO-Chip programs which expect an effectively unlimited stack
+pub const DEFAULT_STACK_DEPTH: usize = 4096;
+
+/// Stack depth of the COSMAC VIP interpreter
+pub const CHIP8_STACK_DEPTH: usize = 12;
+
+/// Stack depth of the SCHIP interpreter
+pub const SUPER_CHIP_STACK_DEPTH: usize = 16;
+
+/// Fixed capacity call stack
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stack {
+    /// Return addresses, oldest first
+    data: Vec<u16>,
+
+    /// Maximum number of return addresses
+    depth: usize,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new(DEFAULT_STACK_DEPTH)
+    }
+}
+
+impl Stack {
+    /// Create an empty stack with a maximum depth
+    #[must_use]
+    pub fn new(depth: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            depth,
+        }
+    }
+
+    /// Get the maximum depth of the stack
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Set the maximum depth of the stack, the newest addresses are dropped if they don't fit
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.data.truncate(depth);
+    }
+
+    /// Get the number of return addresses on the stack
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if the stack is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Check if the stack is full
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.data.len() >= self.depth
+    }
+
+    /// Get the return addresses, oldest first
+    #[must_use]
+    pub fn addresses(&self) -> &[u16] {
+        &self.data
+    }
+
+    /// Push a return address onto the stack
+    ///
+    /// Returns false if the stack is full.
+    #[must_use]
+    pub fn push(&mut self, address: u16) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        self.data.push(address);
+        true
+    }
+
+    /// Pop the newest return address from the stack
+    pub fn pop(&mut self) -> Option<u16> {
+        self.data.pop()
+    }
+
+    /// Replace the return addresses, used when restoring snapshots
+    pub(crate) fn set_addresses(&mut self, addresses: &[u16]) {
+        self.data.clear();
+        self.data
+            .extend_from_slice(&addresses[..addresses.len().min(self.depth)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_depth() {
+        let mut stack = Stack::new(2);
+
+        assert!(stack.push(0x200));
+        assert!(stack.push(0x202));
+        assert!(stack.is_full());
+        assert!(!stack.push(0x204));
+        assert_eq!(stack.addresses(), &[0x200, 0x202]);
+
+        assert_eq!(stack.pop(), Some(0x202));
+        assert_eq!(stack.pop(), Some(0x200));
+        assert_eq!(stack.pop(), None);
+    }
+}
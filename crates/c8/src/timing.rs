@@ -0,0 +1,117 @@
+use crate::instruction::Instruction;
+
+/// How the device decides how many instructions to run each tick
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CpuTiming {
+    /// Run a fixed number of instructions per tick
+    #[default]
+    Fixed,
+
+    /// Charge each instruction the machine cycles the COSMAC VIP interpreter needs for it
+    CosmacVip,
+}
+
+impl CpuTiming {
+    /// Get the locale string key of the timing
+    #[must_use]
+    pub fn name_key(&self) -> &str {
+        match self {
+            CpuTiming::Fixed => "timing_fixed",
+            CpuTiming::CosmacVip => "timing_cosmac_vip",
+        }
+    }
+}
+
+/// Machine cycles of the COSMAC VIP per 60 Hz tick (1.7609 MHz clock, 8 clocks per cycle)
+pub const VIP_CYCLES_PER_TICK: i32 = 3668;
+
+// Machine cycles taken by the display DMA each tick, 32 rows of 8 bytes shown 4 times
+const VIP_DISPLAY_DMA_CYCLES: i32 = 1024;
+
+// Machine cycles taken by the interrupt routine which updates the timers
+const VIP_INTERRUPT_CYCLES: i32 = 78;
+
+/// Machine cycles left for the interpreter each tick
+pub const VIP_INTERPRETER_CYCLES: i32 =
+    VIP_CYCLES_PER_TICK - VIP_DISPLAY_DMA_CYCLES - VIP_INTERRUPT_CYCLES;
+
+// Machine cycles used by the interpreter loop to fetch and decode an instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// Machine cycles used while the interpreter waits for a key press
+pub(crate) const VIP_KEY_WAIT_CYCLES: u32 = VIP_FETCH_CYCLES;
+
+/// Get the machine cycles the COSMAC VIP interpreter takes to run an instruction
+///
+/// The costs are approximations of the interpreter routines, including the fetch and decode
+/// loop. Instructions the VIP doesn't have are charged as a jump.
+pub(crate) fn vip_instruction_cycles(instruction: &Instruction, registers: &[u8]) -> u32 {
+    let execute = match *instruction {
+        // Clears 256 bytes of display memory
+        Instruction::ClearScreen => 24 + 256 * 6,
+        Instruction::Return => 10,
+        Instruction::Call(_) => 26,
+        Instruction::SkipIfEqual { .. } | Instruction::SkipIfNotEqual { .. } => 10,
+        Instruction::SkipIfRegistersEqual { .. } | Instruction::SkipIfRegistersNotEqual { .. } => {
+            14
+        }
+        Instruction::SetRegister { .. } => 6,
+        Instruction::AddValue { .. } => 10,
+        Instruction::Copy { .. }
+        | Instruction::Or { .. }
+        | Instruction::And { .. }
+        | Instruction::Xor { .. }
+        | Instruction::Add { .. }
+        | Instruction::Subtract { .. }
+        | Instruction::ShiftRight { .. }
+        | Instruction::SubtractReverse { .. }
+        | Instruction::ShiftLeft { .. } => 44,
+        Instruction::JumpOffset(_) => 22,
+        Instruction::Random { .. } => 36,
+        Instruction::Draw { x, height, .. } => {
+            sprite_cycles(registers[usize::from(x)], u32::from(height))
+        }
+        Instruction::SkipIfKeyPressed { .. } | Instruction::SkipIfKeyNotPressed { .. } => 18,
+        Instruction::GetDelayTimer { .. }
+        | Instruction::SetDelayTimer { .. }
+        | Instruction::SetSoundTimer { .. } => 10,
+        Instruction::WaitForKey { .. } => 38,
+        Instruction::AddIndex { .. } | Instruction::SmallFont { .. } => 16,
+        // Converts the value with repeated subtraction
+        Instruction::BinaryCodedDecimal { x } => 84 + u32::from(registers[usize::from(x)]) / 4,
+        Instruction::Store { x } | Instruction::Load { x } => 14 + 14 * (u32::from(x) + 1),
+        _ => 12,
+    };
+
+    VIP_FETCH_CYCLES + execute
+}
+
+/// Get the machine cycles needed to draw a sprite
+///
+/// Each row of the sprite is shifted into place one bit at a time, so sprites which aren't
+/// aligned to a byte cost more, and need a second byte written for each row.
+fn sprite_cycles(x: u8, height: u32) -> u32 {
+    let shift = u32::from(x % 8);
+    let second_byte = if shift == 0 { 0 } else { 20 };
+
+    26 + height * (30 + shift * 8 + second_byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::decode;
+
+    #[test]
+    fn test_sprite_alignment() {
+        let mut registers = [0; 16];
+
+        let aligned = vip_instruction_cycles(&decode(0xD015, 0), &registers);
+        registers[0] = 3;
+        let unaligned = vip_instruction_cycles(&decode(0xD015, 0), &registers);
+
+        assert!(unaligned > aligned);
+        assert!(vip_instruction_cycles(&decode(0xD01F, 0), &registers) > unaligned);
+    }
+}
@@ -0,0 +1,361 @@
+use std::{collections::VecDeque, fmt::Write as _, io::Write};
+
+use crate::{
+    cpu::CPU,
+    disassembler::{instruction_text, Syntax},
+    instruction::decode,
+    memory::Memory,
+};
+
+/// Line format of a trace
+///
+/// Each line is the state of the device before an instruction runs. Registers are written as
+/// `V0:00 .. VF:00 I:0000 DT:00 ST:00`, the layout used by the trace logs of other CHIP-8
+/// emulators, so traces can be compared line by line with `diff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// Frame number, program counter, opcode, mnemonic and registers, i.e.
+    /// `     12 0200: 6005 LD V0, 0x05            V0:00 .. VF:00 I:0000 DT:00 ST:00`
+    #[default]
+    Full,
+
+    /// Program counter, opcode and registers, i.e. `PC:0200 O:6005 V0:00 .. I:0000 DT:00 ST:00`
+    ///
+    /// This leaves out the frame number and the mnemonic, which depend on the timing and
+    /// disassembler of each emulator, so only the machine state is compared.
+    State,
+}
+
+/// Change to the trace made by a tracepoint, a breakpoint of the debugger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceAction {
+    /// Start recording, from the instruction at the tracepoint
+    Start,
+
+    /// Stop recording, the instruction at the tracepoint isn't recorded
+    Stop,
+}
+
+impl TraceAction {
+    /// Get the locale key of the action
+    #[must_use]
+    pub fn name_key(&self) -> &str {
+        match self {
+            TraceAction::Start => "trace_start",
+            TraceAction::Stop => "trace_stop",
+        }
+    }
+}
+
+/// List of trace actions for iteration
+pub const TRACE_ACTIONS: [TraceAction; 2] = [TraceAction::Start, TraceAction::Stop];
+
+/// Where trace lines are written
+enum TraceOutput {
+    /// Keep the newest lines in memory
+    Buffer {
+        /// The recorded lines
+        lines: VecDeque<String>,
+
+        /// Maximum number of lines kept
+        capacity: usize,
+    },
+
+    /// Write the lines to a file or other writer
+    Writer(Box<dyn Write>),
+}
+
+/// Records every instruction run by a device
+pub struct Tracer {
+    /// Where lines are written
+    output: TraceOutput,
+
+    /// Format of the lines
+    format: TraceFormat,
+
+    /// Whether instructions are being recorded
+    recording: bool,
+
+    /// Number of the current frame, counted from when the tracer was created
+    frame: u64,
+
+    /// The error which stopped the writer
+    error: Option<std::io::Error>,
+}
+
+impl std::fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .field("recording", &self.recording)
+            .field("frame", &self.frame)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    /// Create a tracer which keeps the newest `capacity` lines in memory
+    #[must_use]
+    pub fn buffer(capacity: usize) -> Self {
+        Self::new(TraceOutput::Buffer {
+            lines: VecDeque::new(),
+            capacity,
+        })
+    }
+
+    /// Create a tracer which writes lines to a file or other writer
+    ///
+    /// Wrap files in a `BufWriter`, as a line is written for every instruction.
+    #[must_use]
+    pub fn writer(writer: Box<dyn Write>) -> Self {
+        Self::new(TraceOutput::Writer(writer))
+    }
+
+    fn new(output: TraceOutput) -> Self {
+        Self {
+            output,
+            format: TraceFormat::default(),
+            recording: true,
+            frame: 0,
+            error: None,
+        }
+    }
+
+    /// Get the line format
+    #[must_use]
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    /// Set the line format
+    pub fn set_format(&mut self, format: TraceFormat) {
+        self.format = format;
+    }
+
+    /// Check if instructions are being recorded
+    #[must_use]
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Start or stop recording
+    ///
+    /// A tracer records from the start, stop it first to only record from a tracepoint which
+    /// starts the trace.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    /// Get the lines kept in memory, which is empty when writing to a writer
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        let lines = match &self.output {
+            TraceOutput::Buffer { lines, .. } => Some(lines.iter().map(String::as_str)),
+            TraceOutput::Writer(_) => None,
+        };
+
+        lines.into_iter().flatten()
+    }
+
+    /// Take the lines kept in memory
+    pub fn take_lines(&mut self) -> Vec<String> {
+        match &mut self.output {
+            TraceOutput::Buffer { lines, .. } => lines.drain(..).collect(),
+            TraceOutput::Writer(_) => Vec::new(),
+        }
+    }
+
+    /// Get the error which stopped writing lines
+    #[must_use]
+    pub fn error(&self) -> Option<&std::io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Flush the writer
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.output {
+            TraceOutput::Buffer { .. } => Ok(()),
+            TraceOutput::Writer(writer) => writer.flush(),
+        }
+    }
+
+    /// Count a new frame
+    pub(crate) fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Record the instruction at the program counter, before it runs
+    pub(crate) fn record(&mut self, cpu: &CPU, memory: &Memory, action: Option<TraceAction>) {
+        if let Some(action) = action {
+            self.recording = action == TraceAction::Start;
+        }
+
+        if !self.recording || self.error.is_some() {
+            return;
+        }
+
+        let line = self.line(cpu, memory);
+
+        match &mut self.output {
+            TraceOutput::Buffer { lines, capacity } => {
+                if lines.len() >= *capacity {
+                    lines.pop_front();
+                }
+
+                if *capacity > 0 {
+                    lines.push_back(line);
+                }
+            }
+            TraceOutput::Writer(writer) => {
+                if let Err(error) = writeln!(writer, "{line}") {
+                    self.error = Some(error);
+                }
+            }
+        }
+    }
+
+    /// Format the state of the device as a line
+    fn line(&self, cpu: &CPU, memory: &Memory) -> String {
+        let data = memory.data();
+        let pc = usize::from(cpu.program_counter());
+        let word = |address: usize| {
+            let high = data[address % data.len()];
+            let low = data[(address + 1) % data.len()];
+            (u16::from(high) << 8) | u16::from(low)
+        };
+        let opcode = word(pc);
+
+        // Writing to a string can't fail
+        let mut line = String::new();
+        let _ = match self.format {
+            TraceFormat::Full => {
+                let mnemonic = instruction_text(decode(opcode, word(pc + 2)), Syntax::Classic);
+                write!(
+                    line,
+                    "{:>7} {pc:04X}: {opcode:04X} {mnemonic:<22}",
+                    self.frame
+                )
+            }
+            TraceFormat::State => write!(line, "PC:{pc:04X} O:{opcode:04X}"),
+        };
+
+        for (index, value) in cpu.registers().iter().enumerate() {
+            let _ = write!(line, " V{index:X}:{value:02X}");
+        }
+
+        let _ = write!(
+            line,
+            " I:{:04X} DT:{:02X} ST:{:02X}",
+            cpu.index_register(),
+            cpu.delay_timer(),
+            cpu.sound_timer()
+        );
+
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{debugger::Breakpoint, device::C8, expression::Expression};
+
+    use super::*;
+
+    #[test]
+    fn test_trace_lines() {
+        // v0 := 5, i := 0x300, jump 0x204
+        let rom = [0x60, 0x05, 0xA3, 0x00, 0x12, 0x04];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.set_tracer(Some(Tracer::buffer(2)));
+        c8.step(3);
+
+        let registers =
+            "V0:05 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 \
+             VE:00 VF:00";
+        let lines: Vec<&str> = c8.tracer().unwrap().lines().collect();
+        assert_eq!(
+            lines,
+            [
+                format!("      1 0202: A300 LD I, 0x300            {registers} I:0000 DT:00 ST:00"),
+                format!("      1 0204: 1204 JP 0x204               {registers} I:0300 DT:00 ST:00"),
+            ]
+        );
+
+        let mut tracer = c8.set_tracer(None).unwrap();
+        tracer.set_format(TraceFormat::State);
+        tracer.take_lines();
+        c8.set_tracer(Some(tracer));
+        c8.step(1);
+
+        let lines: Vec<&str> = c8.tracer().unwrap().lines().collect();
+        assert_eq!(
+            lines,
+            [format!("PC:0204 O:1204 {registers} I:0300 DT:00 ST:00")]
+        );
+    }
+
+    #[test]
+    fn test_trace_start_stop() {
+        // v0 += 1, v1 += 1, v2 += 1, jump 0x200
+        let rom = [0x70, 0x01, 0x71, 0x01, 0x72, 0x01, 0x12, 0x00];
+
+        let mut c8 = C8::default();
+        c8.load_rom(&rom);
+        c8.set_tracer(Some(tracer_stopped()));
+        c8.debugger_mut().set_breakpoint(
+            0x202,
+            Breakpoint {
+                trace_action: Some(TraceAction::Start),
+                ..Breakpoint::default()
+            },
+        );
+        c8.debugger_mut().set_breakpoint(
+            0x206,
+            Breakpoint {
+                trace_action: Some(TraceAction::Stop),
+                ..Breakpoint::default()
+            },
+        );
+        c8.step(8);
+
+        let pcs: Vec<&str> = c8
+            .tracer()
+            .unwrap()
+            .lines()
+            .map(|line| &line[..7])
+            .collect();
+        assert_eq!(pcs, ["PC:0202", "PC:0204", "PC:0202", "PC:0204"]);
+        assert!(!c8.debugger().is_paused());
+
+        // Conditions and hit counts of the tracepoints apply
+        c8.set_tracer(Some(tracer_stopped()));
+        c8.load_rom(&rom);
+        c8.debugger_mut().set_breakpoint(
+            0x202,
+            Breakpoint {
+                condition: Some(Expression::parse("v0 >= 2").unwrap()),
+                hit_target: 2,
+                trace_action: Some(TraceAction::Start),
+                ..Breakpoint::default()
+            },
+        );
+        c8.step(12);
+
+        let pcs: Vec<&str> = c8
+            .tracer()
+            .unwrap()
+            .lines()
+            .map(|line| &line[..7])
+            .collect();
+        assert_eq!(pcs, ["PC:0202", "PC:0204"]);
+    }
+
+    /// Create a tracer which waits for a tracepoint to start recording
+    fn tracer_stopped() -> Tracer {
+        let mut tracer = Tracer::buffer(100);
+        tracer.set_format(TraceFormat::State);
+        tracer.set_recording(false);
+        tracer
+    }
+}
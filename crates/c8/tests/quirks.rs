@@ -0,0 +1,63 @@
+//! Quirk tests using the bundled Timendus quirks test ROM
+
+use c8::{
+    audio::audio_settings::AudioSettings,
+    device::C8,
+    quirks::{CompatibilityDevice, COMPATIBILITY_PROFILES},
+};
+
+const QUIRKS_ROM: &[u8] = include_bytes!("../../../assets/test_roms/5-quirks.ch8");
+
+// The test ROM reads the platform to test from this address, 1 selects CHIP-8
+const PLATFORM_ADDRESS: usize = 0x1FF;
+
+/// Run the quirks test ROM for a platform and return the display as text
+fn run_quirks_test(device: CompatibilityDevice, platform: u8) -> Vec<String> {
+    let profile = COMPATIBILITY_PROFILES
+        .iter()
+        .find(|profile| profile.device == device)
+        .expect("compatibility profile");
+
+    let mut c8 = C8::default();
+    c8.set_compatibility_profile(profile);
+    c8.load_rom(QUIRKS_ROM);
+    c8.memory_mut().data_mut()[PLATFORM_ADDRESS] = platform;
+    c8.audio_device.set_audio_settings(AudioSettings {
+        enabled: false,
+        ..AudioSettings::default()
+    });
+
+    for _ in 0..600 {
+        c8.step(30);
+    }
+
+    let (width, _) = c8.display().resolution().resolution_size_xy();
+    c8.display()
+        .plane_pixels(0)
+        .chunks(width)
+        .map(|row| {
+            row.iter()
+                .map(|&p| if p == 0 { '.' } else { '#' })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_display_wait_chip8() {
+    let display = run_quirks_test(CompatibilityDevice::Chip8, 1);
+
+    // The third result reads "ON" followed by a check mark
+    let result: Vec<&str> = display[11..15].iter().map(|row| &row[42..62]).collect();
+    assert_eq!(
+        result,
+        [
+            "###.##..............",
+            "#.#.#.#..........#.#",
+            "#.#.#.#..........##.",
+            "###.#.#..........#..",
+        ],
+        "display:\n{}",
+        display.join("\n")
+    );
+}
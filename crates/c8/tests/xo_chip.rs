@@ -0,0 +1,109 @@
+//! XO-Chip conformance tests using the bundled XO-Chip programs
+
+use c8::{
+    audio::audio_settings::AudioSettings,
+    device::C8,
+    display::DisplayResolution,
+    message::DeviceMessage,
+    quirks::{CompatibilityDevice, COMPATIBILITY_PROFILES},
+};
+
+const XO_MUSIC_PLAYER: &[u8] = include_bytes!("../../../assets/games/xomusicplayer.ch8");
+const XO_MUSIC_PLAYER_2: &[u8] = include_bytes!("../../../assets/games/xomusicplayer2.ch8");
+const NYAN: &[u8] = include_bytes!("../../../assets/games/NYAN.xo8");
+
+// Instructions per frame, high enough for the programs to keep up with their timers
+const CPU_SPEED: u32 = 200;
+
+/// Create a device using the XO-Chip profile, with audio disabled
+fn xo_chip_device(rom: &[u8]) -> C8 {
+    let profile = COMPATIBILITY_PROFILES
+        .iter()
+        .find(|profile| profile.device == CompatibilityDevice::XOChip)
+        .expect("XO-Chip profile");
+
+    let mut device = C8::default();
+    device.set_compatibility_profile(profile);
+    device.load_rom(rom);
+    device.audio_device.set_audio_settings(AudioSettings {
+        enabled: false,
+        ..AudioSettings::default()
+    });
+    device
+}
+
+/// Step the device for a single frame, failing on errors and unknown instructions
+fn step_frame(device: &mut C8) -> Vec<DeviceMessage> {
+    let messages = device.step(CPU_SPEED);
+
+    for message in &messages {
+        assert!(
+            !matches!(message, DeviceMessage::UnknownOpCode(_)),
+            "unexpected message: {message:?}"
+        );
+    }
+
+    messages
+}
+
+#[test]
+fn test_xo_music_player_patterns() {
+    let mut device = xo_chip_device(XO_MUSIC_PLAYER);
+    let mut patterns: Vec<Vec<u8>> = Vec::new();
+
+    for _ in 0..300 {
+        step_frame(&mut device);
+
+        let pattern = device.cpu().audio_buffer();
+        if !pattern.is_empty() && !patterns.contains(pattern) {
+            patterns.push(pattern.clone());
+        }
+    }
+
+    // The song is made of several different waveforms, including silence
+    assert!(patterns.len() >= 4, "patterns: {patterns:?}");
+    assert!(patterns.iter().all(|pattern| pattern.len() == 16));
+    assert!(patterns.contains(&vec![0; 16]));
+}
+
+#[test]
+fn test_xo_music_player_pitch() {
+    let mut device = xo_chip_device(XO_MUSIC_PLAYER_2);
+    let mut pitches = Vec::new();
+
+    for _ in 0..300 {
+        step_frame(&mut device);
+
+        let pitch = device.cpu().buffer_pitch();
+        if !pitches.contains(&pitch) {
+            pitches.push(pitch);
+        }
+    }
+
+    // The song plays a single waveform at several pitches set with FX3A
+    for pitch in [0x93, 0x7F, 0x7B, 0x77, 0x73] {
+        assert!(pitches.contains(&pitch), "pitches: {pitches:?}");
+    }
+}
+
+#[test]
+fn test_nyan_planes() {
+    let mut device = xo_chip_device(NYAN);
+    let mut messages = Vec::new();
+
+    for _ in 0..120 {
+        messages.extend(step_frame(&mut device));
+    }
+
+    assert!(messages.contains(&DeviceMessage::ChangeResolution(DisplayResolution::High)));
+    assert_eq!(device.display().resolution(), DisplayResolution::High);
+
+    // The animation is drawn using both planes, so all four colors are used
+    let display = device.display();
+    let mut colors = [false; 4];
+    for (&p0, &p1) in display.zipped_iterator() {
+        colors[usize::from((p0 << 1) | p1)] = true;
+    }
+
+    assert_eq!(colors, [true; 4]);
+}
@@ -0,0 +1,1421 @@
+use std::collections::{HashMap, VecDeque};
+
+use c8::PROGRAM_START;
+
+use crate::{
+    error::AssembleError,
+    lexer::{tokenize, Token},
+    Options,
+};
+
+/// Largest address which can be written to
+const MAX_ADDRESS: usize = 0xFFFF;
+
+/// Maximum number of macro expansions, stops recursive macros from running forever
+const MAX_MACRO_EXPANSIONS: usize = 65536;
+
+/// Octo names of the keyboard keys, mapped to the Chip-8 keypad
+const OCTO_KEYS: [(&str, u8); 16] = [
+    ("OCTO_KEY_1", 0x1),
+    ("OCTO_KEY_2", 0x2),
+    ("OCTO_KEY_3", 0x3),
+    ("OCTO_KEY_4", 0xC),
+    ("OCTO_KEY_Q", 0x4),
+    ("OCTO_KEY_W", 0x5),
+    ("OCTO_KEY_E", 0x6),
+    ("OCTO_KEY_R", 0xD),
+    ("OCTO_KEY_A", 0x7),
+    ("OCTO_KEY_S", 0x8),
+    ("OCTO_KEY_D", 0x9),
+    ("OCTO_KEY_F", 0xE),
+    ("OCTO_KEY_Z", 0xA),
+    ("OCTO_KEY_X", 0x0),
+    ("OCTO_KEY_C", 0xB),
+    ("OCTO_KEY_V", 0xF),
+];
+
+/// Words which can't be used as names
+const RESERVED: &[&str] = &[
+    ":",
+    ":alias",
+    ":assert",
+    ":breakpoint",
+    ":byte",
+    ":call",
+    ":calc",
+    ":const",
+    ":macro",
+    ":monitor",
+    ":next",
+    ":org",
+    ":pointer",
+    ":stringmode",
+    ":unpack",
+    ";",
+    "return",
+    "clear",
+    "bcd",
+    "save",
+    "load",
+    "delay",
+    "buzzer",
+    "pitch",
+    "sprite",
+    "jump",
+    "jump0",
+    "native",
+    "exit",
+    "hires",
+    "lores",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "saveflags",
+    "loadflags",
+    "plane",
+    "audio",
+    "loop",
+    "again",
+    "while",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "key",
+    "-key",
+    "hex",
+    "bighex",
+    "long",
+    "random",
+    "i",
+    ":=",
+    "+=",
+    "-=",
+    "=-",
+    "|=",
+    "&=",
+    "^=",
+    ">>=",
+    "<<=",
+    "==",
+    "!=",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "-",
+    "{",
+    "}",
+];
+
+/// Unary operators of calculated constants
+const CALC_UNARY: &[&str] = &[
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor", "@",
+];
+
+/// Binary operators of calculated constants
+const CALC_BINARY: &[&str] = &[
+    "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=",
+    ">=", ">",
+];
+
+/// A reference to a label which hasn't been defined yet
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    /// The low 12 bits of the instruction at the index
+    Address(usize),
+
+    /// The 16 bit word at the index
+    Long(usize),
+
+    /// The byte at the index is set to the high bits of the address, after the nibble if there is one
+    UnpackHigh(usize, Option<u8>),
+
+    /// The byte at the index is set to the low byte of the address
+    UnpackLow(usize),
+}
+
+/// An address operand which may not be known yet
+enum AddressValue {
+    /// A number, constant or defined label
+    Known(u16),
+
+    /// A label which hasn't been defined yet
+    Forward(String),
+}
+
+/// Right hand side of a register operation or condition
+enum Operand {
+    Register(u8),
+    Value(u8),
+}
+
+/// A macro defined with `:macro`
+struct Macro {
+    /// Names of the arguments
+    arguments: Vec<String>,
+
+    /// Tokens between the braces
+    body: Vec<Token>,
+
+    /// Number of times the macro has been used
+    calls: usize,
+}
+
+/// Octo assembler state
+pub(crate) struct Assembler {
+    options: Options,
+
+    /// Tokens left to assemble, macros are expanded in place
+    tokens: VecDeque<Token>,
+
+    /// Last token taken from the queue, used for errors at the end of the source
+    last: Option<Token>,
+
+    /// Program bytes starting at `PROGRAM_START`, None where nothing was written
+    rom: Vec<Option<u8>>,
+
+    /// Address of the next byte
+    here: usize,
+
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+
+    /// Forward references to labels, with the token which used them
+    protos: HashMap<String, Vec<(Fixup, Token)>>,
+
+    /// Start addresses of open loops
+    loops: Vec<(usize, Token)>,
+
+    /// Jumps out of loops made by `while`, None marks the start of a loop
+    whiles: Vec<Option<usize>>,
+
+    /// Jumps to the end of open `begin` and `else` blocks
+    branches: Vec<(usize, Token)>,
+
+    /// True while the first instruction is a placeholder for a jump to `main`
+    jump_to_main: bool,
+
+    /// Number of macros expanded
+    expansions: usize,
+}
+
+impl Assembler {
+    /// Create an assembler for a source
+    pub(crate) fn new(source: &str, options: Options) -> Self {
+        let constants = OCTO_KEYS
+            .iter()
+            .map(|&(name, key)| (name.to_string(), f64::from(key)))
+            .collect();
+
+        // Registers used by pseudo instructions
+        let aliases = [
+            ("unpack-hi", 0x0),
+            ("unpack-lo", 0x1),
+            ("compare-temp", 0xE),
+        ]
+        .iter()
+        .map(|&(name, register)| (name.to_string(), register))
+        .collect();
+
+        Self {
+            options,
+            tokens: tokenize(source).into(),
+            last: None,
+            rom: Vec::new(),
+            here: usize::from(PROGRAM_START),
+            labels: HashMap::new(),
+            constants,
+            aliases,
+            macros: HashMap::new(),
+            protos: HashMap::new(),
+            loops: Vec::new(),
+            whiles: Vec::new(),
+            branches: Vec::new(),
+            jump_to_main: true,
+            expansions: 0,
+        }
+    }
+
+    /// Assemble the source into ROM bytes
+    pub(crate) fn assemble(mut self) -> Result<Vec<u8>, AssembleError> {
+        // Reserve space for a jump to main, it is removed if main is the first label
+        self.emit_word(0x0000)?;
+
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if let Some((_, token)) = self.loops.last() {
+            return Err(error(
+                token,
+                "This 'loop' does not have a matching 'again'.",
+            ));
+        }
+        if let Some((_, token)) = self.branches.last() {
+            return Err(error(
+                token,
+                format!("This '{}' does not have a matching 'end'.", token.text),
+            ));
+        }
+        if let Some((name, uses)) = self.protos.iter().min_by_key(|(_, uses)| {
+            uses.first()
+                .map(|(_, token)| (token.line, token.column))
+                .unwrap_or_default()
+        }) {
+            return Err(error(&uses[0].1, format!("Undefined name '{name}'.")));
+        }
+
+        let Some(&main) = self.labels.get("main") else {
+            return Err(AssembleError::new(
+                1,
+                1,
+                "This program is missing a 'main' label.",
+            ));
+        };
+
+        if self.jump_to_main {
+            if main > 0xFFF {
+                return Err(AssembleError::new(
+                    1,
+                    1,
+                    "The 'main' label must be within the first 4 KiB.",
+                ));
+            }
+
+            let [high, low] = (0x1000 | main).to_be_bytes();
+            self.rom[0] = Some(high);
+            self.rom[1] = Some(low);
+        }
+
+        Ok(self
+            .rom
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+
+    // Intentionally allowing too many lines
+    #[allow(clippy::too_many_lines)]
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                if name.text == "main" && self.jump_to_main && self.here == 0x202 {
+                    // Main is the first label, so the jump isn't needed
+                    self.rom.clear();
+                    self.here = usize::from(PROGRAM_START);
+                    self.jump_to_main = false;
+                }
+                self.define_label(&name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(&name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.constant_value()?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc_block()?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let value = if self.peek_is("{") {
+                    let value = self.calc_block()?;
+                    byte_value(&token, value)?
+                } else {
+                    self.short_value()?
+                };
+                self.emit_byte(value)?;
+            }
+            ":org" => {
+                let value = if self.peek_is("{") {
+                    self.calc_block()?
+                } else {
+                    self.constant_value()?
+                };
+
+                if !(f64::from(PROGRAM_START)..=MAX_ADDRESS as f64).contains(&value) {
+                    return Err(error(
+                        &token,
+                        format!("The address {value} is outside of the program memory."),
+                    ));
+                }
+                self.here = value as usize;
+            }
+            ":call" => self.address_instruction(0x2000, &token)?,
+            ":unpack" => self.unpack()?,
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ":assert" | ":pointer" | ":stringmode" => {
+                return Err(error(
+                    &token,
+                    format!("The '{}' directive isn't supported.", token.text),
+                ));
+            }
+            ";" | "return" => self.emit_word(0x00EE)?,
+            "clear" => self.emit_word(0x00E0)?,
+            "exit" => self.emit_word(0x00FD)?,
+            "lores" => self.emit_word(0x00FE)?,
+            "hires" => self.emit_word(0x00FF)?,
+            "scroll-down" => {
+                let n = self.tiny_value()?;
+                self.emit_word(0x00C0 | u16::from(n))?;
+            }
+            "scroll-up" => {
+                let n = self.tiny_value()?;
+                self.emit_word(0x00D0 | u16::from(n))?;
+            }
+            "scroll-right" => self.emit_word(0x00FB)?,
+            "scroll-left" => self.emit_word(0x00FC)?,
+            "audio" => self.emit_word(0xF002)?,
+            "plane" => {
+                let n = self.tiny_value()?;
+                self.emit_x(0xF001, n)?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit_x(0xF033, x)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit_x(0xF075, x)?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit_x(0xF085, x)?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+
+                if self.peek_is("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    self.emit_xy(if save { 0x5002 } else { 0x5003 }, x, y)?;
+                } else {
+                    self.emit_x(if save { 0xF055 } else { 0xF065 }, x)?;
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let opcode = match token.text.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit_x(opcode, x)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.tiny_value()?;
+                self.emit_xy(0xD000 | u16::from(n), x, y)?;
+            }
+            "jump" => self.address_instruction(0x1000, &token)?,
+            "jump0" => self.address_instruction(0xB000, &token)?,
+            "native" => self.address_instruction(0x0000, &token)?,
+            "loop" => {
+                self.loops.push((self.here, token));
+                self.whiles.push(None);
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(error(&token, "This 'while' is not within a loop."));
+                }
+
+                self.condition(true)?;
+                self.whiles.push(Some(self.here));
+                self.emit_word(0x1000)?;
+            }
+            "again" => {
+                let Some((start, _)) = self.loops.pop() else {
+                    return Err(error(
+                        &token,
+                        "This 'again' does not have a matching 'loop'.",
+                    ));
+                };
+
+                self.emit_word(0x1000)?;
+                self.patch_address(self.here - 2, start, &token)?;
+
+                while let Some(Some(jump)) = self.whiles.pop() {
+                    self.patch_address(jump, self.here, &token)?;
+                }
+            }
+            "if" => {
+                let condition = self.parse_condition()?;
+
+                if self.peek_is("begin") {
+                    let begin = self.next()?;
+                    self.emit_condition(condition, true)?;
+                    self.branches.push((self.here, begin));
+                    self.emit_word(0x1000)?;
+                } else {
+                    self.expect("then")?;
+                    self.emit_condition(condition, false)?;
+                }
+            }
+            "else" => {
+                let Some((jump, _)) = self.branches.pop() else {
+                    return Err(error(
+                        &token,
+                        "This 'else' does not have a matching 'begin'.",
+                    ));
+                };
+
+                let end_jump = self.here;
+                self.emit_word(0x1000)?;
+                self.patch_address(jump, self.here, &token)?;
+                self.branches.push((end_jump, token));
+            }
+            "end" => {
+                let Some((jump, _)) = self.branches.pop() else {
+                    return Err(error(
+                        &token,
+                        "This 'end' does not have a matching 'begin'.",
+                    ));
+                };
+
+                self.patch_address(jump, self.here, &token)?;
+            }
+            "i" => self.index_statement()?,
+            _ => {
+                if let Some(x) = self.register_of(&token) {
+                    self.register_statement(x)?;
+                } else if self.macros.contains_key(&token.text) {
+                    self.expand_macro(&token)?;
+                } else if let Some(value) = self.value_of(&token) {
+                    let byte = byte_value(&token, value)?;
+                    self.emit_byte(byte)?;
+                } else if is_name(&token.text) {
+                    // Any other name is a call to a subroutine, the name is parsed as the address
+                    self.push_front(token.clone());
+                    self.address_instruction(0x2000, &token)?;
+                } else {
+                    return Err(error(
+                        &token,
+                        format!("Unrecognized token '{}'.", token.text),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assemble `i := ...` and `i += vx`
+    fn index_statement(&mut self) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+
+        match operator.text.as_str() {
+            ":=" => {
+                if self.peek_is("hex") || self.peek_is("bighex") {
+                    let font = self.next()?;
+                    let x = self.register()?;
+                    self.emit_x(if font.text == "hex" { 0xF029 } else { 0xF030 }, x)?;
+                } else if self.peek_is("long") {
+                    let long = self.next()?;
+                    self.emit_word(0xF000)?;
+
+                    match self.address_value(true)? {
+                        AddressValue::Known(address) => self.emit_word(address)?,
+                        AddressValue::Forward(name) => {
+                            self.add_fixup(name, Fixup::Long(self.index()), long);
+                            self.emit_word(0x0000)?;
+                        }
+                    }
+                } else {
+                    self.address_instruction(0xA000, &operator)?;
+                }
+            }
+            "+=" => {
+                let x = self.register()?;
+                self.emit_x(0xF01E, x)?;
+            }
+            _ => {
+                return Err(error(
+                    &operator,
+                    format!("Expected ':=' or '+=', got '{}'.", operator.text),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assemble an operation on a register
+    fn register_statement(&mut self, x: u8) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+
+        match operator.text.as_str() {
+            ":=" => {
+                if self.peek_is("random") {
+                    self.next()?;
+                    let mask = self.short_value()?;
+                    self.emit_x(0xC000 | u16::from(mask), x)?;
+                } else if self.peek_is("key") {
+                    self.next()?;
+                    self.emit_x(0xF00A, x)?;
+                } else if self.peek_is("delay") {
+                    self.next()?;
+                    self.emit_x(0xF007, x)?;
+                } else {
+                    match self.operand()? {
+                        Operand::Register(y) => self.emit_xy(0x8000, x, y)?,
+                        Operand::Value(value) => self.emit_x(0x6000 | u16::from(value), x)?,
+                    }
+                }
+            }
+            "+=" => match self.operand()? {
+                Operand::Register(y) => self.emit_xy(0x8004, x, y)?,
+                Operand::Value(value) => self.emit_x(0x7000 | u16::from(value), x)?,
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(y) => self.emit_xy(0x8005, x, y)?,
+                Operand::Value(value) => {
+                    self.emit_x(0x7000 | u16::from(value.wrapping_neg()), x)?;
+                }
+            },
+            "|=" | "&=" | "^=" | "=-" | ">>=" | "<<=" => {
+                let y = self.register()?;
+                let opcode = match operator.text.as_str() {
+                    "|=" => 0x8001,
+                    "&=" => 0x8002,
+                    "^=" => 0x8003,
+                    "=-" => 0x8007,
+                    ">>=" => 0x8006,
+                    _ => 0x800E,
+                };
+                self.emit_xy(opcode, x, y)?;
+            }
+            _ => {
+                return Err(error(
+                    &operator,
+                    format!("Unrecognized operator '{}'.", operator.text),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse the condition of an `if` or `while`
+    fn parse_condition(&mut self) -> Result<(u8, Token, Option<Operand>), AssembleError> {
+        let x = self.register()?;
+        let comparison = self.next()?;
+
+        let operand = match comparison.text.as_str() {
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(self.operand()?),
+            _ => {
+                return Err(error(
+                    &comparison,
+                    format!("Expected a comparison, got '{}'.", comparison.text),
+                ))
+            }
+        };
+
+        Ok((x, comparison, operand))
+    }
+
+    /// Parse and emit the condition of a `while`
+    fn condition(&mut self, negated: bool) -> Result<(), AssembleError> {
+        let condition = self.parse_condition()?;
+        self.emit_condition(condition, negated)
+    }
+
+    /// Emit instructions which skip the next instruction unless the condition is true
+    ///
+    /// A negated condition skips the next instruction if the condition is true.
+    fn emit_condition(
+        &mut self,
+        (x, comparison, operand): (u8, Token, Option<Operand>),
+        negated: bool,
+    ) -> Result<(), AssembleError> {
+        let comparison = match (negated, comparison.text.as_str()) {
+            (false, text) => text.to_string(),
+            (true, "==") => "!=".to_string(),
+            (true, "!=") => "==".to_string(),
+            (true, "key") => "-key".to_string(),
+            (true, "-key") => "key".to_string(),
+            (true, "<") => ">=".to_string(),
+            (true, ">") => "<=".to_string(),
+            (true, "<=") => ">".to_string(),
+            (true, ">=") => "<".to_string(),
+            (true, text) => text.to_string(),
+        };
+
+        match (comparison.as_str(), operand) {
+            ("key", _) => self.emit_x(0xE0A1, x),
+            ("-key", _) => self.emit_x(0xE09E, x),
+            ("==", Some(Operand::Register(y))) => self.emit_xy(0x9000, x, y),
+            ("==", Some(Operand::Value(value))) => self.emit_x(0x4000 | u16::from(value), x),
+            ("!=", Some(Operand::Register(y))) => self.emit_xy(0x5000, x, y),
+            ("!=", Some(Operand::Value(value))) => self.emit_x(0x3000 | u16::from(value), x),
+            (comparison, Some(operand)) => {
+                // Comparisons subtract using the temporary register and test the borrow flag
+                let temp = if self.options.legacy_comparisons {
+                    0xF
+                } else {
+                    self.aliases["compare-temp"]
+                };
+                match operand {
+                    Operand::Register(y) => self.emit_xy(0x8000, temp, y)?,
+                    Operand::Value(value) => self.emit_x(0x6000 | u16::from(value), temp)?,
+                }
+
+                let (subtract, skip) = match comparison {
+                    ">" => (0x8005, 0x3F01),
+                    "<" => (0x8007, 0x3F01),
+                    ">=" => (0x8007, 0x4F01),
+                    _ => (0x8005, 0x4F01),
+                };
+                let skip = match (self.options.legacy_comparisons, skip) {
+                    (true, 0x3F01) => 0x4F00,
+                    (true, _) => 0x3F00,
+                    (false, skip) => skip,
+                };
+                self.emit_xy(subtract, temp, x)?;
+                self.emit_word(skip)
+            }
+            (_, None) => unreachable!("comparisons always have an operand"),
+        }
+    }
+
+    /// Assemble `:unpack nibble label` or `:unpack long label`
+    fn unpack(&mut self) -> Result<(), AssembleError> {
+        let nibble = if self.peek_is("long") {
+            self.next()?;
+            None
+        } else {
+            Some(self.tiny_value()?)
+        };
+
+        let high = self.aliases["unpack-hi"];
+        let low = self.aliases["unpack-lo"];
+        let token = self.peek_token()?;
+
+        match self.address_value(nibble.is_none())? {
+            AddressValue::Known(address) => {
+                let [high_byte, low_byte] = address.to_be_bytes();
+                let high_byte = match nibble {
+                    Some(nibble) => (nibble << 4) | high_byte,
+                    None => high_byte,
+                };
+
+                self.emit_x(0x6000 | u16::from(high_byte), high)?;
+                self.emit_x(0x6000 | u16::from(low_byte), low)?;
+            }
+            AddressValue::Forward(name) => {
+                self.add_fixup(
+                    name.clone(),
+                    Fixup::UnpackHigh(self.index() + 1, nibble),
+                    token.clone(),
+                );
+                self.emit_x(0x6000, high)?;
+                self.add_fixup(name, Fixup::UnpackLow(self.index() + 1), token);
+                self.emit_x(0x6000, low)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a `:macro` definition
+    fn define_macro(&mut self) -> Result<(), AssembleError> {
+        let name = self.name()?;
+        let mut arguments = Vec::new();
+
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            arguments.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+
+        loop {
+            let token = self.tokens.pop_front().ok_or_else(|| {
+                error(
+                    &name,
+                    format!("The macro '{}' does not have a closing '}}'.", name.text),
+                )
+            })?;
+
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(
+            name.text,
+            Macro {
+                arguments,
+                body,
+                calls: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Replace a macro call with the macro body
+    fn expand_macro(&mut self, token: &Token) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(error(
+                token,
+                "Too many macro expansions, is a macro recursive?",
+            ));
+        }
+
+        let argument_count = self.macros[&token.text].arguments.len();
+        let mut values = HashMap::new();
+        for index in 0..argument_count {
+            let value = self.next()?;
+            values.insert(self.macros[&token.text].arguments[index].clone(), value);
+        }
+
+        let Some(definition) = self.macros.get_mut(&token.text) else {
+            return Ok(());
+        };
+        let calls = definition.calls;
+        definition.calls += 1;
+
+        let expanded: Vec<Token> = definition
+            .body
+            .iter()
+            .map(|body_token| match values.get(&body_token.text) {
+                Some(value) => value.clone(),
+                None if body_token.text == "CALLS" => Token {
+                    text: calls.to_string(),
+                    ..body_token.clone()
+                },
+                None => body_token.clone(),
+            })
+            .collect();
+
+        for body_token in expanded.into_iter().rev() {
+            self.push_front(body_token);
+        }
+
+        Ok(())
+    }
+
+    /// Parse `{ expression }` and evaluate it
+    fn calc_block(&mut self) -> Result<f64, AssembleError> {
+        self.expect("{")?;
+        let value = self.calc()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    /// Evaluate an expression, operators have no precedence and are evaluated right to left
+    fn calc(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next()?;
+
+        let value = if CALC_UNARY.contains(&token.text.as_str()) {
+            let value = self.calc()?;
+            match token.text.as_str() {
+                "-" => -value,
+                "~" => !(value as i64) as f64,
+                "!" => f64::from(u8::from(value == 0.0)),
+                "sin" => value.sin(),
+                "cos" => value.cos(),
+                "tan" => value.tan(),
+                "exp" => value.exp(),
+                "log" => value.ln(),
+                "abs" => value.abs(),
+                "sqrt" => value.sqrt(),
+                "sign" => {
+                    if value == 0.0 {
+                        0.0
+                    } else {
+                        value.signum()
+                    }
+                }
+                "ceil" => value.ceil(),
+                "floor" => value.floor(),
+                _ => self.rom_byte(value),
+            }
+        } else {
+            self.calc_terminal(&token)?
+        };
+
+        let Some(operator) = self
+            .tokens
+            .front()
+            .filter(|next| CALC_BINARY.contains(&next.text.as_str()))
+            .cloned()
+        else {
+            return Ok(value);
+        };
+
+        self.next()?;
+        let rhs = self.calc()?;
+        let (a, b) = (value as i64, rhs as i64);
+
+        Ok(match operator.text.as_str() {
+            "-" => value - rhs,
+            "+" => value + rhs,
+            "*" => value * rhs,
+            "/" | "%" if rhs == 0.0 => return Err(error(&operator, "Division by zero.")),
+            "/" => value / rhs,
+            "%" => value % rhs,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or_default() as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or_default() as f64,
+            "pow" => value.powf(rhs),
+            "min" => value.min(rhs),
+            "max" => value.max(rhs),
+            "<" => f64::from(u8::from(value < rhs)),
+            "<=" => f64::from(u8::from(value <= rhs)),
+            "==" => f64::from(u8::from(value == rhs)),
+            "!=" => f64::from(u8::from(value != rhs)),
+            ">=" => f64::from(u8::from(value >= rhs)),
+            _ => f64::from(u8::from(value > rhs)),
+        })
+    }
+
+    /// Evaluate a single value of an expression
+    fn calc_terminal(&mut self, token: &Token) -> Result<f64, AssembleError> {
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => {
+                if let Some(value) = self.value_of(token) {
+                    Ok(value)
+                } else if let Some(&address) = self.labels.get(&token.text) {
+                    Ok(f64::from(address))
+                } else if let Some(register) = self.register_of(token) {
+                    Ok(f64::from(register))
+                } else {
+                    Err(error(
+                        token,
+                        format!("Undefined name '{}' in calculated constant.", token.text),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Get a byte which has already been written
+    fn rom_byte(&self, address: f64) -> f64 {
+        let index = (address as usize).wrapping_sub(usize::from(PROGRAM_START));
+        let byte = self.rom.get(index).copied().flatten().unwrap_or_default();
+        f64::from(byte)
+    }
+
+    /// Assemble an instruction with a 12 bit address operand
+    fn address_instruction(&mut self, opcode: u16, token: &Token) -> Result<(), AssembleError> {
+        match self.address_value(false)? {
+            AddressValue::Known(address) => self.emit_word(opcode | address),
+            AddressValue::Forward(name) => {
+                self.add_fixup(name, Fixup::Address(self.index()), token.clone());
+                self.emit_word(opcode)
+            }
+        }
+    }
+
+    /// Parse an address, which is a 16 bit value if `long` is set and 12 bits otherwise
+    fn address_value(&mut self, long: bool) -> Result<AddressValue, AssembleError> {
+        let token = self.next()?;
+        let max = if long { 0xFFFF } else { 0xFFF };
+
+        let value = match self.value_of(&token) {
+            Some(value) => value,
+            None => match self.labels.get(&token.text) {
+                Some(&address) => f64::from(address),
+                None if is_name(&token.text) && self.register_of(&token).is_none() => {
+                    return Ok(AddressValue::Forward(token.text));
+                }
+                None => {
+                    return Err(error(
+                        &token,
+                        format!("Expected an address, got '{}'.", token.text),
+                    ))
+                }
+            },
+        };
+
+        if !(0.0..=f64::from(max)).contains(&value) {
+            return Err(error(
+                &token,
+                format!(
+                    "The address '{}' must be in the range [0, {max:#X}].",
+                    token.text
+                ),
+            ));
+        }
+
+        Ok(AddressValue::Known(value as u16))
+    }
+
+    /// Parse a register or an 8 bit value
+    fn operand(&mut self) -> Result<Operand, AssembleError> {
+        let token = self.peek_token()?;
+
+        match self.register_of(&token) {
+            Some(register) => {
+                self.next()?;
+                Ok(Operand::Register(register))
+            }
+            None => Ok(Operand::Value(self.short_value()?)),
+        }
+    }
+
+    /// Parse an 8 bit value, negative values are stored as two's complement
+    fn short_value(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        let value = self.value_of(&token).ok_or_else(|| {
+            error(
+                &token,
+                format!("Expected a number or constant, got '{}'.", token.text),
+            )
+        })?;
+
+        byte_value(&token, value)
+    }
+
+    /// Parse a 4 bit value
+    fn tiny_value(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        let value = self.value_of(&token).ok_or_else(|| {
+            error(
+                &token,
+                format!("Expected a number or constant, got '{}'.", token.text),
+            )
+        })?;
+
+        if !(0.0..=15.0).contains(&value) {
+            return Err(error(
+                &token,
+                format!("The value '{}' must be in the range [0, 15].", token.text),
+            ));
+        }
+
+        Ok(value as u8)
+    }
+
+    /// Parse the value of a constant, which can be a number, constant or defined label
+    fn constant_value(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next()?;
+
+        self.value_of(&token)
+            .or_else(|| self.labels.get(&token.text).copied().map(f64::from))
+            .ok_or_else(|| {
+                error(
+                    &token,
+                    format!("Expected a number or constant, got '{}'.", token.text),
+                )
+            })
+    }
+
+    /// Get the value of a number or constant
+    fn value_of(&self, token: &Token) -> Option<f64> {
+        parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+    }
+
+    /// Parse a register or alias
+    fn register(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+
+        self.register_of(&token).ok_or_else(|| {
+            error(
+                &token,
+                format!("Expected a register, got '{}'.", token.text),
+            )
+        })
+    }
+
+    /// Get the register of a register name or alias
+    fn register_of(&self, token: &Token) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(&token.text) {
+            return Some(register);
+        }
+
+        parse_register(&token.text)
+    }
+
+    /// Parse a name for a label, constant, alias or macro
+    fn name(&mut self) -> Result<Token, AssembleError> {
+        let token = self.next()?;
+
+        if !is_name(&token.text) {
+            return Err(error(
+                &token,
+                format!("The name '{}' is reserved.", token.text),
+            ));
+        }
+
+        Ok(token)
+    }
+
+    /// Define a label and resolve the references to it
+    fn define_label(&mut self, name: &Token, address: usize) -> Result<(), AssembleError> {
+        if self.labels.contains_key(&name.text) {
+            return Err(error(
+                name,
+                format!("The label '{}' has already been defined.", name.text),
+            ));
+        }
+
+        let address = address as u16;
+        self.labels.insert(name.text.clone(), address);
+
+        for (fixup, token) in self.protos.remove(&name.text).unwrap_or_default() {
+            match fixup {
+                Fixup::Address(index) => {
+                    self.patch_address(
+                        index + usize::from(PROGRAM_START),
+                        usize::from(address),
+                        &token,
+                    )?;
+                }
+                Fixup::Long(index) => {
+                    let [high, low] = address.to_be_bytes();
+                    self.rom[index] = Some(high);
+                    self.rom[index + 1] = Some(low);
+                }
+                Fixup::UnpackHigh(index, nibble) => {
+                    let [high, _] = address.to_be_bytes();
+                    self.rom[index] = Some(match nibble {
+                        Some(_) if address > 0xFFF => {
+                            return Err(error(
+                                &token,
+                                format!(
+                                    "The label '{}' must be within the first 4 KiB.",
+                                    name.text
+                                ),
+                            ))
+                        }
+                        Some(nibble) => (nibble << 4) | high,
+                        None => high,
+                    });
+                }
+                Fixup::UnpackLow(index) => {
+                    let [_, low] = address.to_be_bytes();
+                    self.rom[index] = Some(low);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record a reference to a label which hasn't been defined yet
+    fn add_fixup(&mut self, name: String, fixup: Fixup, token: Token) {
+        self.protos.entry(name).or_default().push((fixup, token));
+    }
+
+    /// Set the address of the instruction at an address
+    fn patch_address(
+        &mut self,
+        at: usize,
+        address: usize,
+        token: &Token,
+    ) -> Result<(), AssembleError> {
+        if address > 0xFFF {
+            return Err(error(
+                token,
+                format!("The address {address:#06X} is outside of the first 4 KiB."),
+            ));
+        }
+
+        let index = at - usize::from(PROGRAM_START);
+        let [high, low] = (address as u16).to_be_bytes();
+        self.rom[index] = self.rom[index].map(|byte| (byte & 0xF0) | high);
+        self.rom[index + 1] = Some(low);
+
+        Ok(())
+    }
+
+    /// Get the ROM index of the next byte
+    fn index(&self) -> usize {
+        self.here - usize::from(PROGRAM_START)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AssembleError> {
+        if self.here > MAX_ADDRESS {
+            let token = self.last.clone().unwrap_or_else(start_token);
+            return Err(error(&token, "The program is too large."));
+        }
+
+        let index = self.index();
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, None);
+        }
+
+        if self.rom[index].is_some() {
+            let token = self.last.clone().unwrap_or_else(start_token);
+            return Err(error(
+                &token,
+                format!(
+                    "Data overlap, address {:#06X} has already been defined.",
+                    self.here
+                ),
+            ));
+        }
+
+        self.rom[index] = Some(byte);
+        self.here += 1;
+
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AssembleError> {
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_x(&mut self, opcode: u16, x: u8) -> Result<(), AssembleError> {
+        self.emit_word(opcode | (u16::from(x) << 8))
+    }
+
+    fn emit_xy(&mut self, opcode: u16, x: u8, y: u8) -> Result<(), AssembleError> {
+        self.emit_word(opcode | (u16::from(x) << 8) | (u16::from(y) << 4))
+    }
+
+    fn next(&mut self) -> Result<Token, AssembleError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = Some(token.clone());
+                Ok(token)
+            }
+            None => {
+                let token = self.last.clone().unwrap_or_else(start_token);
+                Err(error(&token, "Unexpected end of the program."))
+            }
+        }
+    }
+
+    fn peek_token(&self) -> Result<Token, AssembleError> {
+        self.tokens.front().cloned().ok_or_else(|| {
+            let token = self.last.clone().unwrap_or_else(start_token);
+            error(&token, "Unexpected end of the program.")
+        })
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn push_front(&mut self, token: Token) {
+        self.tokens.push_front(token);
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AssembleError> {
+        let token = self.next()?;
+
+        if token.text == text {
+            Ok(token)
+        } else {
+            Err(error(
+                &token,
+                format!("Expected '{text}', got '{}'.", token.text),
+            ))
+        }
+    }
+}
+
+/// Check the range of an 8 bit value and convert it to a byte
+fn byte_value(token: &Token, value: f64) -> Result<u8, AssembleError> {
+    if !(-128.0..=255.0).contains(&value) {
+        return Err(error(
+            token,
+            format!(
+                "The value '{}' must be in the range [-128, 255].",
+                token.text
+            ),
+        ));
+    }
+
+    Ok((value as i64 & 0xFF) as u8)
+}
+
+/// Parse a decimal, `0x` hexadecimal or `0b` binary number
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+/// Parse a register name, `v0` to `vf`
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+/// Check if a word can be used as a name
+fn is_name(text: &str) -> bool {
+    !RESERVED.contains(&text)
+        && parse_register(text).is_none()
+        && parse_number(text).is_none()
+        && !text.starts_with(|c: char| c.is_ascii_digit() || c == ':')
+}
+
+fn start_token() -> Token {
+    Token {
+        text: String::new(),
+        line: 1,
+        column: 1,
+    }
+}
+
+fn error(token: &Token, message: impl Into<String>) -> AssembleError {
+    AssembleError::new(token.line, token.column, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assemble, AssembleError};
+
+    fn assemble_words(source: &str) -> Vec<u16> {
+        let rom = assemble(source).unwrap_or_else(|error| panic!("{error}"));
+        rom.chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or_default()]))
+            .collect()
+    }
+
+    #[test]
+    fn test_main_jump() {
+        assert_eq!(assemble_words(": main clear"), [0x00E0]);
+        assert_eq!(
+            assemble_words(": data 0x12 0x34 : main jump main"),
+            [0x1204, 0x1234, 0x1204]
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "
+            : main
+            loop
+                while v0 != 5
+                if v0 == 1 begin
+                    v1 := 2
+                else
+                    v1 := 3
+                end
+            again
+        ";
+
+        assert_eq!(
+            assemble_words(source),
+            [0x4005, 0x1210, 0x3001, 0x120C, 0x6102, 0x120E, 0x6103, 0x1200]
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let source = "
+            :alias counter v3
+            :const SPEED 2
+            :macro add-speed register { register += SPEED }
+            :calc DOUBLE { SPEED * 2 + 1 }
+            : main
+            add-speed counter
+            :byte DOUBLE
+            :byte { DOUBLE - 1 }
+            :org 0x300
+            i := long ship
+            :unpack 0xA ship
+            : ship
+        ";
+
+        let rom = assemble(source).unwrap();
+        assert_eq!(&rom[..4], &[0x73, 0x02, 0x06, 0x05]);
+        assert_eq!(
+            &rom[0x100..],
+            &[0xF0, 0x00, 0x03, 0x08, 0x60, 0xA3, 0x61, 0x08]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble(": main\n  v0 := 256"),
+            Err(AssembleError::new(
+                2,
+                9,
+                "The value '256' must be in the range [-128, 255]."
+            ))
+        );
+        assert_eq!(
+            assemble(": main\n  missing"),
+            Err(AssembleError::new(2, 3, "Undefined name 'missing'."))
+        );
+        assert_eq!(
+            assemble("loop again"),
+            Err(AssembleError::new(
+                1,
+                1,
+                "This program is missing a 'main' label."
+            ))
+        );
+    }
+}
@@ -0,0 +1,32 @@
+/// An error found while assembling a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    /// Line of the error, starting from 1
+    pub line: usize,
+
+    /// Column of the error, starting from 1
+    pub column: usize,
+
+    /// Description of the error
+    pub message: String,
+}
+
+impl AssembleError {
+    /// Create an error at a position in the source
+    #[must_use]
+    pub fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
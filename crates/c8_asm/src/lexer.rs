@@ -0,0 +1,59 @@
+/// A whitespace separated word of the source
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token {
+    /// Text of the token
+    pub(crate) text: String,
+
+    /// Line of the first character, starting from 1
+    pub(crate) line: usize,
+
+    /// Column of the first character, starting from 1
+    pub(crate) column: usize,
+}
+
+/// Split the source into tokens, comments start with `#` and run to the end of the line
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut current: Option<Token> = None;
+
+        for (column_index, character) in line.chars().enumerate() {
+            if character.is_whitespace() {
+                tokens.extend(current.take());
+                continue;
+            }
+
+            match current.as_mut() {
+                Some(token) => token.text.push(character),
+                None if character == '#' => break,
+                None => {
+                    current = Some(Token {
+                        text: character.to_string(),
+                        line: line_index + 1,
+                        column: column_index + 1,
+                    });
+                }
+            }
+        }
+
+        tokens.extend(current);
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("# comment\n  v0 := 0x12 # comment\n\t: main");
+        let texts: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+
+        assert_eq!(texts, ["v0", ":=", "0x12", ":", "main"]);
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 6));
+        assert_eq!((tokens[3].line, tokens[3].column), (3, 2));
+    }
+}
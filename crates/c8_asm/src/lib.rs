@@ -0,0 +1,38 @@
+//! Octo assembler
+
+/// Assembler errors
+pub mod error;
+
+mod assembler;
+mod lexer;
+
+pub use error::AssembleError;
+
+/// Options which change the generated code
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Options {
+    /// Compile the `<`, `>`, `<=` and `>=` comparisons like early versions of Octo did, using `vf`
+    /// as the temporary register instead of the `compare-temp` alias
+    pub legacy_comparisons: bool,
+}
+
+/// Assemble Octo source code into ROM bytes
+///
+/// The ROM starts at `PROGRAM_START`, and begins with a jump to the `main` label unless `main` is
+/// the first label.
+///
+/// # Errors
+///
+/// Returns the first error found in the source, with the line and column it was found at.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_options(source, Options::default())
+}
+
+/// Assemble Octo source code into ROM bytes using options
+///
+/// # Errors
+///
+/// Returns the first error found in the source, with the line and column it was found at.
+pub fn assemble_with_options(source: &str, options: Options) -> Result<Vec<u8>, AssembleError> {
+    assembler::Assembler::new(source, options).assemble()
+}
@@ -0,0 +1,42 @@
+//! Rebuild the bundled Octo programs and check them against the shipped ROMs
+
+use c8_asm::{assemble_with_options, Options};
+
+fn check_rom(source: &str, rom: &[u8], options: Options) {
+    let assembled =
+        assemble_with_options(source, options).unwrap_or_else(|error| panic!("{error}"));
+
+    let mismatch = assembled.iter().zip(rom).position(|(a, b)| a != b);
+    assert_eq!(mismatch, None, "first mismatch at byte {mismatch:?}");
+    assert_eq!(assembled.len(), rom.len());
+}
+
+#[test]
+fn test_octo_sample() {
+    check_rom(
+        include_str!("../../../assets/games/octo-sample/prog.8o"),
+        include_bytes!("../../../assets/games/octo-sample/octo-sample.ch8"),
+        Options::default(),
+    );
+}
+
+// Glitch Ghost was built with an early version of Octo
+#[test]
+fn test_glitch_ghost() {
+    check_rom(
+        include_str!("../../../assets/games/glitch-ghost/prog.8o"),
+        include_bytes!("../../../assets/games/glitch-ghost/glitch-ghost.ch8"),
+        Options {
+            legacy_comparisons: true,
+        },
+    );
+}
+
+#[test]
+fn test_skyward() {
+    check_rom(
+        include_str!("../../../assets/games/skyward/skyward.8o"),
+        include_bytes!("../../../assets/games/skyward/skyward.ch8"),
+        Options::default(),
+    );
+}
@@ -0,0 +1,335 @@
+//! Headless Chip-8 runner for scripted runs and regression tests
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use c8::{
+    audio::recorder::AudioRecorder,
+    device::C8,
+    quirks::{CompatibilityDevice, Quirks, COMPATIBILITY_PROFILES},
+    random::RandomSource,
+    recording::Recording,
+    screenshot::{Screenshot, ScreenshotColors, MAX_SCREENSHOT_SCALE},
+    trace::Tracer,
+};
+use clap::{Parser, ValueEnum};
+
+use crate::script::KeyScript;
+
+/// Scripted key input
+mod script;
+
+/// Run a Chip-8 ROM without a window
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// ROM to run, `.8o` sources are assembled first
+    rom: PathBuf,
+
+    /// Compatibility profile
+    #[arg(long, value_enum, default_value_t = Profile::Default)]
+    profile: Profile,
+
+    /// Override a quirk of the profile, e.g. `v_blank=false` or `compat-clip=true`
+    #[arg(long = "quirk", value_name = "NAME=BOOL", value_parser = parse_quirk)]
+    quirks: Vec<(String, bool)>,
+
+    /// Instructions per frame, ignored by profiles with the COSMAC VIP timing
+    #[arg(long, default_value_t = 20)]
+    cycles: u32,
+
+    /// Number of 60 Hz frames to run
+    #[arg(long, default_value_t = 600)]
+    frames: u32,
+
+    /// Key input script, with lines like `120 down 5` and `130 up 5`
+    #[arg(long, value_name = "FILE")]
+    keys: Option<PathBuf>,
+
+    /// Seed of the random number generator
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Write the final display, as PNG if the path ends in `.png` and as text otherwise
+    #[arg(long, value_name = "FILE")]
+    display: Option<PathBuf>,
+
+    /// Record every frame, as an animated GIF if the path ends in `.gif` and as numbered PNG
+    /// files in a directory otherwise
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Write the sound as a WAV file
+    #[arg(long, value_name = "FILE")]
+    audio: Option<PathBuf>,
+
+    /// Upscale of PNG displays and recordings, each display pixel becomes a square of this size
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=MAX_SCREENSHOT_SCALE as i64))]
+    scale: u8,
+
+    /// Write a dump of the whole memory
+    #[arg(long, value_name = "FILE")]
+    memory: Option<PathBuf>,
+
+    /// Write the device messages, one per line after the frame number
+    #[arg(long, value_name = "FILE")]
+    messages: Option<PathBuf>,
+
+    /// Write an execution trace
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+}
+
+/// Compatibility profiles which can be selected
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Profile {
+    /// Octo defaults
+    Default,
+
+    /// COSMAC VIP Chip-8
+    Chip8,
+
+    /// Super Chip
+    SuperChip,
+
+    /// XO-Chip
+    XoChip,
+}
+
+impl Profile {
+    /// Get the device of the profile
+    fn device(self) -> CompatibilityDevice {
+        match self {
+            Profile::Default => CompatibilityDevice::Default,
+            Profile::Chip8 => CompatibilityDevice::Chip8,
+            Profile::SuperChip => CompatibilityDevice::SuperChip,
+            Profile::XoChip => CompatibilityDevice::XOChip,
+        }
+    }
+}
+
+/// Parse a `NAME=BOOL` quirk override
+fn parse_quirk(text: &str) -> Result<(String, bool), String> {
+    let (name, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=BOOL, found '{text}'"))?;
+    let value = value
+        .parse()
+        .map_err(|_| format!("expected true or false, found '{value}'"))?;
+
+    // Check the name now, so mistakes are reported with the other argument errors
+    set_quirk(&mut Quirks::default(), name, value)?;
+
+    Ok((name.to_string(), value))
+}
+
+/// Set a quirk by its field name or Octo option name
+fn set_quirk(quirks: &mut Quirks, name: &str, value: bool) -> Result<(), String> {
+    let quirk = match name {
+        "vf_zero" | "compat-logic" => &mut quirks.vf_zero,
+        "i_incremented" | "compat-load" => &mut quirks.i_incremented,
+        "vx_shifted_directly" | "compat-shift" => &mut quirks.vx_shifted_directly,
+        "v_blank" | "compat-vblank" => &mut quirks.v_blank,
+        "clip_sprites" | "compat-clip" => &mut quirks.clip_sprites,
+        "jump_bits" | "compat-jump0" => &mut quirks.jump_bits,
+        _ => return Err(format!("unknown quirk '{name}'")),
+    };
+
+    *quirk = value;
+    Ok(())
+}
+
+/// Create an output file, `-` writes to stdout
+fn create_output(path: &Path) -> std::io::Result<Box<dyn Write>> {
+    if path == Path::new("-") {
+        Ok(Box::new(std::io::stdout().lock()))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Load a ROM, assembling `.8o` sources
+fn load_rom(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+
+    if path.extension().is_some_and(|extension| extension == "8o") {
+        Ok(c8_asm::assemble(&String::from_utf8_lossy(&data))?)
+    } else {
+        Ok(data)
+    }
+}
+
+fn run(args: &Args) -> Result<ExitCode, Box<dyn Error>> {
+    let rom = load_rom(&args.rom)?;
+    let script = match &args.keys {
+        Some(path) => KeyScript::parse(&std::fs::read_to_string(path)?)?,
+        None => KeyScript::default(),
+    };
+
+    let profile = COMPATIBILITY_PROFILES
+        .iter()
+        .find(|profile| profile.device == args.profile.device())
+        .copied()
+        .unwrap_or_default();
+    let mut quirks = profile.quirks;
+    for (name, value) in &args.quirks {
+        set_quirk(&mut quirks, name, *value)?;
+    }
+
+    let mut c8 = C8::default();
+    c8.set_compatibility_profile(&profile);
+    c8.set_quirks(quirks);
+    c8.set_random_source(RandomSource::Seeded(args.seed));
+    c8.load_rom(&rom);
+
+    if let Some(path) = &args.trace {
+        c8.set_tracer(Some(Tracer::writer(create_output(path)?)));
+    }
+
+    if args.audio.is_some() {
+        c8.set_audio_recorder(Some(AudioRecorder::new()));
+    }
+
+    // Run the frames, stopping early if the program exits or the device halts
+    let colors = ScreenshotColors::default();
+    let mut recording = args.record.as_ref().map(|_| Recording::new());
+    let mut messages = Vec::new();
+    let mut halted = None;
+    for frame in 0..args.frames {
+        script.apply(frame, c8.keypad_mut());
+
+        messages.extend(c8.step(args.cycles).into_iter().map(|m| (frame, m)));
+
+        if let Some(error) = c8.execution_error() {
+            halted = Some((frame, error));
+            break;
+        }
+
+        if let Some(recording) = recording.as_mut() {
+            recording.record(c8.display(), &colors);
+        }
+
+        if !c8.is_running() {
+            break;
+        }
+    }
+
+    if let Some(mut tracer) = c8.set_tracer(None) {
+        tracer.flush()?;
+    }
+
+    if let Some(path) = &args.display {
+        let mut writer = create_output(path)?;
+        if path.extension().is_some_and(|extension| extension == "png") {
+            Screenshot::capture(c8.display(), &colors, usize::from(args.scale))
+                .write_png(&mut writer)?;
+        } else {
+            writer.write_all(c8.display().to_text().as_bytes())?;
+        }
+        writer.flush()?;
+    }
+
+    if let (Some(path), Some(recording)) = (&args.record, &recording) {
+        let scale = usize::from(args.scale);
+        if path.extension().is_some_and(|extension| extension == "gif") {
+            let mut writer = BufWriter::new(File::create(path)?);
+            recording.write_gif(&mut writer, scale)?;
+            writer.flush()?;
+        } else {
+            std::fs::create_dir_all(path)?;
+            recording.write_png_frames(path, scale)?;
+        }
+    }
+
+    if let (Some(path), Some(recorder)) = (&args.audio, c8.set_audio_recorder(None)) {
+        let mut writer = create_output(path)?;
+        recorder.write_wav(&mut writer)?;
+        writer.flush()?;
+    }
+
+    if let Some(path) = &args.memory {
+        let mut writer = create_output(path)?;
+        writer.write_all(c8.memory().data())?;
+        writer.flush()?;
+    }
+
+    if let Some(path) = &args.messages {
+        let mut writer = create_output(path)?;
+        for (frame, message) in &messages {
+            writeln!(writer, "{frame} {message:?}")?;
+        }
+        writer.flush()?;
+    }
+
+    match halted {
+        Some((frame, error)) => {
+            eprintln!("Device halted at frame {frame}: {error}");
+            Ok(ExitCode::FAILURE)
+        }
+        None => Ok(ExitCode::SUCCESS),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_quirks() {
+        assert_eq!(
+            parse_quirk("v_blank=true"),
+            Ok(("v_blank".to_string(), true))
+        );
+        assert!(parse_quirk("compat-clip=false").is_ok());
+        assert!(parse_quirk("v_blank").is_err());
+        assert!(parse_quirk("v_blank=yes").is_err());
+        assert!(parse_quirk("unknown=true").is_err());
+
+        let mut quirks = Quirks::default();
+        set_quirk(&mut quirks, "compat-jump0", true).unwrap();
+        assert!(quirks.jump_bits);
+    }
+
+    #[test]
+    fn test_arguments() {
+        Args::command().debug_assert();
+
+        let args = Args::try_parse_from([
+            "chip8-cli",
+            "rom.ch8",
+            "--profile",
+            "super-chip",
+            "--quirk",
+            "v_blank=true",
+            "--frames",
+            "10",
+        ])
+        .unwrap();
+        assert_eq!(args.profile, Profile::SuperChip);
+        assert_eq!(args.quirks, [("v_blank".to_string(), true)]);
+        assert_eq!(args.frames, 10);
+        assert_eq!(args.cycles, 20);
+        assert_eq!(args.scale, 1);
+
+        assert!(Args::try_parse_from(["chip8-cli", "rom.ch8", "--scale", "0"]).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use c8::keypad::{Keypad, KEYPAD_KEYS};
+
+/// Error in a key input script
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    /// Line of the error, starting at 1
+    pub line: usize,
+
+    /// Description of the error
+    pub message: String,
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Key script line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// A key press or release
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeyEvent {
+    /// Frame the event happens at, before the frame runs
+    frame: u32,
+
+    /// Index of the key, 0 to F
+    key: usize,
+
+    /// Whether the key is pressed or released
+    pressed: bool,
+}
+
+/// Key presses and releases at fixed frames
+///
+/// Each line is `FRAME down KEY` or `FRAME up KEY`, with the key as a hex digit, and `#` starts
+/// a comment. Keys stay down until they are released.
+#[derive(Debug, Clone, Default)]
+pub struct KeyScript {
+    /// Events in frame order
+    events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    /// Parse a key script
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut events = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ScriptError {
+                line: index + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [frame, action, key] = words[..] else {
+                if words.is_empty() {
+                    continue;
+                }
+
+                return Err(error("expected FRAME down|up KEY".to_string()));
+            };
+
+            let frame = frame
+                .parse()
+                .map_err(|_| error(format!("invalid frame '{frame}'")))?;
+            let pressed = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error(format!("expected down or up, found '{action}'"))),
+            };
+            let key = match usize::from_str_radix(key, 16) {
+                Ok(index) if key.len() == 1 => index,
+                _ => return Err(error(format!("invalid key '{key}'"))),
+            };
+
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+
+        // Note: The sort is stable, so events of the same frame keep their order
+        events.sort_by_key(|event| event.frame);
+
+        Ok(Self { events })
+    }
+
+    /// Update the keypad with the events of a frame
+    pub fn apply(&self, frame: u32, keypad: &mut Keypad) {
+        let start = self.events.partition_point(|event| event.frame < frame);
+
+        for event in self.events[start..]
+            .iter()
+            .take_while(|event| event.frame == frame)
+        {
+            if let Some(key) = KEYPAD_KEYS.iter().find(|key| key.key_index() == event.key) {
+                keypad.set_key(key, event.pressed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use c8::keypad::KeypadKey;
+
+    use super::*;
+
+    #[test]
+    fn test_key_script() {
+        let script = KeyScript::parse(
+            "# Start the game\n\
+             10 down 5\n\
+             \n\
+             20 up 5 # Let go\n\
+             10 down a\n",
+        )
+        .unwrap();
+
+        let mut keypad = Keypad::default();
+        script.apply(9, &mut keypad);
+        assert!(!keypad.is_key_pressed(&KeypadKey::Num5));
+
+        script.apply(10, &mut keypad);
+        assert!(keypad.is_key_pressed(&KeypadKey::Num5));
+        assert!(keypad.is_key_pressed(&KeypadKey::A));
+
+        script.apply(20, &mut keypad);
+        assert!(!keypad.is_key_pressed(&KeypadKey::Num5));
+        assert!(keypad.is_key_pressed(&KeypadKey::A));
+    }
+
+    #[test]
+    fn test_key_script_errors() {
+        let error = |text: &str| KeyScript::parse(text).unwrap_err();
+
+        assert_eq!(error("1 down").line, 1);
+        assert_eq!(error("\nx down 1").message, "invalid frame 'x'");
+        assert_eq!(
+            error("1 hold 1").message,
+            "expected down or up, found 'hold'"
+        );
+        assert_eq!(error("1 down 10").message, "invalid key '10'");
+    }
+}
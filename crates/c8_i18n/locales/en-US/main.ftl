@@ -22,13 +22,40 @@ game_roms = Games
 ### Rom Controls
 reload_rom = Reload ROM
 unload_rom = Unload ROM
+octo_options = Octo Options
+import_options = Import options.json
+import_options_hover = Apply the CPU speed, colors, quirks and font of an Octo options.json file, one next to an opened ROM is applied automatically
+export_options = Export options.json
+save_cartridge = Save Cartridge
+save_cartridge_hover = Save the ROM and settings as an Octo cartridge GIF, labeled with the current screen
+rom_database_applied = {"Configured from the ROM database: "}
+rom_keys = {"Keys: "}
+revert = Revert
+revert_hover = Restore the settings from before the ROM was configured and reload it
+dismiss = Dismiss
+load_failed = {"Failed to load "}
+flags_not_saved = {"Failed to save the flags: "}
+screenshot = Screenshot
+screenshot_hover = Save the display as a PNG image
+record = Record
+record_hover = Record the display and sound, to save as an animated GIF, PNG frames or a WAV file
+save_recording = Save Recording
+recording_length = {"Length: "}
+save_gif = Save GIF
+save_wav = Save WAV
+save_png_frames = Save PNG Frames
+save_png_frames_hover = Save a numbered PNG file for every frame in a folder, e.g. for ffmpeg
+discard_recording = Discard
 
 # Controls
 
 ## CPU
 cpu_speed = CPU Speed
 speed = Speed
-speed_hover = The number of instructions executed per frame (the UI updates at 60Hz/16ms)
+speed_hover = The number of instructions executed per 60Hz tick, independent of the display refresh rate
+timing_fixed = Fixed
+timing_cosmac_vip = VIP accurate
+timing_cosmac_vip_hover = Run instructions at the speed of the original COSMAC VIP interpreter
 
 ## Display
 display = Display
@@ -36,6 +63,7 @@ scale = Scale
 display_fullscreen = Fullscreen
 display_underneath = Display Underneath
 display_underneath_hover = Draw the display underneath the rest of the UI
+screenshot_scale = Screenshot Scale
 
 ## Pixel
 pixel_colors = Pixel Colors
@@ -74,6 +102,31 @@ language = Language
 font_small = Small Font
 font_large = Large Font
 font_hover = Font usage may not be supported by all ROMs
+random = Random Numbers
+random_hover = Source of the random numbers used by programs, seeded sources make runs reproducible
+random_thread = Random
+random_seeded = Seeded
+random_vip_style = VIP Style
+seed = {"Seed: "}
+rewind = Rewind
+rewind_hover = Hold Backspace to step backwards in time
+rom_database = Configure Known ROMs
+rom_database_hover = Apply the profile, speed, colors and font of ROMs found in the ROM database when they are loaded
+load_rom_database = Load ROM Database
+load_rom_database_hover = {"Add the ROMs of a programs.json file from the CHIP-8 database, ROMs known: "}
+rom_settings = ROM Settings
+rom_settings_saved = This ROM uses its own settings
+rom_settings_global = This ROM uses the global settings
+save_rom_settings = Save for This ROM
+save_rom_settings_hover = Save the speed, colors, quirks, keyboard and audio settings as the defaults of this ROM
+reset_rom_settings = Reset to Global
+reset_rom_settings_hover = Forget the settings of this ROM and go back to the global settings
+error_policy = Error Handling
+error_policy_hover = What happens when a program accesses invalid memory or misuses the stack
+error_policy_halt = Halt
+error_policy_wrap = Wrap Addresses
+error_policy_report = Report and Continue
+halted = {"Halted: "}
 
 
 ## Audio
@@ -86,6 +139,35 @@ enable_audio = Enable Audio
 ## Visualizer
 memory = Memory
 registers = Registers
+stack = Stack
+address = {"Address: "}
+length = {"Length: "}
+pause = Pause
+continue = Continue
+step_into = Step Into
+step_over = Step Over
+step_out = Step Out
+add_breakpoint = Add Breakpoint
+add_watchpoint = Add Watchpoint
+breakpoint_condition = Condition, e.g. v3 == 0x10 && i >= 0x300
+breakpoint_log = Log message, e.g. x = {"{"}v0{"}"}
+breakpoint_log_hover = Log the message instead of pausing, expressions in braces are replaced by their value, add :x for hexadecimal
+hit_count = {"Hit Count: "}
+hit_count_hover = Only pause once the breakpoint was hit this many times
+expression_hover = Registers v0-vf, i, pc, delay and sound, memory bytes mem[address] and C operators
+watch_expression = Watch expression
+add_watch = Add Watch
+clear_log = Clear Log
+trace = Trace
+trace_hover = Record the latest instructions with the registers before each one runs
+trace_start = Start Trace
+trace_stop = Stop Trace
+breakpoint_trace_hover = Pause at the breakpoint, or start or stop the trace instead of pausing
+copy_trace = Copy Trace
+watch_read = Read
+watch_write = Write
+watch_read_write = Read/Write
+watch_register_hover = Pause when the register changes
 
 # Devices
 chip8 = Chip-8
@@ -1,4 +1,4 @@
-use crate::roms::{GAME_ROMS, ROM, TEST_ROMS};
+use crate::roms::{GAME_ROMS, ROM, ROM_DATABASE, TEST_ROMS};
 
 use super::{
     is_mobile,
@@ -7,13 +7,26 @@ use super::{
 };
 
 use c8::{
-    audio::audio_settings::AudioSettings,
+    audio::{audio_settings::AudioSettings, recorder::AudioRecorder},
+    cartridge::Cartridge,
+    debugger::{Breakpoint, MAX_LOG_MESSAGES, MEMORY_ACCESSES, MemoryAccess, WatchedRegister},
     device::C8,
     display::DisplayResolution,
-    fonts::FONT_DATA,
+    error::{ERROR_POLICIES, ErrorPolicy},
+    expression::{Expression, ExpressionError, LogMessage},
+    fonts::{FONT_DATA, FontName, FontSize},
     keypad::KEYPAD_KEYS,
     message::DeviceMessage,
-    quirks::{COMPATIBILITY_PROFILES, CompatibilityProfile, Quirks},
+    octo_options::{OctoOptions, OptionsWarning},
+    quirks::{COMPATIBILITY_PROFILES, CompatibilityDevice, CompatibilityProfile, Quirks},
+    random::RandomSource,
+    recording::{RECORDING_FPS, Recording},
+    rewind::RewindBuffer,
+    rom_database::{RomDatabase, RomInfo},
+    rom_sha1,
+    screenshot::{MAX_SCREENSHOT_SCALE, Screenshot, ScreenshotColors},
+    timing::CpuTiming,
+    trace::{TRACE_ACTIONS, TraceAction, Tracer},
 };
 use c8_i18n::{
     locale_text::LocaleText,
@@ -22,9 +35,26 @@ use c8_i18n::{
 use egui::{Color32, TextureOptions, Vec2};
 use fluent_templates::Loader;
 use rfd::AsyncFileDialog;
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::Duration,
+};
 use unic_langid::LanguageIdentifier;
 
+#[cfg(not(target_arch = "wasm32"))]
+use c8::flags::FileFlagStorage;
+#[cfg(target_arch = "wasm32")]
+use c8::flags::{FlagStorage, MemoryFlagStorage};
+
+/// Name of the app, which is also the name of its storage directory
+pub const APP_NAME: &str = "Chip 8";
+
+// Key of the RPL user flags in the eframe storage on the web
+#[cfg(target_arch = "wasm32")]
+const FLAGS_KEY: &str = "rpl_flags";
+
 // 60 seems to be a good default, Octo uses 20
 const DEFAULT_CPU_SPEED: u32 = 20;
 
@@ -32,8 +62,22 @@ const DEFAULT_DISPLAY_SIZE: Vec2 = Vec2::new(512.0, 256.0);
 
 const DEFAULT_DISPLAY_SCALE: f32 = 1.0;
 
+// Holding this key steps the emulator backwards in time
+const REWIND_KEY: egui::Key = egui::Key::Backspace;
+
+// Pressing this key saves a screenshot of the display
+const SCREENSHOT_KEY: egui::Key = egui::Key::F12;
+
+const DEFAULT_SCREENSHOT_SCALE: usize = 4;
+
+// Number of trace lines kept in memory
+const TRACE_BUFFER_LINES: usize = 10_000;
+
+// Size of the memory view in the visualizer panel
+const MEMORY_VIEW_ROWS: usize = 16;
+const MEMORY_VIEW_COLUMNS: usize = 8;
+
 /// The application state
-// TODO: Add rom-specific settings
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct AppUI {
@@ -55,6 +99,30 @@ pub struct AppUI {
     #[serde(skip)]
     file_name: Rc<RefCell<Option<String>>>,
 
+    // Octo `options.json` file found next to the ROM or imported separately
+    #[serde(skip)]
+    file_options: Rc<RefCell<Option<String>>>,
+
+    // ROM database file picked to be added to the database
+    #[serde(skip)]
+    file_database: Rc<RefCell<Option<String>>>,
+
+    // Metadata of known ROMs, used to configure them when they are loaded
+    #[serde(skip)]
+    rom_database: RomDatabase,
+
+    // Shown after the ROM database configured a ROM, until it is dismissed or reverted
+    #[serde(skip)]
+    database_notice: Option<DatabaseNotice>,
+
+    // Shown when an opened file couldn't be loaded, until it is dismissed or a ROM is loaded
+    #[serde(skip)]
+    load_error: Option<String>,
+
+    // Shown when the flags of the ROM couldn't be saved, until it is dismissed
+    #[serde(skip)]
+    flags_error: Option<String>,
+
     // The ROM file
     #[serde(skip)]
     rom_file: Vec<u8>,
@@ -62,13 +130,86 @@ pub struct AppUI {
     #[serde(skip)]
     rom_name: String,
 
+    // SHA-1 of the ROM, the key of its saved settings
+    #[serde(skip)]
+    rom_hash: String,
+
+    // Font from before the current ROM was configured, restored with the global settings
+    #[serde(skip)]
+    global_font: Option<FontName>,
+
     // The Chip8 device
     #[serde(skip)]
     c8_device: C8,
 
+    // RPL user flags of the device, shared with the app so they can be saved with eframe
+    #[cfg(target_arch = "wasm32")]
+    #[serde(skip)]
+    rpl_flags: Rc<RefCell<MemoryFlagStorage>>,
+
+    // History of the device used for rewinding
+    #[serde(skip)]
+    rewind_buffer: RewindBuffer,
+
     #[serde(skip)]
     debug_window: bool,
 
+    // Address entered for a new breakpoint
+    #[serde(skip)]
+    breakpoint_address: u16,
+
+    // First address shown in the memory view, also used for new watchpoints
+    #[serde(skip)]
+    memory_address: u16,
+
+    // Condition, hit count, log message and trace action of new breakpoints
+    #[serde(skip)]
+    breakpoint_condition: String,
+
+    #[serde(skip)]
+    breakpoint_hit_target: u32,
+
+    #[serde(skip)]
+    breakpoint_log: String,
+
+    #[serde(skip)]
+    breakpoint_trace: Option<TraceAction>,
+
+    #[serde(skip)]
+    breakpoint_error: Option<String>,
+
+    // Expressions evaluated every frame in the visualizer panel
+    #[serde(skip)]
+    watch_expression: String,
+
+    #[serde(skip)]
+    watch_expressions: Vec<Expression>,
+
+    #[serde(skip)]
+    watch_error: Option<String>,
+
+    // Frames recorded for a GIF or PNG frames, and whether the display is being recorded
+    #[serde(skip)]
+    recording: Recording,
+
+    #[serde(skip)]
+    is_recording: bool,
+
+    // Sound rendered while recording, to save as a WAV file
+    #[serde(skip)]
+    audio_recording: Option<AudioRecorder>,
+
+    // Messages logged by logpoints
+    #[serde(skip)]
+    debug_log: VecDeque<String>,
+
+    // Length and access of new memory watchpoints
+    #[serde(skip)]
+    watch_length: u16,
+
+    #[serde(skip)]
+    watch_access: MemoryAccess,
+
     language: LocaleText,
 
     settings: Settings,
@@ -82,13 +223,41 @@ impl Default for AppUI {
             display_handle: None,
             rom_file: Vec::new(),
             rom_name: String::new(),
+            rom_hash: String::new(),
+            global_font: None,
             c8_device: C8::default(),
+            #[cfg(target_arch = "wasm32")]
+            rpl_flags: Rc::new(RefCell::new(MemoryFlagStorage::default())),
+            rewind_buffer: RewindBuffer::default(),
 
             file_data: Rc::new(RefCell::new(None)),
             file_name: Rc::new(RefCell::new(None)),
+            file_options: Rc::new(RefCell::new(None)),
+            file_database: Rc::new(RefCell::new(None)),
+            rom_database: RomDatabase::parse(ROM_DATABASE).unwrap_or_default(),
+            database_notice: None,
+            load_error: None,
+            flags_error: None,
 
             debug_window: false,
 
+            breakpoint_address: c8::PROGRAM_START,
+            breakpoint_condition: String::new(),
+            breakpoint_hit_target: 0,
+            breakpoint_log: String::new(),
+            breakpoint_trace: None,
+            breakpoint_error: None,
+            watch_expression: String::new(),
+            watch_expressions: Vec::new(),
+            watch_error: None,
+            debug_log: VecDeque::new(),
+            recording: Recording::new(),
+            is_recording: false,
+            audio_recording: None,
+            memory_address: c8::PROGRAM_START,
+            watch_length: 1,
+            watch_access: MemoryAccess::Write,
+
             language: LocaleText::default(),
             settings: Settings::default(),
         }
@@ -101,6 +270,9 @@ struct Settings {
     // The CPU speed
     cpu_speed: u32,
 
+    // Whether the CPU speed is fixed or follows the COSMAC VIP timing
+    cpu_timing: CpuTiming,
+
     // The pixel colors
     pixel_colors: PixelColors,
 
@@ -116,6 +288,9 @@ struct Settings {
     // Quirk settings
     quirk_settings: Quirks,
 
+    // The device of the selected compatibility profile, used for the stack depth
+    compatibility_device: CompatibilityDevice,
+
     // Display in fullscreen
     display_fullscreen: bool,
 
@@ -125,18 +300,105 @@ struct Settings {
     key_mapping: KeyboardMapping,
 
     audio_settings: AudioSettings,
+
+    // Whether the device history is recorded for rewinding
+    rewind_enabled: bool,
+
+    // Source of random numbers for the device
+    random_source: RandomSource,
+
+    // How execution errors are handled
+    error_policy: ErrorPolicy,
+
+    // Upscale of saved screenshots
+    screenshot_scale: usize,
+
+    // Whether known ROMs are configured from the ROM database when they are loaded
+    rom_database_enabled: bool,
+
+    // Settings saved for specific ROMs, by the SHA-1 of the ROM
+    rom_settings: HashMap<String, RomSettings>,
+
+    // Settings from before the current ROM was configured by the ROM database, options or its saved
+    // settings, restored when another ROM is loaded or the ROM is unloaded
+    global_settings: Option<RomSettings>,
+}
+
+// Flag storage shared between the device and the app
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
+struct SharedFlagStorage(Rc<RefCell<MemoryFlagStorage>>);
+
+#[cfg(target_arch = "wasm32")]
+impl FlagStorage for SharedFlagStorage {
+    fn load_flags(&self, key: &str) -> Option<Vec<u8>> {
+        self.0.borrow().load_flags(key)
+    }
+
+    fn save_flags(&mut self, key: &str, flags: &[u8]) -> std::io::Result<()> {
+        self.0.borrow_mut().save_flags(key, flags)
+    }
+}
+
+// Settings which can be saved for a specific ROM
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+struct RomSettings {
+    cpu_speed: u32,
+    cpu_timing: CpuTiming,
+    pixel_colors: PixelColors,
+    quirk_settings: Quirks,
+    compatibility_device: CompatibilityDevice,
+    key_mapping: KeyboardMapping,
+    audio_settings: AudioSettings,
+}
+
+impl RomSettings {
+    fn from_settings(settings: &Settings) -> Self {
+        Self {
+            cpu_speed: settings.cpu_speed,
+            cpu_timing: settings.cpu_timing,
+            pixel_colors: settings.pixel_colors,
+            quirk_settings: settings.quirk_settings,
+            compatibility_device: settings.compatibility_device,
+            key_mapping: settings.key_mapping,
+            audio_settings: settings.audio_settings,
+        }
+    }
+
+    fn apply(&self, settings: &mut Settings) {
+        settings.cpu_speed = self.cpu_speed;
+        settings.cpu_timing = self.cpu_timing;
+        settings.pixel_colors = self.pixel_colors;
+        settings.quirk_settings = self.quirk_settings;
+        settings.compatibility_device = self.compatibility_device;
+        settings.key_mapping = self.key_mapping;
+        settings.audio_settings = self.audio_settings;
+    }
+}
+
+// Settings a ROM was configured with from the ROM database, and the settings they replaced
+struct DatabaseNotice {
+    info: RomInfo,
+    cpu_speed: u32,
+    cpu_timing: CpuTiming,
+    pixel_colors: PixelColors,
+    quirk_settings: Quirks,
+    compatibility_device: CompatibilityDevice,
+    font: FontName,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             cpu_speed: DEFAULT_CPU_SPEED,
+            cpu_timing: CpuTiming::default(),
             pixel_colors: PixelColors::default(),
             display_scale: DEFAULT_DISPLAY_SCALE,
 
             control_panel_expanded: true,
             visualizer_panel_expanded: false,
             quirk_settings: Quirks::default(),
+            compatibility_device: CompatibilityDevice::default(),
 
             display_fullscreen: false,
             draw_display_underneath: false,
@@ -144,6 +406,18 @@ impl Default for Settings {
             key_mapping: KeyboardMapping::default(),
 
             audio_settings: AudioSettings::default(),
+
+            rewind_enabled: true,
+
+            random_source: RandomSource::default(),
+            error_policy: ErrorPolicy::default(),
+
+            screenshot_scale: DEFAULT_SCREENSHOT_SCALE,
+
+            rom_database_enabled: true,
+
+            rom_settings: HashMap::new(),
+            global_settings: None,
         }
     }
 }
@@ -152,12 +426,64 @@ impl eframe::App for AppUI {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, self);
+
+        #[cfg(target_arch = "wasm32")]
+        eframe::set_value(storage, FLAGS_KEY, &*self.rpl_flags.borrow());
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Step the emulator
-        let messages = self.c8_device.step(self.settings.cpu_speed);
+        // Run the emulator for the time since the last repaint, so it runs at the same speed
+        // regardless of the refresh rate
+        let elapsed = Duration::try_from_secs_f32(ctx.input(|i| i.unstable_dt)).unwrap_or_default();
+        let ticks = self.c8_device.accumulate_ticks(elapsed);
+
+        // Step the emulator, or step it backwards while the rewind key is held
+        let rewinding = self.settings.rewind_enabled && ctx.input(|i| i.key_down(REWIND_KEY));
+        let mut messages = Vec::new();
+
+        for _ in 0..ticks {
+            if rewinding {
+                if self.rewind_buffer.rewind(&mut self.c8_device) {
+                    self.update_resolution();
+                }
+
+                continue;
+            }
+
+            // Note: Nothing changes while the debugger is paused, so there is nothing to record
+            let new_messages = if self.settings.rewind_enabled
+                && self.c8_device.is_running()
+                && !self.c8_device.debugger().is_paused()
+            {
+                self.rewind_buffer
+                    .step(&mut self.c8_device, self.settings.cpu_speed)
+            } else {
+                self.c8_device.step(self.settings.cpu_speed)
+            };
+
+            messages.extend(new_messages);
+
+            // Note: The error is shown in the menu bar while the device is halted
+            if self.c8_device.execution_error().is_some() {
+                break;
+            }
+
+            if self.is_recording && !self.c8_device.debugger().is_paused() {
+                let colors = ScreenshotColors::from(&self.settings.pixel_colors);
+                self.recording.record(self.c8_device.display(), &colors);
+            }
+        }
+
+        // Print logpoint messages and keep the latest for the visualizer panel
+        for message in self.c8_device.debugger_mut().take_logs() {
+            println!("{message}");
+
+            if self.debug_log.len() >= MAX_LOG_MESSAGES {
+                self.debug_log.pop_front();
+            }
+            self.debug_log.push_back(message);
+        }
 
         // Process messages
         for message in messages.iter() {
@@ -169,6 +495,13 @@ impl eframe::App for AppUI {
                     // TODO: Push to a list
                     //println!("Unknown OpCode: {:#06X}", op_code);
                 }
+                DeviceMessage::Break(_) => {
+                    // Show the reason next to the registers
+                    self.settings.visualizer_panel_expanded = true;
+                }
+                DeviceMessage::FlagsNotSaved(error) => {
+                    self.flags_error = Some(error.to_string());
+                }
                 _ => {}
             }
         }
@@ -223,16 +556,46 @@ impl AppUI {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-        }
+        let mut new_self = if let Some(storage) = cc.storage {
+            eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
+        } else {
+            let mut new_self = Self::default();
+            new_self.settings.control_panel_expanded = !is_mobile(&cc.egui_ctx);
+            new_self
+        };
 
-        let mut new_self = Self::default();
-        new_self.settings.control_panel_expanded = !is_mobile(&cc.egui_ctx);
+        new_self.init_flag_storage(cc.storage);
+
+        // No ROM is loaded, so the settings the last ROM was configured with don't carry over
+        new_self.restore_global_settings();
 
         new_self
     }
 
+    // Keep the RPL user flags in files next to the app state
+    #[cfg(not(target_arch = "wasm32"))]
+    fn init_flag_storage(&mut self, _storage: Option<&dyn eframe::Storage>) {
+        if let Some(directory) = eframe::storage_dir(APP_NAME) {
+            self.c8_device
+                .set_flag_storage(Some(Box::new(FileFlagStorage::new(
+                    directory.join("flags"),
+                ))));
+        }
+    }
+
+    // Keep the RPL user flags in the eframe storage, which is saved with the app state
+    #[cfg(target_arch = "wasm32")]
+    fn init_flag_storage(&mut self, storage: Option<&dyn eframe::Storage>) {
+        if let Some(flags) = storage.and_then(|storage| eframe::get_value(storage, FLAGS_KEY)) {
+            *self.rpl_flags.borrow_mut() = flags;
+        }
+
+        self.c8_device
+            .set_flag_storage(Some(Box::new(SharedFlagStorage(Rc::clone(
+                &self.rpl_flags,
+            )))));
+    }
+
     fn update_resolution(&mut self) {
         let (width, height) = self.c8_device.display().resolution().resolution_size_xy();
         let bg_color = self.settings.pixel_colors.background_color();
@@ -305,31 +668,409 @@ impl AppUI {
         }
     }
 
-    fn load_rom(&mut self, rom_data: Vec<u8>) {
+    // Returns true if the ROM was loaded
+    fn load_rom(&mut self, rom_data: Vec<u8>) -> bool {
         if rom_data.is_empty() {
             eprintln!("ROM data is empty");
-            return;
+            return false;
+        }
+
+        // The settings the previous ROM was configured with don't carry over
+        self.restore_global_settings();
+        self.settings.global_settings = Some(RomSettings::from_settings(&self.settings));
+        self.global_font = Some(self.c8_device.memory().system_font());
+
+        self.rom_hash = rom_sha1(&rom_data);
+        self.apply_rom_database(&rom_data);
+
+        // Saved settings take precedence over the ROM database
+        if let Some(rom_settings) = self.settings.rom_settings.get(&self.rom_hash).copied() {
+            rom_settings.apply(&mut self.settings);
+            self.database_notice = None;
         }
 
         self.reset_display();
         self.c8_device
             .audio_device
             .set_audio_settings(self.settings.audio_settings);
+        self.c8_device
+            .set_random_source(self.settings.random_source);
+        self.c8_device.set_error_policy(self.settings.error_policy);
+        self.c8_device
+            .set_compatibility_profile(&CompatibilityProfile {
+                device: self.settings.compatibility_device,
+                quirks: self.settings.quirk_settings,
+                timing: self.settings.cpu_timing,
+            });
 
         // Assign the rom data to the rom file copy
         self.rom_file = rom_data.clone();
 
         self.c8_device.load_rom(&self.rom_file.clone());
+        self.rewind_buffer.clear();
+        self.load_error = None;
+
+        true
+    }
+
+    // Returns true if the Octo source was assembled and loaded
+    fn load_octo_source(&mut self, name: &str, source: &[u8]) -> bool {
+        match c8_asm::assemble(&String::from_utf8_lossy(source)) {
+            Ok(rom) => self.load_rom(rom),
+            Err(error) => {
+                eprintln!("Failed to assemble {name}: {error}");
+                self.load_error = Some(format!("{name}: {error}"));
+                false
+            }
+        }
+    }
+
+    // Restore the settings and font from before the current ROM was configured
+    fn restore_global_settings(&mut self) {
+        if let Some(global_settings) = self.settings.global_settings.take() {
+            global_settings.apply(&mut self.settings);
+        }
+
+        if let Some(font) = self.global_font.take() {
+            self.c8_device
+                .memory_mut()
+                .load_font_name(font, &FontSize::Small);
+        }
+    }
+
+    // Configure a known ROM from the ROM database, the settings it replaces are kept for reverting
+    fn apply_rom_database(&mut self, rom_data: &[u8]) {
+        self.database_notice = None;
+
+        if !self.settings.rom_database_enabled {
+            return;
+        }
+
+        let Some(info) = self.rom_database.lookup(rom_data).cloned() else {
+            return;
+        };
+
+        eprintln!("Configured {} from the ROM database", info.title);
+
+        self.database_notice = Some(DatabaseNotice {
+            info: info.clone(),
+            cpu_speed: self.settings.cpu_speed,
+            cpu_timing: self.settings.cpu_timing,
+            pixel_colors: self.settings.pixel_colors,
+            quirk_settings: self.settings.quirk_settings,
+            compatibility_device: self.settings.compatibility_device,
+            font: self.c8_device.memory().system_font(),
+        });
+
+        if let Some(profile) = info.profile {
+            self.settings.quirk_settings = profile.quirks;
+            self.settings.compatibility_device = profile.device;
+            self.settings.cpu_timing = profile.timing;
+        }
+
+        if let Some(tickrate) = info.tickrate {
+            self.settings.cpu_speed = tickrate;
+        }
+
+        self.settings.pixel_colors.set_octo_colors(&info.colors);
+
+        if let Some(font) = info.font {
+            self.c8_device
+                .memory_mut()
+                .load_font_name(font, &FontSize::Small);
+        }
+    }
+
+    // Restore the settings the ROM database replaced
+    fn revert_rom_database(&mut self) {
+        let Some(notice) = self.database_notice.take() else {
+            return;
+        };
+
+        self.settings.cpu_speed = notice.cpu_speed;
+        self.settings.cpu_timing = notice.cpu_timing;
+        self.settings.pixel_colors = notice.pixel_colors;
+        self.settings.quirk_settings = notice.quirk_settings;
+        self.settings.compatibility_device = notice.compatibility_device;
+        self.c8_device
+            .set_compatibility_profile(&CompatibilityProfile {
+                device: self.settings.compatibility_device,
+                quirks: self.settings.quirk_settings,
+                timing: self.settings.cpu_timing,
+            });
+        self.c8_device
+            .memory_mut()
+            .load_font_name(notice.font, &FontSize::Small);
+
+        // The ROM may already depend on the replaced settings
+        self.reload_rom();
+    }
+
+    fn import_rom_database(&mut self, json: &str) {
+        match RomDatabase::parse(json) {
+            Ok(database) => {
+                eprintln!("Added {} ROMs to the ROM database", database.len());
+                self.rom_database.merge(database);
+            }
+            Err(error) => eprintln!("Failed to load the ROM database: {error}"),
+        }
+    }
+
+    // Reapply the saved settings of the current ROM, after other settings were applied to it
+    fn apply_rom_settings(&mut self) {
+        let Some(rom_settings) = self.settings.rom_settings.get(&self.rom_hash).copied() else {
+            return;
+        };
+
+        rom_settings.apply(&mut self.settings);
+        self.apply_settings_to_device();
+    }
+
+    // Save the current settings for the current ROM
+    fn save_rom_settings(&mut self) {
+        // Note: The global settings were kept when the ROM was loaded, so they aren't replaced
+        self.settings.rom_settings.insert(
+            self.rom_hash.clone(),
+            RomSettings::from_settings(&self.settings),
+        );
+    }
+
+    // Remove the saved settings of the current ROM and go back to the global settings
+    fn reset_rom_settings(&mut self) {
+        self.settings.rom_settings.remove(&self.rom_hash);
+        self.database_notice = None;
+
+        // The global settings are kept, so they are restored again for the next ROM
+        if let Some(global_settings) = self.settings.global_settings {
+            global_settings.apply(&mut self.settings);
+            self.apply_settings_to_device();
+        }
+    }
+
+    fn apply_settings_to_device(&mut self) {
+        self.c8_device
+            .audio_device
+            .set_audio_settings(self.settings.audio_settings);
+        self.c8_device
+            .set_compatibility_profile(&CompatibilityProfile {
+                device: self.settings.compatibility_device,
+                quirks: self.settings.quirk_settings,
+                timing: self.settings.cpu_timing,
+            });
     }
 
     fn reload_rom(&mut self) {
         self.reset_display();
         self.c8_device.load_rom(&self.rom_file.clone());
+        self.rewind_buffer.clear();
+    }
+
+    fn save_screenshot(&self) {
+        let colors = ScreenshotColors::from(&self.settings.pixel_colors);
+        let screenshot = Screenshot::capture(
+            self.c8_device.display(),
+            &colors,
+            self.settings.screenshot_scale,
+        );
+        let data = screenshot.to_png();
+
+        let file_name = format!("{}.png", self.file_stem());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            futures::executor::block_on(Self::save_file(file_name, "PNG", "png", data));
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(Self::save_file(file_name, "PNG", "png", data));
+        }
+    }
+
+    fn import_octo_options(&mut self, json: &str) {
+        let (options, warnings) = match OctoOptions::parse(json) {
+            Ok(result) => result,
+            Err(error) => {
+                eprintln!("Failed to import options.json: {error}");
+                return;
+            }
+        };
+
+        self.apply_octo_options(&options, &warnings);
+    }
+
+    fn apply_octo_options(&mut self, options: &OctoOptions, warnings: &[OptionsWarning]) {
+        for warning in warnings {
+            eprintln!("options.json: {warning}");
+        }
+
+        if let Some(tickrate) = options.tickrate {
+            self.settings.cpu_speed = tickrate;
+        }
+
+        self.settings.pixel_colors.set_octo_colors(&options.colors);
+
+        // Octo runs every program with its own profile and the quirks from the options
+        self.settings.quirk_settings = options.quirks;
+        self.settings.compatibility_device = CompatibilityDevice::Default;
+        self.settings.cpu_timing = CpuTiming::Fixed;
+        self.c8_device
+            .set_compatibility_profile(&CompatibilityProfile {
+                device: self.settings.compatibility_device,
+                quirks: self.settings.quirk_settings,
+                timing: self.settings.cpu_timing,
+            });
+
+        if let Some(font) = options.font {
+            self.c8_device
+                .memory_mut()
+                .load_font_name(font, &FontSize::Small);
+        }
+    }
+
+    // Get the current settings as Octo options
+    fn octo_options(&self) -> OctoOptions {
+        OctoOptions {
+            tickrate: Some(self.settings.cpu_speed),
+            colors: (&self.settings.pixel_colors).into(),
+            quirks: self.settings.quirk_settings,
+            font: Some(self.c8_device.memory().system_font()),
+        }
+    }
+
+    fn export_octo_options(&self) {
+        let data = self.octo_options().to_json().into_bytes();
+
+        // Note: Octo expects the file name, the ROM name isn't part of it
+        let file_name = "options.json".to_string();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            futures::executor::block_on(Self::save_file(file_name, "JSON", "json", data));
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(Self::save_file(file_name, "JSON", "json", data));
+        }
+    }
+
+    // Returns true if the cartridge was read, assembled and loaded
+    fn load_cartridge(&mut self, name: &str, data: &[u8]) -> bool {
+        let (cartridge, warnings) = match Cartridge::read_gif(data) {
+            Ok(result) => result,
+            Err(error) => {
+                eprintln!("Failed to read the cartridge {name}: {error}");
+                self.load_error = Some(format!("{name}: {error}"));
+                return false;
+            }
+        };
+
+        if !self.load_octo_source(name, cartridge.program.as_bytes()) {
+            return false;
+        }
+
+        // The options of the cartridge take precedence over the ROM database, but not over the
+        // saved settings of the ROM
+        self.database_notice = None;
+        self.apply_octo_options(&cartridge.options, &warnings);
+        self.apply_rom_settings();
+
+        true
+    }
+
+    fn save_cartridge(&self) {
+        let colors = ScreenshotColors::from(&self.settings.pixel_colors);
+        let cartridge = Cartridge::from_rom(&self.rom_file, self.octo_options());
+        let data = cartridge.to_gif(self.c8_device.display(), &colors);
+
+        let file_name = format!("{}.gif", self.file_stem());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            futures::executor::block_on(Self::save_file(file_name, "GIF", "gif", data));
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(Self::save_file(file_name, "GIF", "gif", data));
+        }
+    }
+
+    fn file_stem(&self) -> &str {
+        if self.rom_name.is_empty() {
+            "chip8"
+        } else {
+            &self.rom_name
+        }
+    }
+
+    fn save_recording_gif(&self) {
+        let mut data = Vec::new();
+        if let Err(error) = self
+            .recording
+            .write_gif(&mut data, self.settings.screenshot_scale)
+        {
+            eprintln!("Failed to encode the recording: {error}");
+            return;
+        }
+
+        let file_name = format!("{}.gif", self.file_stem());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            futures::executor::block_on(Self::save_file(file_name, "GIF", "gif", data));
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(Self::save_file(file_name, "GIF", "gif", data));
+        }
+    }
+
+    fn save_recording_wav(&self) {
+        let Some(recorder) = &self.audio_recording else {
+            return;
+        };
+
+        let data = recorder.to_wav();
+        let file_name = format!("{}.wav", self.file_stem());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            futures::executor::block_on(Self::save_file(file_name, "WAV", "wav", data));
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(Self::save_file(file_name, "WAV", "wav", data));
+        }
+    }
+
+    // Note: The web version can't write a directory of files
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_recording_png_frames(&self) {
+        let Some(directory) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+
+        match self
+            .recording
+            .write_png_frames(&directory, self.settings.screenshot_scale)
+        {
+            Ok(count) => eprintln!("Saved {count} frames to {}", directory.display()),
+            Err(error) => eprintln!("Failed to save the recording: {error}"),
+        }
     }
 
     fn unload_rom(&mut self) {
+        self.database_notice = None;
+        self.restore_global_settings();
+        self.rom_hash.clear();
         self.reset_display();
         self.c8_device.reset_device();
+        self.apply_settings_to_device();
+        self.rewind_buffer.clear();
     }
 
     pub fn ui_mobile_portrait(&mut self, ctx: &egui::Context) {
@@ -413,6 +1154,10 @@ impl AppUI {
                 }
             });
         }
+        if ctx.input(|i| i.key_pressed(SCREENSHOT_KEY)) {
+            self.save_screenshot();
+        }
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // Menu bar
             egui::MenuBar::new().ui(ui, |ui| {
@@ -438,22 +1183,56 @@ impl AppUI {
 
                 // Check if the file data has been updated
                 if let Some(file_data) = self.file_data.take() {
+                    let mut is_octo_source = false;
+                    let mut is_cartridge = false;
+                    let mut name = String::new();
+
                     // Update the file name
                     if let Some(file_name) = self.file_name.take() {
-                        let name = file_name.strip_suffix(".ch8").unwrap_or(&file_name);
-                        self.rom_name = name.to_string();
+                        is_octo_source = file_name.ends_with(".8o");
+                        is_cartridge = file_name.ends_with(".gif");
+
+                        let stem = file_name
+                            .strip_suffix(".ch8")
+                            .or_else(|| file_name.strip_suffix(".8o"))
+                            .or_else(|| file_name.strip_suffix(".gif"))
+                            .unwrap_or(&file_name);
+                        name = stem.to_string();
 
                         // Reset the file name
                         self.file_name = Rc::new(RefCell::new(None));
                     }
 
-                    // Load the ROM
-                    self.load_rom(file_data);
+                    // Octo sources are assembled before loading
+                    let loaded = if is_cartridge {
+                        self.load_cartridge(&name, &file_data)
+                    } else if is_octo_source {
+                        self.load_octo_source(&name, &file_data)
+                    } else {
+                        // Load the ROM
+                        self.load_rom(file_data)
+                    };
+
+                    // A failed load keeps the name of the ROM which is still loaded
+                    if loaded {
+                        self.rom_name = name;
+                    }
 
                     // Reset the file data
                     self.file_data = Rc::new(RefCell::new(None));
                 }
 
+                // Apply the options after the ROM, so they take precedence over the ROM database
+                // but not over the saved settings of the ROM
+                if let Some(options) = self.file_options.take() {
+                    self.import_octo_options(&options);
+                    self.apply_rom_settings();
+                }
+
+                if let Some(database) = self.file_database.take() {
+                    self.import_rom_database(&database);
+                }
+
                 if ui
                     .add_enabled(
                         !self.rom_file.is_empty(),
@@ -474,8 +1253,38 @@ impl AppUI {
                     self.unload_rom();
                 }
 
+                self.menu_octo_options(ui);
+
+                if ui
+                    .button(self.language.locale_string("screenshot"))
+                    .on_hover_text(format!(
+                        "{} ({SCREENSHOT_KEY:?})",
+                        self.language.locale_string("screenshot_hover")
+                    ))
+                    .clicked()
+                {
+                    self.save_screenshot();
+                }
+
+                self.menu_recording(ui);
+
                 ui.separator();
 
+                self.menu_database_notice(ui);
+
+                self.menu_load_error(ui);
+
+                self.menu_flags_error(ui);
+
+                if let Some(error) = self.c8_device.execution_error() {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("{}{error}", self.language.locale_string("halted")),
+                    );
+
+                    ui.separator();
+                }
+
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::RIGHT), |ui| {
                     // Global dark/light mode buttons
                     egui::widgets::global_theme_preference_switch(ui);
@@ -555,31 +1364,221 @@ impl AppUI {
             // Clone the file data reference
             let data_clone = Rc::clone(&self.file_data.clone());
             let name_clone = Rc::clone(&self.file_name.clone());
+            let options_clone = Rc::clone(&self.file_options);
 
             #[cfg(not(target_arch = "wasm32"))]
             {
                 futures::executor::block_on(async move {
-                    let (file_data, file_name) = Self::load_file().await;
+                    let (file_data, file_name, file_options) = Self::load_file().await;
 
                     // Update the shared state
                     *data_clone.borrow_mut() = file_data;
                     *name_clone.borrow_mut() = file_name;
+                    *options_clone.borrow_mut() = file_options;
                 });
             }
 
             #[cfg(target_arch = "wasm32")]
             {
                 wasm_bindgen_futures::spawn_local(async move {
-                    let (file_data, file_name) = Self::load_file().await;
+                    let (file_data, file_name, file_options) = Self::load_file().await;
 
                     // Update the shared state
                     *data_clone.borrow_mut() = file_data;
                     *name_clone.borrow_mut() = file_name;
+                    *options_clone.borrow_mut() = file_options;
                 });
             }
         }
     }
 
+    fn menu_octo_options(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button(self.language.locale_string("octo_options"), |ui| {
+            if ui
+                .button(self.language.locale_string("import_options"))
+                .on_hover_text(self.language.locale_string("import_options_hover"))
+                .clicked()
+            {
+                let options_clone = Rc::clone(&self.file_options);
+
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    futures::executor::block_on(async move {
+                        *options_clone.borrow_mut() = Self::load_json_file().await;
+                    });
+                }
+
+                #[cfg(target_arch = "wasm32")]
+                {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        *options_clone.borrow_mut() = Self::load_json_file().await;
+                    });
+                }
+
+                ui.close();
+            }
+
+            if ui
+                .button(self.language.locale_string("export_options"))
+                .clicked()
+            {
+                self.export_octo_options();
+                ui.close();
+            }
+
+            ui.separator();
+
+            if ui
+                .add_enabled(
+                    !self.rom_file.is_empty(),
+                    egui::Button::new(self.language.locale_string("save_cartridge")),
+                )
+                .on_hover_text(self.language.locale_string("save_cartridge_hover"))
+                .clicked()
+            {
+                self.save_cartridge();
+                ui.close();
+            }
+        });
+    }
+
+    fn menu_recording(&mut self, ui: &mut egui::Ui) {
+        let was_recording = self.is_recording;
+        ui.toggle_value(
+            &mut self.is_recording,
+            self.language.locale_string("record"),
+        )
+        .on_hover_text(self.language.locale_string("record_hover"));
+
+        // Start a new recording each time, the device renders the sound while recording
+        if self.is_recording && !was_recording {
+            self.recording.clear();
+
+            let mut recorder = AudioRecorder::new();
+            recorder.set_volume(self.settings.audio_settings.volume);
+            self.c8_device.set_audio_recorder(Some(recorder));
+        } else if !self.is_recording && was_recording {
+            self.audio_recording = self.c8_device.set_audio_recorder(None);
+        }
+
+        if self.is_recording || self.recording.is_empty() {
+            return;
+        }
+
+        ui.menu_button(self.language.locale_string("save_recording"), |ui| {
+            let seconds = self.recording.len() as f32 / RECORDING_FPS as f32;
+            ui.label(format!(
+                "{}{seconds:.1}s",
+                self.language.locale_string("recording_length")
+            ));
+
+            if ui.button(self.language.locale_string("save_gif")).clicked() {
+                self.save_recording_gif();
+                ui.close();
+            }
+
+            if ui.button(self.language.locale_string("save_wav")).clicked() {
+                self.save_recording_wav();
+                ui.close();
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui
+                .button(self.language.locale_string("save_png_frames"))
+                .on_hover_text(self.language.locale_string("save_png_frames_hover"))
+                .clicked()
+            {
+                self.save_recording_png_frames();
+                ui.close();
+            }
+
+            if ui
+                .button(self.language.locale_string("discard_recording"))
+                .clicked()
+            {
+                self.recording.clear();
+                self.audio_recording = None;
+                ui.close();
+            }
+        });
+    }
+
+    fn menu_database_notice(&mut self, ui: &mut egui::Ui) {
+        let Some(notice) = &self.database_notice else {
+            return;
+        };
+
+        let mut details = notice.info.authors.join(", ");
+        if !notice.info.keys.is_empty() {
+            let keys: Vec<String> = notice
+                .info
+                .keys
+                .iter()
+                .map(|hint| format!("{} {:X}", hint.action, hint.key))
+                .collect();
+            details = format!(
+                "{details}\n{}{}",
+                self.language.locale_string("rom_keys"),
+                keys.join(", ")
+            );
+        }
+
+        ui.label(format!(
+            "{}{}",
+            self.language.locale_string("rom_database_applied"),
+            notice.info.title
+        ))
+        .on_hover_text(details.trim());
+
+        if ui
+            .button(self.language.locale_string("revert"))
+            .on_hover_text(self.language.locale_string("revert_hover"))
+            .clicked()
+        {
+            self.revert_rom_database();
+        }
+
+        if ui.button(self.language.locale_string("dismiss")).clicked() {
+            self.database_notice = None;
+        }
+
+        ui.separator();
+    }
+
+    fn menu_load_error(&mut self, ui: &mut egui::Ui) {
+        let Some(error) = &self.load_error else {
+            return;
+        };
+
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            format!("{}{error}", self.language.locale_string("load_failed")),
+        );
+
+        if ui.button(self.language.locale_string("dismiss")).clicked() {
+            self.load_error = None;
+        }
+
+        ui.separator();
+    }
+
+    fn menu_flags_error(&mut self, ui: &mut egui::Ui) {
+        let Some(error) = &self.flags_error else {
+            return;
+        };
+
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            format!("{}{error}", self.language.locale_string("flags_not_saved")),
+        );
+
+        if ui.button(self.language.locale_string("dismiss")).clicked() {
+            self.flags_error = None;
+        }
+
+        ui.separator();
+    }
+
     fn menu_about(&self, ui: &mut egui::Ui) {
         ui.menu_button(self.language.locale_string("about"), |ui| {
             let version_label = format!(
@@ -642,6 +1641,10 @@ impl AppUI {
 
                     ui.separator();
 
+                    self.controls_rom_settings(ui);
+
+                    ui.separator();
+
                     self.controls_audio(ui);
 
                     #[cfg(debug_assertions)]
@@ -655,23 +1658,53 @@ impl AppUI {
 
     fn controls_cpu_speed(&mut self, ui: &mut egui::Ui) {
         egui::CollapsingHeader::new(self.language.locale_string("cpu_speed")).show(ui, |ui| {
-            ui.add(
-                egui::Slider::new(&mut self.settings.cpu_speed, 1..=240)
-                    .clamping(egui::SliderClamping::Never)
-                    .text(self.language.locale_string("speed")),
-            )
-            .on_hover_text(self.language.locale_string("speed_hover"));
+            let mut cpu_timing = self.settings.cpu_timing;
+
+            // Note: Changing the speed switches back to the fixed timing
+            if ui
+                .add_enabled(
+                    cpu_timing == CpuTiming::Fixed,
+                    egui::Slider::new(&mut self.settings.cpu_speed, 1..=240)
+                        .clamping(egui::SliderClamping::Never)
+                        .text(self.language.locale_string("speed")),
+                )
+                .on_hover_text(self.language.locale_string("speed_hover"))
+                .changed()
+            {
+                cpu_timing = CpuTiming::Fixed;
+            }
 
             ui.horizontal(|ui| {
                 if ui.button(self.language.locale_string("default")).clicked() {
                     self.settings.cpu_speed = DEFAULT_CPU_SPEED;
+                    cpu_timing = CpuTiming::Fixed;
                 }
                 for speed in (500..=2000).step_by(500) {
                     if ui.button(speed.to_string()).clicked() {
                         self.settings.cpu_speed = speed;
+                        cpu_timing = CpuTiming::Fixed;
                     }
                 }
             });
+
+            if ui
+                .selectable_label(
+                    cpu_timing == CpuTiming::CosmacVip,
+                    self.language.locale_string("timing_cosmac_vip"),
+                )
+                .on_hover_text(self.language.locale_string("timing_cosmac_vip_hover"))
+                .clicked()
+            {
+                cpu_timing = match cpu_timing {
+                    CpuTiming::Fixed => CpuTiming::CosmacVip,
+                    CpuTiming::CosmacVip => CpuTiming::Fixed,
+                };
+            }
+
+            if cpu_timing != self.settings.cpu_timing {
+                self.settings.cpu_timing = cpu_timing;
+                self.c8_device.set_cpu_timing(cpu_timing);
+            }
         });
     }
 
@@ -701,6 +1734,16 @@ impl AppUI {
                 }
             }
 
+            ui.separator();
+
+            ui.add(
+                egui::Slider::new(
+                    &mut self.settings.screenshot_scale,
+                    1..=MAX_SCREENSHOT_SCALE,
+                )
+                .text(self.language.locale_string("screenshot_scale")),
+            );
+
             #[cfg(debug_assertions)]
             {
                 ui.separator();
@@ -833,13 +1876,11 @@ impl AppUI {
                 self.language.locale_string("quirk_shift_vx"),
             )
             .on_hover_text(self.language.locale_string("quirk_shift_vx_hover"));
-            /*
-               ui.checkbox(
-                   &mut self.settings.quirk_settings.v_blank,
-                   self.language.get_locale_string("quirk_v_blank"),
-               )
-               .on_hover_text(self.language.get_locale_string("quirk_v_blank_hover"));
-            */
+            ui.checkbox(
+                &mut self.settings.quirk_settings.v_blank,
+                self.language.locale_string("quirk_v_blank"),
+            )
+            .on_hover_text(self.language.locale_string("quirk_v_blank_hover"));
             ui.checkbox(
                 &mut self.settings.quirk_settings.clip_sprites,
                 self.language.locale_string("quirk_clip_sprites"),
@@ -852,18 +1893,30 @@ impl AppUI {
             )
             .on_hover_text(self.language.locale_string("quirk_jump_hover"));
 
-            let profile_name =
-                CompatibilityProfile::find_profile_name_key(self.settings.quirk_settings);
+            let profile_name = CompatibilityProfile::find_device_profile_name_key(
+                self.settings.compatibility_device,
+                self.settings.quirk_settings,
+            );
 
             egui::ComboBox::from_label(self.language.locale_string("compatibility_profile"))
                 .selected_text(self.language.locale_string(profile_name))
                 .show_ui(ui, |ui| {
                     for profile in COMPATIBILITY_PROFILES.iter() {
-                        ui.selectable_value(
-                            &mut self.settings.quirk_settings,
-                            profile.quirks,
-                            self.language.locale_string(profile.name_key()),
-                        );
+                        let selected = profile.device == self.settings.compatibility_device
+                            && profile.quirks == self.settings.quirk_settings;
+
+                        if ui
+                            .selectable_label(
+                                selected,
+                                self.language.locale_string(profile.name_key()),
+                            )
+                            .clicked()
+                        {
+                            self.settings.quirk_settings = profile.quirks;
+                            self.settings.compatibility_device = profile.device;
+                            self.settings.cpu_timing = profile.timing;
+                            self.c8_device.set_compatibility_profile(profile);
+                        }
                     }
                 });
         });
@@ -910,6 +1963,158 @@ impl AppUI {
                         });
                     }
                 });
+
+            // Random number source
+            let random_sources = [
+                RandomSource::Thread,
+                RandomSource::Seeded(0),
+                RandomSource::VipStyle(0),
+            ];
+            let mut random_source = self.settings.random_source;
+
+            egui::ComboBox::from_label(self.language.locale_string("random"))
+                .selected_text(self.language.locale_string(random_source.name_key()))
+                .show_ui(ui, |ui| {
+                    for source in random_sources {
+                        let selected = std::mem::discriminant(&random_source)
+                            == std::mem::discriminant(&source);
+
+                        if ui
+                            .selectable_label(
+                                selected,
+                                self.language.locale_string(source.name_key()),
+                            )
+                            .clicked()
+                        {
+                            random_source = source;
+                        }
+                    }
+                })
+                .response
+                .on_hover_text(self.language.locale_string("random_hover"));
+
+            match &mut random_source {
+                RandomSource::Thread => {}
+                RandomSource::Seeded(seed) => {
+                    ui.add(egui::DragValue::new(seed).prefix(self.language.locale_string("seed")));
+                }
+                RandomSource::VipStyle(seed) => {
+                    ui.add(
+                        egui::DragValue::new(seed)
+                            .hexadecimal(4, false, true)
+                            .prefix(self.language.locale_string("seed")),
+                    );
+                }
+            }
+
+            // Note: Changing the source reseeds the generator of the running program
+            if random_source != self.settings.random_source {
+                self.settings.random_source = random_source;
+                self.c8_device.set_random_source(random_source);
+            }
+
+            // Error policy
+            egui::ComboBox::from_label(self.language.locale_string("error_policy"))
+                .selected_text(
+                    self.language
+                        .locale_string(self.settings.error_policy.name_key()),
+                )
+                .show_ui(ui, |ui| {
+                    for policy in ERROR_POLICIES {
+                        if ui
+                            .selectable_value(
+                                &mut self.settings.error_policy,
+                                policy,
+                                self.language.locale_string(policy.name_key()),
+                            )
+                            .clicked()
+                        {
+                            self.c8_device.set_error_policy(policy);
+                        }
+                    }
+                })
+                .response
+                .on_hover_text(self.language.locale_string("error_policy_hover"));
+
+            // Rewind
+            if ui
+                .checkbox(
+                    &mut self.settings.rewind_enabled,
+                    self.language.locale_string("rewind"),
+                )
+                .on_hover_text(self.language.locale_string("rewind_hover"))
+                .changed()
+            {
+                self.rewind_buffer.clear();
+            }
+
+            // ROM database
+            ui.checkbox(
+                &mut self.settings.rom_database_enabled,
+                self.language.locale_string("rom_database"),
+            )
+            .on_hover_text(self.language.locale_string("rom_database_hover"));
+
+            if ui
+                .button(self.language.locale_string("load_rom_database"))
+                .on_hover_text(format!(
+                    "{}{}",
+                    self.language.locale_string("load_rom_database_hover"),
+                    self.rom_database.len()
+                ))
+                .clicked()
+            {
+                let database_clone = Rc::clone(&self.file_database);
+
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    futures::executor::block_on(async move {
+                        *database_clone.borrow_mut() = Self::load_json_file().await;
+                    });
+                }
+
+                #[cfg(target_arch = "wasm32")]
+                {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        *database_clone.borrow_mut() = Self::load_json_file().await;
+                    });
+                }
+            }
+        });
+    }
+
+    fn controls_rom_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(self.language.locale_string("rom_settings")).show(ui, |ui| {
+            let saved = self.settings.rom_settings.contains_key(&self.rom_hash);
+            ui.label(if saved {
+                self.language.locale_string("rom_settings_saved")
+            } else {
+                self.language.locale_string("rom_settings_global")
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        !self.rom_hash.is_empty(),
+                        egui::Button::new(self.language.locale_string("save_rom_settings")),
+                    )
+                    .on_hover_text(self.language.locale_string("save_rom_settings_hover"))
+                    .clicked()
+                {
+                    self.save_rom_settings();
+                }
+
+                if ui
+                    .add_enabled(
+                        saved,
+                        egui::Button::new(self.language.locale_string("reset_rom_settings")),
+                    )
+                    .on_hover_text(self.language.locale_string("reset_rom_settings_hover"))
+                    .clicked()
+                {
+                    self.reset_rom_settings();
+                }
+            });
         });
     }
 
@@ -987,14 +2192,419 @@ impl AppUI {
 
     fn visualizer_memory(&mut self, ui: &mut egui::Ui) {
         egui::CollapsingHeader::new(self.language.locale_string("memory")).show(ui, |ui| {
-            ui.label(self.language.locale_string("under_construction"));
+            ui.add(
+                egui::DragValue::new(&mut self.memory_address)
+                    .hexadecimal(4, false, true)
+                    .prefix(self.language.locale_string("address")),
+            );
+
+            // Hex dump of the memory, 8 bytes per row
+            let data = self.c8_device.memory().data();
+            let start = usize::from(self.memory_address);
+            let end = (start + MEMORY_VIEW_ROWS * MEMORY_VIEW_COLUMNS).min(data.len());
+            let pc = usize::from(self.c8_device.cpu().program_counter());
+
+            egui::Grid::new("memory_view").striped(true).show(ui, |ui| {
+                for (row, bytes) in data[start..end].chunks(MEMORY_VIEW_COLUMNS).enumerate() {
+                    let address = start + row * MEMORY_VIEW_COLUMNS;
+                    ui.monospace(format!("{address:04X}"));
+
+                    let text: Vec<String> =
+                        bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+                    if (address..address + bytes.len()).contains(&pc) {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            egui::RichText::new(text.join(" ")).monospace(),
+                        );
+                    } else {
+                        ui.monospace(text.join(" "));
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+
+            // Memory watchpoints
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.watch_length)
+                        .range(1..=0x1000)
+                        .prefix(self.language.locale_string("length")),
+                );
+
+                egui::ComboBox::from_id_salt("watch_access")
+                    .selected_text(self.language.locale_string(self.watch_access.name_key()))
+                    .show_ui(ui, |ui| {
+                        for access in MEMORY_ACCESSES {
+                            ui.selectable_value(
+                                &mut self.watch_access,
+                                access,
+                                self.language.locale_string(access.name_key()),
+                            );
+                        }
+                    });
+
+                if ui
+                    .button(self.language.locale_string("add_watchpoint"))
+                    .clicked()
+                {
+                    let start = usize::from(self.memory_address);
+                    self.c8_device.debugger_mut().watch_memory(
+                        start..start + usize::from(self.watch_length),
+                        self.watch_access,
+                    );
+                }
+            });
+
+            let mut removed = None;
+            for (index, watchpoint) in self
+                .c8_device
+                .debugger()
+                .memory_watchpoints()
+                .iter()
+                .enumerate()
+            {
+                ui.horizontal(|ui| {
+                    ui.monospace(format!(
+                        "{:04X}..{:04X} {}",
+                        watchpoint.range.start,
+                        watchpoint.range.end,
+                        self.language.locale_string(watchpoint.access.name_key())
+                    ));
+
+                    if ui.small_button("x").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+
+            if let Some(index) = removed {
+                self.c8_device
+                    .debugger_mut()
+                    .remove_memory_watchpoint(index);
+            }
         });
     }
 
     fn visualizer_registers(&mut self, ui: &mut egui::Ui) {
         egui::CollapsingHeader::new(self.language.locale_string("registers")).show(ui, |ui| {
-            ui.label(self.language.locale_string("under_construction"));
+            self.debugger_controls(ui);
+
+            ui.separator();
+
+            // Checked registers stop the device when they change
+            let cpu = self.c8_device.cpu();
+            let mut registers: Vec<(WatchedRegister, String)> = vec![(
+                WatchedRegister::Index,
+                format!("I  = {:04X}", cpu.index_register()),
+            )];
+            registers.extend(cpu.registers().iter().enumerate().map(|(index, value)| {
+                (
+                    WatchedRegister::V(index as u8),
+                    format!("V{index:X} = {value:02X}"),
+                )
+            }));
+
+            ui.monospace(format!("PC = {:04X}", cpu.program_counter()));
+            ui.monospace(format!(
+                "DT = {:02X}  ST = {:02X}",
+                cpu.delay_timer(),
+                cpu.sound_timer()
+            ));
+
+            egui::Grid::new("registers_view").show(ui, |ui| {
+                for (column, (register, text)) in registers.into_iter().enumerate() {
+                    let mut watched = self.c8_device.debugger().is_register_watched(register);
+
+                    if ui
+                        .checkbox(&mut watched, egui::RichText::new(text).monospace())
+                        .on_hover_text(self.language.locale_string("watch_register_hover"))
+                        .changed()
+                    {
+                        if watched {
+                            self.c8_device.debugger_mut().watch_register(register);
+                        } else {
+                            self.c8_device.debugger_mut().unwatch_register(register);
+                        }
+                    }
+
+                    // The index register gets its own row
+                    if column % 2 == 0 {
+                        ui.end_row();
+                    }
+                }
+            });
+
+            ui.separator();
+
+            // Stack, newest address first
+            ui.label(self.language.locale_string("stack"));
+            for address in self.c8_device.stack().addresses().iter().rev() {
+                ui.monospace(format!("{address:04X}"));
+            }
+        });
+    }
+
+    fn debugger_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.c8_device.debugger().is_paused() {
+                if ui.button(self.language.locale_string("continue")).clicked() {
+                    self.c8_device.debugger_mut().resume();
+                }
+            } else if ui.button(self.language.locale_string("pause")).clicked() {
+                self.c8_device.debugger_mut().pause();
+            }
+
+            if ui
+                .button(self.language.locale_string("step_into"))
+                .clicked()
+            {
+                self.c8_device.step_into();
+            }
+
+            if ui
+                .button(self.language.locale_string("step_over"))
+                .clicked()
+            {
+                self.c8_device.step_over();
+            }
+
+            if ui.button(self.language.locale_string("step_out")).clicked() {
+                self.c8_device.step_out();
+            }
+        });
+
+        if let Some(reason) = self.c8_device.debugger().last_break() {
+            ui.colored_label(ui.visuals().warn_fg_color, reason.to_string());
+        }
+
+        // Breakpoints, with an optional condition, hit count, log message and trace action
+        ui.add(
+            egui::DragValue::new(&mut self.breakpoint_address)
+                .hexadecimal(4, false, true)
+                .prefix(self.language.locale_string("address")),
+        );
+
+        ui.add(
+            egui::TextEdit::singleline(&mut self.breakpoint_condition)
+                .hint_text(self.language.locale_string("breakpoint_condition")),
+        )
+        .on_hover_text(self.language.locale_string("expression_hover"));
+
+        ui.add(
+            egui::TextEdit::singleline(&mut self.breakpoint_log)
+                .hint_text(self.language.locale_string("breakpoint_log")),
+        )
+        .on_hover_text(self.language.locale_string("breakpoint_log_hover"));
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.breakpoint_hit_target)
+                    .prefix(self.language.locale_string("hit_count")),
+            )
+            .on_hover_text(self.language.locale_string("hit_count_hover"));
+
+            let action_key = self
+                .breakpoint_trace
+                .as_ref()
+                .map_or("pause", TraceAction::name_key);
+            egui::ComboBox::from_id_salt("breakpoint_trace")
+                .selected_text(self.language.locale_string(action_key))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut self.breakpoint_trace,
+                        None,
+                        self.language.locale_string("pause"),
+                    );
+
+                    for action in TRACE_ACTIONS {
+                        ui.selectable_value(
+                            &mut self.breakpoint_trace,
+                            Some(action),
+                            self.language.locale_string(action.name_key()),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(self.language.locale_string("breakpoint_trace_hover"));
+
+            if ui
+                .button(self.language.locale_string("add_breakpoint"))
+                .clicked()
+            {
+                match self.parse_breakpoint() {
+                    Ok(breakpoint) => {
+                        self.breakpoint_error = None;
+                        self.c8_device
+                            .debugger_mut()
+                            .set_breakpoint(self.breakpoint_address, breakpoint);
+                    }
+                    Err(error) => self.breakpoint_error = Some(error.to_string()),
+                }
+            }
+        });
+
+        if let Some(error) = &self.breakpoint_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        let mut removed = None;
+        for (address, breakpoint) in self.c8_device.debugger().breakpoints() {
+            ui.horizontal(|ui| {
+                let mut text = format!(
+                    "{address:04X} ({})",
+                    self.c8_device.debugger().hit_count(*address)
+                );
+                if let Some(condition) = &breakpoint.condition {
+                    text += &format!(" if {}", condition.source());
+                }
+                if let Some(message) = &breakpoint.log_message {
+                    text += &format!(" log \"{}\"", message.source());
+                }
+                if let Some(action) = &breakpoint.trace_action {
+                    text += &format!(" {}", self.language.locale_string(action.name_key()));
+                }
+                ui.monospace(text);
+
+                if ui.small_button("x").clicked() {
+                    removed = Some(*address);
+                }
+            });
+        }
+
+        if let Some(address) = removed {
+            self.c8_device.debugger_mut().remove_breakpoint(address);
+        }
+
+        ui.separator();
+
+        // Watch expressions
+        ui.horizontal(|ui| {
+            let response = ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.watch_expression)
+                        .hint_text(self.language.locale_string("watch_expression")),
+                )
+                .on_hover_text(self.language.locale_string("expression_hover"));
+
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui
+                .button(self.language.locale_string("add_watch"))
+                .clicked()
+                || submitted
+            {
+                match Expression::parse(&self.watch_expression) {
+                    Ok(expression) => {
+                        self.watch_error = None;
+                        self.watch_expressions.push(expression);
+                        self.watch_expression.clear();
+                    }
+                    Err(error) => self.watch_error = Some(error.to_string()),
+                }
+            }
         });
+
+        if let Some(error) = &self.watch_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        let mut removed = None;
+        for (index, expression) in self.watch_expressions.iter().enumerate() {
+            let value = expression.evaluate(self.c8_device.cpu(), self.c8_device.memory());
+
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{} = {value} ({value:#X})", expression.source()));
+
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+
+        if let Some(index) = removed {
+            self.watch_expressions.remove(index);
+        }
+
+        ui.separator();
+
+        // Execution trace, kept in memory until it is copied
+        ui.horizontal(|ui| {
+            let mut tracing = self.c8_device.tracer().is_some();
+            if ui
+                .checkbox(&mut tracing, self.language.locale_string("trace"))
+                .on_hover_text(self.language.locale_string("trace_hover"))
+                .changed()
+            {
+                // Wait for a tracepoint which starts the trace, if there is one
+                let waits = self
+                    .c8_device
+                    .debugger()
+                    .breakpoints()
+                    .values()
+                    .any(|breakpoint| breakpoint.trace_action == Some(TraceAction::Start));
+
+                self.c8_device.set_tracer(tracing.then(|| {
+                    let mut tracer = Tracer::buffer(TRACE_BUFFER_LINES);
+                    tracer.set_recording(!waits);
+                    tracer
+                }));
+            }
+
+            if let Some(tracer) = self.c8_device.tracer()
+                && ui
+                    .button(self.language.locale_string("copy_trace"))
+                    .clicked()
+            {
+                let lines: Vec<&str> = tracer.lines().collect();
+                ui.ctx().copy_text(lines.join("\n"));
+            }
+        });
+
+        // Logpoint messages, newest last
+        if !self.debug_log.is_empty() {
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .id_salt("debug_log")
+                .max_height(120.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for message in &self.debug_log {
+                        ui.monospace(message);
+                    }
+                });
+
+            if ui
+                .button(self.language.locale_string("clear_log"))
+                .clicked()
+            {
+                self.debug_log.clear();
+            }
+        }
+    }
+
+    /// Create a breakpoint from the breakpoint inputs
+    fn parse_breakpoint(&self) -> Result<Breakpoint, ExpressionError> {
+        let condition = if self.breakpoint_condition.trim().is_empty() {
+            None
+        } else {
+            Some(Expression::parse(&self.breakpoint_condition)?)
+        };
+
+        let log_message = if self.breakpoint_log.is_empty() {
+            None
+        } else {
+            Some(LogMessage::parse(&self.breakpoint_log)?)
+        };
+
+        Ok(Breakpoint {
+            condition,
+            hit_target: self.breakpoint_hit_target,
+            log_message,
+            trace_action: self.breakpoint_trace,
+        })
     }
 
     fn powered_by_egui_and_eframe(ui: &mut egui::Ui, language: &LanguageIdentifier) {
@@ -1013,23 +2623,124 @@ impl AppUI {
         });
     }
 
-    async fn load_file() -> (Option<Vec<u8>>, Option<String>) {
+    async fn load_file() -> (Option<Vec<u8>>, Option<String>, Option<String>) {
         let file_task = AsyncFileDialog::new()
-            .add_filter("Chip8", &["ch8"])
+            .add_filter("Chip8", &["ch8", "8o", "gif"])
             .set_directory("/")
             .pick_file()
             .await;
 
-        // TODO: Check for an `options.json` file in the same directory and convert it to compatible settings
-
         match file_task {
             Some(file) => {
                 let name = file.file_name();
 
+                // Octo and the Chip-8 archive keep the settings of a program in an
+                // `options.json` file next to it, the web version can't see other files
+                #[cfg(not(target_arch = "wasm32"))]
+                let options = file.path().parent().and_then(|directory| {
+                    std::fs::read_to_string(directory.join("options.json")).ok()
+                });
+
+                #[cfg(target_arch = "wasm32")]
+                let options = None;
+
                 let file = file.read().await;
-                (Some(file), Some(name))
+                (Some(file), Some(name), options)
             }
-            None => (None, None),
+            None => (None, None, None),
+        }
+    }
+
+    async fn load_json_file() -> Option<String> {
+        let file = AsyncFileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+            .await?;
+
+        let data = file.read().await;
+        Some(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    async fn save_file(file_name: String, filter: &str, extension: &str, data: Vec<u8>) {
+        let file_task = AsyncFileDialog::new()
+            .add_filter(filter, &[extension])
+            .set_file_name(file_name)
+            .save_file()
+            .await;
+
+        if let Some(file) = file_task
+            && let Err(error) = file.write(&data).await
+        {
+            eprintln!("Failed to save {}: {error}", file.file_name());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_rom(name: &str) -> Vec<u8> {
+        GAME_ROMS
+            .iter()
+            .find(|rom| rom.name() == name)
+            .unwrap()
+            .data()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_rom_database_settings() {
+        let mut app = AppUI::default();
+        let global_speed = app.settings.cpu_speed;
+
+        // The ROM database configures a known ROM
+        app.load_rom(game_rom("Glitch Ghost"));
+        assert_eq!(app.settings.cpu_speed, 200);
+        assert!(app.database_notice.is_some());
+
+        // Unknown ROMs get the global settings back, even after the notice was dismissed
+        app.database_notice = None;
+        app.load_rom(vec![0x12, 0x00]);
+        assert_eq!(app.settings.cpu_speed, global_speed);
+
+        // Unloading restores them too
+        app.load_rom(game_rom("Glitch Ghost"));
+        app.unload_rom();
+        assert_eq!(app.settings.cpu_speed, global_speed);
+        assert_eq!(app.settings.global_settings, None);
+    }
+
+    #[test]
+    fn test_rom_settings() {
+        let mut app = AppUI::default();
+        let global_speed = app.settings.cpu_speed;
+        let rom = vec![0x12, 0x00];
+        let other_rom = vec![0x13, 0x00];
+
+        // Saving a changed speed doesn't make it the global speed
+        app.load_rom(rom.clone());
+        app.settings.cpu_speed = global_speed * 2;
+        app.save_rom_settings();
+        app.reset_rom_settings();
+        assert_eq!(app.settings.cpu_speed, global_speed);
+
+        app.load_rom(other_rom.clone());
+        assert_eq!(app.settings.cpu_speed, global_speed);
+
+        // Saved settings come back with their ROM only
+        app.load_rom(rom.clone());
+        app.settings.cpu_speed = global_speed * 3;
+        app.save_rom_settings();
+        app.settings.cpu_speed = global_speed * 4;
+        app.save_rom_settings();
+
+        app.load_rom(other_rom);
+        assert_eq!(app.settings.cpu_speed, global_speed);
+
+        app.load_rom(rom);
+        assert_eq!(app.settings.cpu_speed, global_speed * 4);
+        app.reset_rom_settings();
+        assert_eq!(app.settings.cpu_speed, global_speed);
+    }
+}
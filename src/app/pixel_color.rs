@@ -1,3 +1,7 @@
+use c8::{
+    octo_options::{OctoColors, Rgb},
+    screenshot::ScreenshotColors,
+};
 use egui::Color32;
 use std::default;
 
@@ -62,7 +66,51 @@ impl From<&PixelColors> for [Color32; 6] {
     }
 }
 
+impl From<&PixelColors> for ScreenshotColors {
+    fn from(colors: &PixelColors) -> Self {
+        Self {
+            background: colors.background.to_array(),
+            foreground1: colors.foreground1.to_array(),
+            foreground2: colors.foreground2.to_array(),
+            blended: colors.blended.to_array(),
+        }
+    }
+}
+
+impl From<&PixelColors> for OctoColors {
+    fn from(colors: &PixelColors) -> Self {
+        let rgb = |color: Color32| Some([color.r(), color.g(), color.b()]);
+
+        Self {
+            background: rgb(colors.background),
+            foreground1: rgb(colors.foreground1),
+            foreground2: rgb(colors.foreground2),
+            blended: rgb(colors.blended),
+            buzzer: rgb(colors.buzzer),
+            silence: rgb(colors.silence),
+        }
+    }
+}
+
 impl PixelColors {
+    /// Set the colors of an Octo palette, colors which aren't set are kept
+    pub fn set_octo_colors(&mut self, colors: &OctoColors) {
+        let pairs: [(&mut Color32, Option<Rgb>); 6] = [
+            (&mut self.background, colors.background),
+            (&mut self.foreground1, colors.foreground1),
+            (&mut self.foreground2, colors.foreground2),
+            (&mut self.blended, colors.blended),
+            (&mut self.buzzer, colors.buzzer),
+            (&mut self.silence, colors.silence),
+        ];
+
+        for (color, rgb) in pairs {
+            if let Some([r, g, b]) = rgb {
+                *color = Color32::from_rgb(r, g, b);
+            }
+        }
+    }
+
     /// Get the color of a pixel
     pub fn pixel_color(&self, layer: ColorLayer) -> &Color32 {
         match layer {
@@ -200,5 +248,37 @@ const PALETTE_GREY: PixelColors = PixelColors {
 
 #[cfg(test)]
 mod tests {
-    //use super::*;
+    use super::*;
+
+    #[test]
+    fn test_screenshot_colors() {
+        // Headless screenshots use the default palette of the app
+        assert_eq!(
+            ScreenshotColors::from(&PixelColors::default()),
+            ScreenshotColors::default()
+        );
+    }
+
+    #[test]
+    fn test_octo_colors() {
+        // The Octo palette round trips through an `options.json` file
+        let mut colors = PixelColors::default();
+        colors.set_octo_colors(&OctoColors::from(&PALETTE_OCTO));
+        assert_eq!(
+            <[Color32; 6]>::from(&colors),
+            <[Color32; 6]>::from(&PALETTE_OCTO)
+        );
+
+        // Colors which aren't set are kept
+        let mut colors = PixelColors::default();
+        colors.set_octo_colors(&OctoColors {
+            foreground1: Some([1, 2, 3]),
+            ..OctoColors::default()
+        });
+        assert_eq!(*colors.foreground1_color(), Color32::from_rgb(1, 2, 3));
+        assert_eq!(
+            colors.background_color(),
+            PALETTE_DEFAULT.background_color()
+        );
+    }
 }
@@ -18,7 +18,7 @@ fn main() -> eframe::Result<()> {
         ..Default::default()
     };
     eframe::run_native(
-        "Chip 8",
+        chip8::app::app_ui::APP_NAME,
         native_options,
         Box::new(|cc| Ok(Box::new(chip8::app::app_ui::AppUI::new(cc)))),
     )
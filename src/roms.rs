@@ -106,3 +106,6 @@ pub const GAME_ROMS: &[ROM] = &[
     },
      */
 ];
+
+/// Metadata of the included ROMs, in the `programs.json` layout of the community CHIP-8 database
+pub const ROM_DATABASE: &str = include_str!("../assets/database/programs.json");
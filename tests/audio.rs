@@ -0,0 +1,96 @@
+//! Tests of the audio rendered from the bundled XO-Chip music player
+
+use c8::{
+    audio::{audio_settings::AudioSettings, recorder::AudioRecorder},
+    device::C8,
+    quirks::{COMPATIBILITY_PROFILES, CompatibilityDevice},
+};
+use chip8::roms::GAME_ROMS;
+
+/// Instructions per frame, the music players need a fast CPU
+const CPU_SPEED: u32 = 1000;
+
+/// Number of frames recorded
+const FRAMES: usize = 300;
+
+/// Render the sound of a game ROM under the XO-Chip profile
+fn render(name: &str) -> AudioRecorder {
+    let rom = GAME_ROMS
+        .iter()
+        .find(|rom| rom.name() == name)
+        .unwrap_or_else(|| panic!("{name} is not a game ROM"));
+    let profile = COMPATIBILITY_PROFILES
+        .iter()
+        .find(|profile| profile.device == CompatibilityDevice::XOChip)
+        .unwrap();
+
+    let mut c8 = C8::default();
+    c8.audio_device.set_audio_settings(AudioSettings::silent());
+    c8.set_compatibility_profile(profile);
+    c8.load_rom(rom.data());
+    c8.set_audio_recorder(Some(AudioRecorder::new()));
+
+    for frame in 0..FRAMES {
+        c8.step(CPU_SPEED);
+        if let Some(error) = c8.execution_error() {
+            panic!("{name} halted at frame {frame}: {error}");
+        }
+    }
+
+    c8.set_audio_recorder(None).unwrap()
+}
+
+/// Count the sign changes in each frame, which follow the pitch and pattern
+fn frame_crossings(recorder: &AudioRecorder) -> Vec<usize> {
+    let frame_samples = recorder.sample_rate() as usize / 60;
+
+    recorder
+        .samples()
+        .chunks(frame_samples)
+        .map(|frame| {
+            frame
+                .windows(2)
+                .filter(|pair| (pair[0] > 0) != (pair[1] > 0))
+                .count()
+        })
+        .collect()
+}
+
+#[test]
+fn test_music_player_2() {
+    let recorder = render("Music Player 2");
+    assert_eq!(recorder.samples().len(), FRAMES * 44100 / 60);
+
+    // The music starts right away and keeps playing
+    let audible = recorder
+        .samples()
+        .chunks(44100 / 60)
+        .filter(|frame| frame.iter().any(|&sample| sample != 0))
+        .count();
+    assert!(
+        audible > FRAMES * 9 / 10,
+        "only {audible} frames are audible"
+    );
+
+    // The pattern changes, unlike a plain beep which has the same waveform every frame
+    let mut crossings = frame_crossings(&recorder);
+    crossings.sort_unstable();
+    crossings.dedup();
+    assert!(
+        crossings.len() > 3,
+        "the waveform barely changes: {crossings:?}"
+    );
+
+    let wav = recorder.to_wav();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(wav.len(), 44 + recorder.samples().len() * 2);
+}
+
+#[test]
+fn test_deterministic() {
+    // The sound depends only on the program, not on an audio device or the host speed
+    assert_eq!(
+        render("Music Player 2").samples(),
+        render("Music Player 2").samples()
+    );
+}
@@ -0,0 +1,38 @@
+//! Tests of packing the bundled games into Octo cartridges and booting them again
+
+use c8::{
+    audio::audio_settings::AudioSettings, cartridge::Cartridge, device::C8,
+    octo_options::OctoOptions, screenshot::ScreenshotColors,
+};
+use chip8::roms::GAME_ROMS;
+
+#[test]
+fn test_game_round_trip() {
+    let options = OctoOptions {
+        tickrate: Some(200),
+        ..OctoOptions::default()
+    };
+
+    for rom in GAME_ROMS.iter() {
+        // The label shows the screen after the game started
+        let mut c8 = C8::default();
+        c8.audio_device.set_audio_settings(AudioSettings::silent());
+        c8.load_rom(rom.data());
+        for _ in 0..60 {
+            c8.step(20);
+        }
+
+        let cartridge = Cartridge::from_rom(rom.data(), options.clone());
+        let data = cartridge.to_gif(c8.display(), &ScreenshotColors::default());
+
+        let (read, warnings) = Cartridge::read_gif(data.as_slice())
+            .unwrap_or_else(|error| panic!("{}: {error}", rom.name()));
+        assert!(warnings.is_empty(), "{}: {warnings:?}", rom.name());
+        assert_eq!(read.options, options, "{}", rom.name());
+
+        // The program assembles back to the same bytes
+        let assembled = c8_asm::assemble(&read.program)
+            .unwrap_or_else(|error| panic!("{}: {error}", rom.name()));
+        assert_eq!(assembled, rom.data(), "{}", rom.name());
+    }
+}
@@ -0,0 +1,234 @@
+//! Conformance tests which run the bundled test ROMs under each compatibility profile
+//!
+//! The final display of each run is compared against a golden image in `tests/golden`, one
+//! line per row of pixels with `.` for the background, `#` and `+` for the first and second
+//! plane and `*` for both. After an intended change to the output, regenerate the images with
+//! `UPDATE_GOLDEN=1 cargo test --test conformance` and review the diff.
+
+use std::path::PathBuf;
+
+use c8::{
+    audio::audio_settings::AudioSettings,
+    device::C8,
+    keypad::{KEYPAD_KEYS, KeypadKey},
+    quirks::{COMPATIBILITY_PROFILES, CompatibilityDevice, CompatibilityProfile},
+    random::RandomSource,
+};
+use chip8::roms::TEST_ROMS;
+
+/// Instructions per frame, the default speed of the app
+const CPU_SPEED: u32 = 20;
+
+/// Number of frames each ROM runs, long enough for the slowest profile to finish
+const FRAMES: u32 = 900;
+
+/// Frames between menu keypresses, so each menu has been drawn before its key is pressed
+const MENU_INTERVAL: u32 = 60;
+
+/// Frames a menu key is held down
+const PRESS_FRAMES: u32 = 5;
+
+/// Input given to a ROM
+#[derive(Default)]
+struct Input {
+    /// Keys pressed one after another to pick menu entries
+    menu: &'static [u8],
+
+    /// Key held down after the menu keys, until the end of the run
+    held: Option<u8>,
+}
+
+/// Get the name of a profile used in golden file names
+fn profile_name(device: CompatibilityDevice) -> &'static str {
+    match device {
+        CompatibilityDevice::Default => "default",
+        CompatibilityDevice::Chip8 => "chip8",
+        CompatibilityDevice::SuperChip => "super_chip",
+        CompatibilityDevice::XOChip => "xo_chip",
+    }
+}
+
+/// Get the keypad key of a hex digit
+fn key(digit: u8) -> &'static KeypadKey {
+    KEYPAD_KEYS
+        .iter()
+        .find(|key| key.key_index() == usize::from(digit))
+        .expect("keys are hex digits")
+}
+
+/// Run a test ROM and get its final display as text
+fn run_rom(name: &str, profile: &CompatibilityProfile, input: &Input) -> String {
+    let rom = TEST_ROMS
+        .iter()
+        .find(|rom| rom.name() == name)
+        .unwrap_or_else(|| panic!("{name} is not a test ROM"));
+
+    // Note: The beep test doesn't need to be heard, only its display is compared
+    let mut c8 = C8::default();
+    c8.audio_device.set_audio_settings(AudioSettings::silent());
+    c8.set_compatibility_profile(profile);
+    c8.set_random_source(RandomSource::Seeded(0));
+    c8.load_rom(rom.data());
+
+    for frame in 0..FRAMES {
+        // Press the menu keys in turn, then hold the held key
+        let step = frame / MENU_INTERVAL;
+        let menu_key = (frame % MENU_INTERVAL < PRESS_FRAMES)
+            .then(|| step.checked_sub(1))
+            .flatten()
+            .and_then(|index| input.menu.get(index as usize));
+        let held_key = input.held.filter(|_| step > input.menu.len() as u32);
+
+        for digit in 0..16 {
+            let pressed = menu_key == Some(&digit) || held_key == Some(digit);
+            c8.keypad_mut().set_key(key(digit), pressed);
+        }
+
+        c8.step(CPU_SPEED);
+        if let Some(error) = c8.execution_error() {
+            panic!("{name} halted at frame {frame}: {error}");
+        }
+    }
+
+    c8.display().to_text()
+}
+
+/// Run a test ROM under every profile and compare the displays with the golden images
+fn check_rom(name: &str, input: impl Fn(CompatibilityDevice) -> Input) {
+    let golden_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = Vec::new();
+    for profile in &COMPATIBILITY_PROFILES {
+        let path = golden_dir.join(format!("{name}-{}.txt", profile_name(profile.device)));
+        let actual = run_rom(name, profile, &input(profile.device));
+
+        if update {
+            std::fs::create_dir_all(&golden_dir).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{} differs\nexpected:\n{expected}\nactual:\n{actual}",
+                path.display()
+            )),
+            Err(error) => failures.push(format!(
+                "{}: {error}, run with UPDATE_GOLDEN=1 to create it",
+                path.display()
+            )),
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_corax_plus() {
+    check_rom("corax+", |_| Input::default());
+}
+
+#[test]
+fn test_flags() {
+    check_rom("flags", |_| Input::default());
+}
+
+/// Checks of the quirks ROM, in the order it shows them
+const QUIRK_CHECKS: [&str; 6] = [
+    "vF reset",
+    "memory",
+    "display wait",
+    "clipping",
+    "shifting",
+    "jumping",
+];
+
+/// Rows of the cross the quirks ROM draws after a failed check
+const QUIRK_CROSS: [&str; 3] = ["#.#", ".#.", "#.#"];
+
+/// Get the input which picks the platform of a profile in the quirks ROM
+fn quirks_input(device: CompatibilityDevice) -> Input {
+    // The menu picks the platform, Super Chip has a second menu for the modern or legacy version
+    Input {
+        menu: match device {
+            CompatibilityDevice::Chip8 => &[1],
+            CompatibilityDevice::SuperChip => &[2, 1],
+            CompatibilityDevice::Default | CompatibilityDevice::XOChip => &[3],
+        },
+        held: None,
+    }
+}
+
+/// Get the checks the quirks ROM marks as failed on its final display
+fn failed_quirk_checks(display: &str) -> Vec<&'static str> {
+    let rows: Vec<&str> = display.lines().collect();
+
+    // Each check takes 5 rows below a blank row, the mark is at the end of its first 3 rows
+    QUIRK_CHECKS
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| {
+            (0..QUIRK_CROSS.len()).all(|row| {
+                rows.get(2 + index * 5 + row)
+                    .and_then(|line| line.get(59..62))
+                    == Some(QUIRK_CROSS[row])
+            })
+        })
+        .map(|(_, &check)| check)
+        .collect()
+}
+
+#[test]
+fn test_quirks() {
+    check_rom("quirks", quirks_input);
+}
+
+#[test]
+fn test_quirk_checks() {
+    // Every check passes, so the golden images don't hide a failed check
+    for profile in &COMPATIBILITY_PROFILES {
+        let display = run_rom("quirks", profile, &quirks_input(profile.device));
+
+        assert_eq!(
+            failed_quirk_checks(&display),
+            Vec::<&str>::new(),
+            "failed quirk checks on the {} profile",
+            profile_name(profile.device)
+        );
+    }
+}
+
+#[test]
+fn test_keypad() {
+    // Pick the `EX9E` test and hold a key, which is highlighted on the keypad
+    check_rom("keypad", |_| Input {
+        menu: &[1],
+        held: Some(5),
+    });
+}
+
+#[test]
+fn test_beep() {
+    // The beep plays while B is held, the display only shows the instructions
+    check_rom("beep", |_| Input {
+        menu: &[],
+        held: Some(0xB),
+    });
+}
+
+#[test]
+fn test_scrolling() {
+    // The menu picks Super Chip or XO-Chip, then the low or high resolution. Low resolution
+    // Super Chip has a third menu for the modern or legacy version.
+    check_rom("scrolling", |device| Input {
+        menu: match device {
+            CompatibilityDevice::Default => &[2, 2],
+            CompatibilityDevice::Chip8 => &[1, 1, 2],
+            CompatibilityDevice::SuperChip => &[1, 2],
+            CompatibilityDevice::XOChip => &[2, 1],
+        },
+        held: None,
+    });
+}
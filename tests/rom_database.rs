@@ -0,0 +1,41 @@
+//! Tests of the metadata of the bundled ROMs
+
+use c8::{
+    octo_options::OctoOptions, quirks::CompatibilityDevice, rom_database::RomDatabase, rom_sha1,
+};
+use chip8::roms::{GAME_ROMS, ROM_DATABASE, TEST_ROMS};
+
+#[test]
+fn test_bundled_roms() {
+    let database = RomDatabase::parse(ROM_DATABASE).unwrap();
+    assert_eq!(database.len(), TEST_ROMS.len() + GAME_ROMS.len());
+
+    for rom in TEST_ROMS.iter().chain(GAME_ROMS) {
+        let info = database
+            .lookup(rom.data())
+            .unwrap_or_else(|| panic!("{} ({}) is missing", rom.name(), rom_sha1(rom.data())));
+
+        assert!(!info.title.is_empty(), "{} has no title", rom.name());
+        assert!(info.profile.is_some(), "{} has no profile", rom.name());
+    }
+}
+
+#[test]
+fn test_glitch_ghost() {
+    // The entry matches the options shipped with the game
+    let database = RomDatabase::parse(ROM_DATABASE).unwrap();
+    let rom = GAME_ROMS
+        .iter()
+        .find(|rom| rom.name() == "Glitch Ghost")
+        .unwrap();
+    let info = database.lookup(rom.data()).unwrap();
+
+    let (options, _) =
+        OctoOptions::parse(include_str!("../assets/games/glitch-ghost/options.json")).unwrap();
+    assert_eq!(info.tickrate, options.tickrate);
+    assert_eq!(info.colors, options.colors);
+
+    let profile = info.profile.unwrap();
+    assert_eq!(profile.device, CompatibilityDevice::Default);
+    assert_eq!(profile.quirks, options.quirks);
+}